@@ -0,0 +1,340 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Aura based parachain consensus.
+//!
+//! The slot is derived from the relay parent, every relay chain block is one slot. The author
+//! of a slot is the authority at index `slot % authorities.len()` of the Aura authority set at
+//! the parent block. The author seals the block with the Aura seal over the hash of the header
+//! without the seal.
+//!
+//! The relay chain only checks that a block is valid, not that its author sealed no other block
+//! for the same slot. `AuraVerifier` detects such equivocations of imported blocks.
+
+use crate::{
+	ParachainConsensus, ParachainCandidate, RelayChainConsensus, CollatorKeyError,
+	load_collator_key,
+};
+use substrate_client::{
+	Client, CallExecutor, backend::Backend, block_builder::api::BlockBuilder as BlockBuilderApi,
+	runtime_api::ApiExt,
+};
+use substrate_primitives::{Blake2Hasher, H256, Pair, crypto::Protected};
+use sr_primitives::{generic::{BlockId, Digest}, Justification};
+use sr_primitives::traits::{
+	Block as BlockT, Header as HeaderT, ProvideRuntimeApi, DigestItemFor,
+};
+use substrate_consensus_common::{
+	BlockOrigin, ImportBlock, ForkChoiceStrategy, import_queue::Verifier,
+};
+use substrate_consensus_aura::CompatibleDigestItem;
+use substrate_consensus_aura_primitives::AuraApi;
+use polkadot_primitives::Hash as PHash;
+use cumulus_runtime::{
+	ValidationData, ProvidePolkadotInherent, DownwardMessage, CollectCollationInfo,
+};
+use cumulus_client_proof_recorder::RecordedBlock;
+
+use parity_codec::Codec;
+use parking_lot::Mutex;
+use log::{error, warn, debug};
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+/// The public key of the given `Pair`.
+type AuthorityId<P> = <P as Pair>::Public;
+
+/// The number of slots before the latest imported slot for which the sealed headers are kept
+/// to detect equivocations.
+const EQUIVOCATION_SLOTS: u64 = 256;
+
+/// Two different headers that were sealed by the same authority for the same slot.
+#[derive(Debug, Clone)]
+pub struct AuraEquivocation<Header, AuthorityId> {
+	/// The slot of both headers.
+	pub slot: u64,
+	/// The authority that sealed both headers.
+	pub author: AuthorityId,
+	/// The header that was imported first, including the seal.
+	pub first_header: Header,
+	/// The header that was imported second, including the seal.
+	pub second_header: Header,
+}
+
+/// Is called with every detected `AuraEquivocation`, e.g. to report it to the runtime.
+pub type ReportEquivocation<Block, P> = Arc<
+	dyn Fn(AuraEquivocation<<Block as BlockT>::Header, AuthorityId<P>>) + Send + Sync
+>;
+
+/// Returns the slot that is used for a parachain block built on the given relay parent.
+fn slot_for_relay_parent(validation_data: &ValidationData) -> u64 {
+	u64::from(validation_data.relay_parent_number)
+}
+
+/// Returns the expected author of the given slot.
+fn slot_author<P: Pair>(slot: u64, authorities: &[AuthorityId<P>]) -> Option<&AuthorityId<P>> {
+	if authorities.is_empty() {
+		return None
+	}
+
+	authorities.get((slot % authorities.len() as u64) as usize)
+}
+
+/// Returns the Aura authorities at the given block.
+fn authorities<Block, C, P>(
+	client: &C,
+	at: &BlockId<Block>,
+) -> Result<Vec<AuthorityId<P>>, String> where
+	Block: BlockT,
+	C: ProvideRuntimeApi,
+	C::Api: AuraApi<Block, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec,
+{
+	client.runtime_api()
+		.authorities(at)
+		.map_err(|e| format!("Could not get the Aura authorities at {}: {:?}", at, e))
+}
+
+/// A `ParachainConsensus` that only builds a block if the local key is the author of the slot.
+pub struct AuraConsensus<Block: BlockT, B, E, RA, I, P> {
+	inner: RelayChainConsensus<Block, B, E, RA, I>,
+	client: Arc<Client<B, E, Block, RA>>,
+	key: Arc<P>,
+}
+
+impl<Block: BlockT, B, E, RA, I, P> AuraConsensus<Block, B, E, RA, I, P> {
+	/// Create a new instance that builds the blocks with `inner` and seals them with `key`.
+	pub fn new(
+		inner: RelayChainConsensus<Block, B, E, RA, I>,
+		client: Arc<Client<B, E, Block, RA>>,
+		key: Arc<P>,
+	) -> Self {
+		Self { inner, client, key }
+	}
+
+	/// Create a new instance that seals the blocks with the collator key from the keystore at
+	/// `keystore_path`.
+	///
+	/// Fails if there is no collator key in the keystore.
+	pub fn from_keystore(
+		inner: RelayChainConsensus<Block, B, E, RA, I>,
+		client: Arc<Client<B, E, Block, RA>>,
+		keystore_path: PathBuf,
+		password: Option<Protected<String>>,
+	) -> Result<Self, CollatorKeyError> where P: Pair {
+		let key = load_collator_key::<P>(keystore_path, password)?;
+		Ok(Self::new(inner, client, key))
+	}
+}
+
+impl<Block, B, E, RA, I, P> ParachainConsensus<Block>
+	for AuraConsensus<Block, B, E, RA, I, P> where
+	I: ProvidePolkadotInherent<Block>,
+	B: Backend<Block, Blake2Hasher> + Send + Sync,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>
+		+ AuraApi<Block, AuthorityId<P>> + CollectCollationInfo<Block> + ApiExt<Block>,
+	P: Pair + Send + Sync,
+	P::Public: Codec + PartialEq,
+	P::Signature: Codec,
+	DigestItemFor<Block>: CompatibleDigestItem<P>,
+{
+	fn produce_candidate(
+		&self,
+		parent: &Block::Header,
+		relay_parent: PHash,
+		validation_data: &ValidationData,
+		downward_messages: &[DownwardMessage],
+	) -> Option<ParachainCandidate<Block>> {
+		let slot = slot_for_relay_parent(validation_data);
+		let authorities = authorities::<_, _, P>(&*self.client, &BlockId::hash(parent.hash()))
+			.map_err(|e| error!("{}", e))
+			.ok()?;
+
+		if slot_author::<P>(slot, &authorities) != Some(&self.key.public()) {
+			debug!("Not the Aura author of slot {} at relay parent {}", slot, relay_parent);
+			return None
+		}
+
+		let inherent_digests = Digest {
+			logs: vec![<DigestItemFor<Block> as CompatibleDigestItem<P>>::aura_pre_digest(slot)],
+		};
+		let (recorded, validator_inherents) = self.inner.build_block(
+			parent,
+			relay_parent,
+			validation_data,
+			downward_messages,
+			inherent_digests,
+		)?;
+
+		let (mut header, extrinsics) = recorded.block.deconstruct();
+		let signature = self.key.sign(header.hash().as_ref());
+		let seal = <DigestItemFor<Block> as CompatibleDigestItem<P>>::aura_seal(signature);
+		let collation_info = self.inner.import_and_collect(
+			header.clone(),
+			extrinsics.clone(),
+			vec![seal.clone()],
+		);
+		header.digest_mut().push(seal);
+		let block = Block::new(header, extrinsics);
+
+		let recorded = RecordedBlock { block: block.clone(), ..recorded };
+		let block_data = recorded.into_parachain_block_data(validator_inherents);
+
+		Some(ParachainCandidate { block, block_data, collation_info })
+	}
+}
+
+/// The sealed headers of the recent slots, with their authors.
+type SealedHeaders<Block, P> =
+	BTreeMap<u64, Vec<(AuthorityId<P>, <Block as BlockT>::Header)>>;
+
+/// Verifies the Aura seal of imported parachain blocks.
+///
+/// Equivocations are logged and passed to the `ReportEquivocation`, the blocks are still
+/// imported.
+pub struct AuraVerifier<Block: BlockT, C, P: Pair> {
+	client: Arc<C>,
+	sealed_headers: Mutex<SealedHeaders<Block, P>>,
+	report_equivocation: Option<ReportEquivocation<Block, P>>,
+}
+
+impl<Block: BlockT, C, P: Pair> AuraVerifier<Block, C, P> {
+	/// Create a new instance.
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, sealed_headers: Mutex::new(BTreeMap::new()), report_equivocation: None }
+	}
+
+	/// Pass every detected equivocation to `report_equivocation`.
+	pub fn with_equivocation_reporter(
+		mut self,
+		report_equivocation: ReportEquivocation<Block, P>,
+	) -> Self {
+		self.report_equivocation = Some(report_equivocation);
+		self
+	}
+}
+
+impl<Block: BlockT, C, P: Pair> AuraVerifier<Block, C, P> where P::Public: PartialEq {
+	/// Note the sealed `header` of `author` for `slot`.
+	///
+	/// Returns the equivocation if `author` already sealed a different header for `slot`.
+	fn check_equivocation(
+		&self,
+		slot: u64,
+		author: &AuthorityId<P>,
+		header: &Block::Header,
+	) -> Option<AuraEquivocation<Block::Header, AuthorityId<P>>> {
+		let mut sealed_headers = self.sealed_headers.lock();
+
+		let latest = sealed_headers.keys().next_back().cloned().unwrap_or(slot).max(slot);
+		let keep_from = latest.saturating_sub(EQUIVOCATION_SLOTS);
+		if slot < keep_from {
+			return None
+		}
+		*sealed_headers = sealed_headers.split_off(&keep_from);
+
+		let headers = sealed_headers.entry(slot).or_insert_with(Vec::new);
+		let first_header = headers.iter()
+			.find(|(a, h)| a == author && h.hash() != header.hash())
+			.map(|(_, h)| h.clone());
+
+		match first_header {
+			Some(first_header) => Some(AuraEquivocation {
+				slot,
+				author: author.clone(),
+				first_header,
+				second_header: header.clone(),
+			}),
+			None => {
+				if !headers.iter().any(|(_, h)| h.hash() == header.hash()) {
+					headers.push((author.clone(), header.clone()));
+				}
+				None
+			},
+		}
+	}
+}
+
+impl<Block, C, P> Verifier<Block> for AuraVerifier<Block, C, P> where
+	Block: BlockT<Hash=H256>,
+	C: ProvideRuntimeApi + Send + Sync,
+	C::Api: AuraApi<Block, AuthorityId<P>>,
+	P: Pair + Send + Sync + 'static,
+	P::Public: Codec + PartialEq + AsRef<P::Public>,
+	P::Signature: Codec,
+	DigestItemFor<Block>: CompatibleDigestItem<P>,
+{
+	fn verify(
+		&self,
+		origin: BlockOrigin,
+		mut header: Block::Header,
+		justification: Option<Justification>,
+		body: Option<Vec<Block::Extrinsic>>,
+	) -> Result<(ImportBlock<Block>, Option<Vec<AuthorityId<P>>>), String> {
+		let hash = header.hash();
+		let sealed_header = header.clone();
+		let seal = header.digest_mut().pop()
+			.ok_or_else(|| format!("Header {} is unsealed", hash))?;
+		let signature = seal.as_aura_seal()
+			.ok_or_else(|| format!("Header {} has an invalid seal", hash))?;
+		let slot = header.digest().logs().iter()
+			.filter_map(|d| d.as_aura_pre_digest())
+			.next()
+			.ok_or_else(|| format!("Header {} has no Aura pre-digest", hash))?;
+
+		let authorities = authorities::<_, _, P>(
+			&*self.client,
+			&BlockId::hash(*header.parent_hash()),
+		)?;
+		let author = slot_author::<P>(slot, &authorities)
+			.ok_or_else(|| format!("No Aura authorities at {}", header.parent_hash()))?;
+
+		let pre_hash = header.hash();
+		if !P::verify(&signature, pre_hash.as_ref(), author) {
+			return Err(format!("Header {} has a bad seal of slot {}", hash, slot))
+		}
+
+		if let Some(equivocation) = self.check_equivocation(slot, author, &sealed_header) {
+			warn!(
+				"The Aura author of slot {} equivocated: {} and {}",
+				slot,
+				equivocation.first_header.hash(),
+				hash,
+			);
+			if let Some(ref report_equivocation) = self.report_equivocation {
+				report_equivocation(equivocation);
+			}
+		}
+
+		let import_block = ImportBlock {
+			origin,
+			header,
+			justification,
+			post_digests: vec![seal],
+			body,
+			finalized: false,
+			auxiliary: Vec::new(),
+			fork_choice: ForkChoiceStrategy::LongestChain,
+		};
+
+		Ok((import_block, None))
+	}
+}
@@ -0,0 +1,103 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Keeps track of the candidates that were authored by the collator.
+
+use polkadot_primitives::Hash as PHash;
+use futures::sync::mpsc;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use std::collections::VecDeque;
+
+/// The default number of candidates that are kept.
+const DEFAULT_CAPACITY: usize = 32;
+
+/// A candidate that was authored by the collator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthoredCandidate<Hash, Number> {
+	/// The hash of the parachain block.
+	pub hash: Hash,
+	/// The number of the parachain block.
+	pub number: Number,
+	/// The hash of the relay parent the candidate was built for.
+	pub relay_parent: PHash,
+	/// The number of the relay parent the candidate was built for.
+	pub relay_parent_number: u32,
+	/// The size in bytes of the PoV of the candidate.
+	pub pov_size: usize,
+}
+
+struct Inner<Hash, Number> {
+	candidates: VecDeque<AuthoredCandidate<Hash, Number>>,
+	subscribers: Vec<mpsc::UnboundedSender<AuthoredCandidate<Hash, Number>>>,
+}
+
+/// The most recent candidates that were authored by the collator.
+///
+/// Every new candidate is also sent to the subscribers.
+pub struct AuthoredCandidates<Hash, Number> {
+	capacity: usize,
+	inner: Mutex<Inner<Hash, Number>>,
+}
+
+impl<Hash: Clone, Number: Clone> AuthoredCandidates<Hash, Number> {
+	/// Create a new instance that keeps the last 32 candidates.
+	pub fn new() -> Self {
+		Self::with_capacity(DEFAULT_CAPACITY)
+	}
+
+	/// Create a new instance that keeps the last `capacity` candidates.
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self {
+			capacity,
+			inner: Mutex::new(Inner { candidates: VecDeque::new(), subscribers: Vec::new() }),
+		}
+	}
+
+	/// Note a new authored `candidate` and send it to the subscribers.
+	pub fn note(&self, candidate: AuthoredCandidate<Hash, Number>) {
+		let mut inner = self.inner.lock();
+
+		inner.subscribers.retain(|s| s.unbounded_send(candidate.clone()).is_ok());
+
+		if inner.candidates.len() >= self.capacity {
+			inner.candidates.pop_front();
+		}
+		if self.capacity > 0 {
+			inner.candidates.push_back(candidate);
+		}
+	}
+
+	/// Returns the most recent candidates, the newest last.
+	pub fn recent(&self) -> Vec<AuthoredCandidate<Hash, Number>> {
+		self.inner.lock().candidates.iter().cloned().collect()
+	}
+
+	/// Returns a stream of the candidates that are authored from now on.
+	pub fn subscribe(&self) -> mpsc::UnboundedReceiver<AuthoredCandidate<Hash, Number>> {
+		let (sender, receiver) = mpsc::unbounded();
+		self.inner.lock().subscribers.push(sender);
+		receiver
+	}
+}
+
+impl<Hash: Clone, Number: Clone> Default for AuthoredCandidates<Hash, Number> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,137 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `benchmark-pov` subcommand, which measures the witness size of runtime calls.
+//!
+//! The node passes one extrinsic for every call that should be measured. Every extrinsic is
+//! applied alone on top of the best block with `ProofRecorder::estimate_proof_size`, none of
+//! them is imported.
+
+use substrate_client::{
+	Client, CallExecutor, backend::Backend, block_builder::api::BlockBuilder as BlockBuilderApi,
+};
+use substrate_primitives::{Blake2Hasher, H256};
+use sr_primitives::{generic::BlockId, traits::{Block as BlockT, ProvideRuntimeApi}};
+use cumulus_client_proof_recorder::ProofRecorder;
+use structopt::StructOpt;
+use serde::Serialize;
+
+use parity_codec::Encode;
+
+use std::{fs, io::{self, Write}, path::PathBuf, str::FromStr};
+
+/// The output format of the `benchmark-pov` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchmarkFormat {
+	/// A JSON array of `ProofSizeResult`.
+	Json,
+	/// A CSV table with a header line.
+	Csv,
+}
+
+impl FromStr for BenchmarkFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"json" => Ok(BenchmarkFormat::Json),
+			"csv" => Ok(BenchmarkFormat::Csv),
+			_ => Err(format!("Unknown format {}, expected json or csv", s)),
+		}
+	}
+}
+
+/// The measured sizes of one call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofSizeResult {
+	/// The name of the call, as given by the node.
+	pub name: String,
+	/// The size in bytes of the encoded extrinsic.
+	pub encoded_size: usize,
+	/// The size in bytes of the witness data that the call adds to a block.
+	///
+	/// `None` if the extrinsic could not be applied.
+	pub proof_size: Option<usize>,
+	/// The error, if the extrinsic could not be applied.
+	pub error: Option<String>,
+}
+
+/// The `benchmark-pov` subcommand of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub struct BenchmarkPovCmd {
+	/// The output format, `json` or `csv`.
+	#[structopt(long = "format", default_value = "json")]
+	pub format: BenchmarkFormat,
+
+	/// The file to write the results to, stdout if not given.
+	#[structopt(long = "output", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+}
+
+impl BenchmarkPovCmd {
+	/// Measure the witness size of every named extrinsic in `calls` on top of the best block of
+	/// the client of `proof_recorder` and write the results.
+	pub fn run<B, E, Block, RA>(
+		&self,
+		proof_recorder: &ProofRecorder<B, E, Block, RA>,
+		calls: Vec<(String, <Block as BlockT>::Extrinsic)>,
+	) -> io::Result<()> where
+		B: Backend<Block, Blake2Hasher>,
+		E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+		Block: BlockT<Hash=H256>,
+		RA: Send + Sync,
+		Client<B, E, Block, RA>: ProvideRuntimeApi,
+		<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
+	{
+		let parent = BlockId::Hash(proof_recorder.client().info().chain.best_hash);
+
+		let results = calls.into_iter().map(|(name, extrinsic)| {
+			let encoded_size = extrinsic.encode().len();
+			let (proof_size, error) = match proof_recorder.estimate_proof_size(&parent, extrinsic) {
+				Ok(size) => (Some(size), None),
+				Err(e) => (None, Some(format!("{:?}", e))),
+			};
+			ProofSizeResult { name, encoded_size, proof_size, error }
+		}).collect::<Vec<_>>();
+
+		let output = match self.format {
+			BenchmarkFormat::Json => serde_json::to_string_pretty(&results)
+				.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
+			BenchmarkFormat::Csv => to_csv(&results),
+		};
+
+		match self.output {
+			Some(ref path) => fs::write(path, output),
+			None => io::stdout().write_all(output.as_bytes()),
+		}
+	}
+}
+
+/// Format the `results` as CSV, the text columns are quoted.
+fn to_csv(results: &[ProofSizeResult]) -> String {
+	let mut csv = String::from("name,encoded_size,proof_size,error\n");
+	for result in results {
+		csv.push_str(&format!(
+			"\"{}\",{},{},\"{}\"\n",
+			result.name.replace('"', "\"\""),
+			result.encoded_size,
+			result.proof_size.map(|s| s.to_string()).unwrap_or_default(),
+			result.error.as_ref().map(|e| e.replace('"', "\"\"")).unwrap_or_default(),
+		));
+	}
+	csv
+}
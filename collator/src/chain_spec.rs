@@ -0,0 +1,56 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The parachain specific data of a chain spec.
+//!
+//! Chain specs do not support extensions yet, so the data is stored in their properties as
+//! `relayChain` and `paraId`.
+
+use cumulus_runtime::ParaId;
+use polkadot_primitives::parachain::Id as PParaId;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+/// The parachain specific data of a chain spec.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Extensions {
+	/// The id of the chain spec of the relay chain the parachain is registered at.
+	pub relay_chain: String,
+	/// The id of the parachain.
+	pub para_id: ParaId,
+}
+
+impl Extensions {
+	/// Read the extensions from the given chain spec `properties`.
+	///
+	/// Returns `None` if the properties do not contain valid extensions.
+	pub fn try_get(properties: &Map<String, Value>) -> Option<Self> {
+		serde_json::from_value(Value::Object(properties.clone())).ok()
+	}
+
+	/// Write the extensions into the given chain spec `properties`.
+	pub fn insert_into(&self, properties: &mut Map<String, Value>) {
+		if let Ok(Value::Object(extensions)) = serde_json::to_value(self) {
+			properties.extend(extensions);
+		}
+	}
+
+	/// Returns the id of the parachain as the relay chain represents it.
+	pub fn para_id(&self) -> PParaId {
+		self.para_id.into()
+	}
+}
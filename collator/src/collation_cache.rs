@@ -0,0 +1,74 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Protection against producing two different collations for the same relay parent.
+//!
+//! The Polkadot collator signs every collation with the collator key. Two different collations
+//! for the same relay parent and parachain head signed with the same key are an equivocation,
+//! so a collation is only produced once and returned again if it is requested again.
+
+use polkadot_primitives::{
+	Hash as PHash, parachain::{BlockData, HeadData, OutgoingMessages},
+};
+use parking_lot::Mutex;
+
+use std::collections::VecDeque;
+
+/// The number of collations that are kept.
+const CAPACITY: usize = 16;
+
+/// A collation as it is returned to the Polkadot collator.
+pub(crate) type Collation = (BlockData, HeadData, OutgoingMessages);
+
+/// The collations that were produced for the last relay parents.
+pub(crate) struct CollationCache<Hash> {
+	collations: Mutex<VecDeque<((PHash, Hash), Collation)>>,
+}
+
+impl<Hash: PartialEq> CollationCache<Hash> {
+	/// Create a new instance.
+	pub(crate) fn new() -> Self {
+		Self { collations: Mutex::new(VecDeque::with_capacity(CAPACITY)) }
+	}
+
+	/// Returns the collation that was produced for `relay_parent` on top of the parachain head
+	/// `included`.
+	pub(crate) fn get(
+		&self,
+		relay_parent: &PHash,
+		included: &Hash,
+	) -> Option<Collation> {
+		self.collations.lock()
+			.iter()
+			.find(|((r, i), _)| r == relay_parent && i == included)
+			.map(|(_, collation)| collation.clone())
+	}
+
+	/// Insert the `collation` that was produced for `relay_parent` on top of the parachain head
+	/// `included`.
+	pub(crate) fn insert(
+		&self,
+		relay_parent: PHash,
+		included: Hash,
+		collation: Collation,
+	) {
+		let mut collations = self.collations.lock();
+		if collations.len() >= CAPACITY {
+			collations.pop_front();
+		}
+		collations.push_back(((relay_parent, included), collation));
+	}
+}
@@ -0,0 +1,134 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Management of the key that is used by the collator to author parachain blocks.
+//!
+//! The key is stored in the keystore of the node. `CollatorKeyCmd` inserts it and
+//! `CollatorParams` enables authoring on startup, `load_collator_key` refuses to return
+//! anything if there is no key in the keystore.
+
+use substrate_primitives::{Pair, crypto::Protected};
+use substrate_keystore::{Store, Error as KeystoreError};
+use structopt::StructOpt;
+
+use std::{fmt, path::PathBuf, sync::Arc};
+
+/// An error that occurred while managing the collator key.
+#[derive(Debug)]
+pub enum CollatorKeyError {
+	/// The keystore returned an error.
+	Keystore(KeystoreError),
+	/// The collator should author blocks, but there is no key in the keystore.
+	NoKey(PathBuf),
+}
+
+impl fmt::Display for CollatorKeyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CollatorKeyError::Keystore(e) => write!(f, "Keystore error: {:?}", e),
+			CollatorKeyError::NoKey(path) => write!(
+				f,
+				"No collator key in the keystore at {}, insert one with `key insert`",
+				path.display(),
+			),
+		}
+	}
+}
+
+impl std::error::Error for CollatorKeyError {}
+
+impl From<KeystoreError> for CollatorKeyError {
+	fn from(e: KeystoreError) -> Self {
+		CollatorKeyError::Keystore(e)
+	}
+}
+
+/// The command line parameters of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub struct CollatorParams {
+	/// Author parachain blocks with the collator key from the keystore.
+	///
+	/// The node refuses to start if there is no collator key.
+	#[structopt(long = "collator")]
+	pub collator: bool,
+
+	/// Connect to the relay chain node at the given WebSocket URL instead of running an
+	/// embedded relay chain node.
+	///
+	/// Can be given multiple times, the next URL is used when the current node fails.
+	#[structopt(long = "relay-chain-rpc-url")]
+	pub relay_chain_rpc_urls: Vec<String>,
+}
+
+/// The `key` subcommand of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub enum CollatorKeyCmd {
+	/// Insert the collator key into the keystore.
+	#[structopt(name = "insert")]
+	Insert {
+		/// The secret URI of the key, e.g. a seed or a mnemonic phrase.
+		#[structopt(long = "suri")]
+		suri: String,
+		/// The path to the keystore.
+		#[structopt(long = "keystore-path", parse(from_os_str))]
+		keystore_path: PathBuf,
+	},
+}
+
+impl CollatorKeyCmd {
+	/// Run the command for keys of type `P`.
+	pub fn run<P: Pair>(
+		&self,
+		password: Option<Protected<String>>,
+	) -> Result<(), CollatorKeyError> {
+		match self {
+			CollatorKeyCmd::Insert { suri, keystore_path } => {
+				let public = insert_collator_key::<P>(keystore_path.clone(), suri, password)?;
+				println!("Inserted collator key {:?}", public);
+				Ok(())
+			},
+		}
+	}
+}
+
+/// Insert the collator key with the given secret URI into the keystore at `keystore_path`.
+pub fn insert_collator_key<P: Pair>(
+	keystore_path: PathBuf,
+	suri: &str,
+	password: Option<Protected<String>>,
+) -> Result<P::Public, CollatorKeyError> {
+	let store = Store::open(keystore_path, password)?;
+	let pair = store.insert::<P>(suri)?;
+
+	Ok(pair.public())
+}
+
+/// Load the collator key from the keystore at `keystore_path`.
+///
+/// If the keystore contains multiple keys of type `P`, the first one is used. Returns
+/// `CollatorKeyError::NoKey` if the keystore contains no key of type `P`.
+pub fn load_collator_key<P: Pair>(
+	keystore_path: PathBuf,
+	password: Option<Protected<String>>,
+) -> Result<Arc<P>, CollatorKeyError> {
+	let store = Store::open(keystore_path.clone(), password)?;
+	let public = store.contents::<P::Public>()?
+		.into_iter()
+		.next()
+		.ok_or(CollatorKeyError::NoKey(keystore_path))?;
+
+	Ok(Arc::new(store.load::<P>(&public)?))
+}
@@ -0,0 +1,132 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Subcommands that export the genesis data that is required to register the parachain.
+//!
+//! The node builds the client for the configured chain spec, which contains the genesis block,
+//! and runs the command on it.
+
+use crate::{genesis_head_data, genesis_validation_code};
+use substrate_client::{
+	Client, CallExecutor, backend::Backend, error::Error as ClientError,
+};
+use substrate_primitives::{Blake2Hasher, hexdisplay::HexDisplay};
+use sr_primitives::traits::Block as BlockT;
+use structopt::StructOpt;
+
+use std::{fmt, fs, io::{self, Write}, path::{Path, PathBuf}};
+
+/// An error that occurred while exporting genesis data.
+#[derive(Debug)]
+pub enum ExportGenesisError {
+	/// The client returned an error.
+	Client(ClientError),
+	/// The data could not be written.
+	Io(io::Error),
+}
+
+impl fmt::Display for ExportGenesisError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ExportGenesisError::Client(e) => write!(f, "Client error: {}", e),
+			ExportGenesisError::Io(e) => write!(f, "IO error: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for ExportGenesisError {}
+
+impl From<ClientError> for ExportGenesisError {
+	fn from(e: ClientError) -> Self {
+		ExportGenesisError::Client(e)
+	}
+}
+
+impl From<io::Error> for ExportGenesisError {
+	fn from(e: io::Error) -> Self {
+		ExportGenesisError::Io(e)
+	}
+}
+
+/// The `export-genesis-state` subcommand of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub struct ExportGenesisStateCmd {
+	/// The file to write the genesis head data to, stdout if not given.
+	#[structopt(parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Write the raw bytes instead of hex.
+	#[structopt(long = "raw")]
+	pub raw: bool,
+}
+
+impl ExportGenesisStateCmd {
+	/// Export the encoded genesis header of `client`.
+	pub fn run<B, E, Block, RA>(
+		&self,
+		client: &Client<B, E, Block, RA>,
+	) -> Result<(), ExportGenesisError> where
+		B: Backend<Block, Blake2Hasher>,
+		E: CallExecutor<Block, Blake2Hasher>,
+		Block: BlockT,
+	{
+		let head_data = genesis_head_data(client)?;
+		write_output(self.output.as_ref().map(|p| p.as_path()), &head_data.0, self.raw)
+	}
+}
+
+/// The `export-genesis-wasm` subcommand of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub struct ExportGenesisWasmCmd {
+	/// The file to write the genesis validation code to, stdout if not given.
+	#[structopt(parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Write the raw bytes instead of hex.
+	#[structopt(long = "raw")]
+	pub raw: bool,
+}
+
+impl ExportGenesisWasmCmd {
+	/// Export the wasm runtime in the genesis storage of `client`.
+	pub fn run<B, E, Block, RA>(
+		&self,
+		client: &Client<B, E, Block, RA>,
+	) -> Result<(), ExportGenesisError> where
+		B: Backend<Block, Blake2Hasher>,
+		E: CallExecutor<Block, Blake2Hasher>,
+		Block: BlockT,
+	{
+		let code = genesis_validation_code(client)?;
+		write_output(self.output.as_ref().map(|p| p.as_path()), &code, self.raw)
+	}
+}
+
+/// Write `data` to the file at `output` or to stdout, as raw bytes or as hex.
+fn write_output(output: Option<&Path>, data: &[u8], raw: bool) -> Result<(), ExportGenesisError> {
+	let data = if raw {
+		data.to_vec()
+	} else {
+		format!("0x{}", HexDisplay::from(&data)).into_bytes()
+	};
+
+	match output {
+		Some(path) => fs::write(path, data)?,
+		None => io::stdout().write_all(&data)?,
+	}
+
+	Ok(())
+}
@@ -0,0 +1,181 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `inspect-pov` subcommand, which explains what is in a PoV and why it fails validation.
+//!
+//! The PoV is read from a file, as raw bytes or as `0x` prefixed hex, like the block data of a
+//! collation. The node runs the command with the types of its parachain runtime, so the blocks
+//! can be executed natively with `validate_block`.
+
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT};
+use cumulus_runtime::{
+	ParachainBlockData, ValidationData, ValidationError, ProvidePolkadotInherent, WitnessData,
+	validate_block::{validate_block_with_stats, ExecuteBlock, DEFAULT_MAX_POV_SIZE},
+};
+use polkadot_parachain::ValidationParams;
+use structopt::StructOpt;
+
+use parity_codec::{Encode, Decode};
+
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+/// An error that occurred while inspecting a PoV.
+#[derive(Debug)]
+pub enum InspectPovError {
+	/// A file could not be read.
+	Io(io::Error),
+	/// The content of the file at the given path could not be decoded.
+	Decode(PathBuf),
+	/// The blocks failed validation.
+	Validation(ValidationError),
+}
+
+impl fmt::Display for InspectPovError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			InspectPovError::Io(e) => write!(f, "IO error: {}", e),
+			InspectPovError::Decode(path) => write!(f, "Could not decode {}", path.display()),
+			InspectPovError::Validation(e) => write!(f, "Validation failed: {:?}", e),
+		}
+	}
+}
+
+impl std::error::Error for InspectPovError {}
+
+impl From<io::Error> for InspectPovError {
+	fn from(e: io::Error) -> Self {
+		InspectPovError::Io(e)
+	}
+}
+
+/// The `inspect-pov` subcommand of a collator.
+#[derive(Debug, Clone, StructOpt)]
+pub struct InspectPovCmd {
+	/// The file that contains the encoded `ParachainBlockData`.
+	#[structopt(parse(from_os_str))]
+	pub input: PathBuf,
+
+	/// The file that contains the head data of the parent, the encoded parent header.
+	///
+	/// If given, the blocks are executed with `validate_block`.
+	#[structopt(long = "parent-head", parse(from_os_str))]
+	pub parent_head: Option<PathBuf>,
+
+	/// The file that contains the encoded `ValidationData` that is passed to `validate_block`.
+	#[structopt(long = "validation-data", parse(from_os_str))]
+	pub validation_data: Option<PathBuf>,
+
+	/// The maximum PoV size in bytes that is passed to `validate_block`.
+	#[structopt(long = "max-pov-size")]
+	pub max_pov_size: Option<usize>,
+}
+
+impl InspectPovCmd {
+	/// Print the blocks and the witness data of the PoV and execute them, if the parent head
+	/// is given.
+	///
+	/// `E` and `I` are the block executor and the `PolkadotInherent` of the parachain runtime,
+	/// as they are passed to `register_validate_block!`.
+	pub fn run<Block, E, I>(&self) -> Result<(), InspectPovError> where
+		Block: BlockT,
+		E: ExecuteBlock<Block>,
+		I: ProvidePolkadotInherent<Block>,
+	{
+		let pov = read_input(&self.input)?;
+		let block_data = ParachainBlockData::<Block>::decode_versioned(&pov)
+			.ok_or_else(|| InspectPovError::Decode(self.input.clone()))?;
+
+		println!("PoV size: {} bytes", pov.len());
+		println!("Witness data storage root: {}", block_data.witness_data_storage_root());
+		for (header, extrinsics) in block_data.blocks() {
+			println!(
+				"Block {:?} ({}): parent {}, {} extrinsics, {} bytes",
+				header.number(),
+				header.hash(),
+				header.parent_hash(),
+				extrinsics.len(),
+				header.encode().len() + extrinsics.encode().len(),
+			);
+		}
+
+		let (encoding, nodes) = match block_data.witness_data() {
+			WitnessData::Full(nodes) => ("full", nodes),
+			WitnessData::Compact(nodes) => ("compact", nodes),
+		};
+		println!(
+			"Witness data: {} encoding, {} nodes, {} bytes",
+			encoding,
+			nodes.len(),
+			block_data.witness_data().encode().len(),
+		);
+
+		let parent_head = match self.parent_head {
+			Some(ref path) => read_input(path)?,
+			None => return Ok(()),
+		};
+		let validation_data = match self.validation_data {
+			Some(ref path) => Some(
+				ValidationData::decode(&mut &read_input(path)?[..])
+					.ok_or_else(|| InspectPovError::Decode(path.clone()))?
+			),
+			None => None,
+		};
+
+		let params = ValidationParams { block_data: pov, parent_head, ingress: Vec::new() };
+		let max_pov_size = self.max_pov_size.unwrap_or(DEFAULT_MAX_POV_SIZE);
+		let (res, stats) = validate_block_with_stats::<Block, E, I>(
+			params,
+			validation_data,
+			max_pov_size,
+		).map_err(InspectPovError::Validation)?;
+
+		println!("Validation succeeded, storage root {}", res.storage_root);
+		println!(
+			"Read {} of {} unique witness nodes ({} duplicates), {} of {} bytes were not used",
+			stats.read_nodes,
+			stats.nodes,
+			stats.duplicate_nodes,
+			stats.unused_size(),
+			stats.size,
+		);
+
+		Ok(())
+	}
+}
+
+/// Read the file at `path` as raw bytes or as `0x` prefixed hex.
+fn read_input(path: &Path) -> Result<Vec<u8>, InspectPovError> {
+	let data = fs::read(path)?;
+
+	if data.starts_with(b"0x") {
+		decode_hex(&data[2..]).ok_or_else(|| InspectPovError::Decode(path.to_path_buf()))
+	} else {
+		Ok(data)
+	}
+}
+
+/// Decode the given hex digits, ignoring trailing whitespace.
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+	let hex = std::str::from_utf8(hex).ok()?.trim_end();
+	if hex.len() % 2 != 0 {
+		return None
+	}
+
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+		.collect()
+}
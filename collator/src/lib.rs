@@ -0,0 +1,528 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Cumulus Collator implementation for Substrate.
+//!
+//! The `Collator` asks its `ParachainConsensus` for a new parachain block on top of the
+//! parachain head that is given by the relay chain and returns it as a collation to the
+//! Polkadot collator.
+
+use sr_primitives::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, NumberFor, ProvideRuntimeApi},
+};
+use substrate_client::runtime_api::ApiExt;
+use polkadot_primitives::{
+	Hash as PHash,
+	parachain::{Id as ParaId, Message, BlockData, HeadData, OutgoingMessages, TargetedMessage},
+};
+use polkadot_collator::{
+	ParachainContext, BuildParachainContext, InvalidHead, ParachainStatus, Network,
+};
+use cumulus_runtime::{
+	ParachainBlockData, ValidationData, DownwardMessage, CollationInfo, CollationInfoV1,
+	CollectCollationInfo,
+};
+use cumulus_relay_chain_interface::RelayChainInterface;
+
+use parity_codec::{Encode, Decode};
+use log::{error, info, debug};
+
+use retry::TimeoutTracker;
+use collation_cache::{CollationCache, Collation};
+
+use std::sync::Arc;
+
+mod relay_chain_consensus;
+mod aura;
+mod collator_key;
+mod parachain_inherent;
+mod registration;
+mod export_genesis;
+mod relay_chain_cli;
+mod purge_chain;
+mod chain_spec;
+mod lookahead;
+mod inspect_pov;
+mod benchmark_pov;
+mod metrics;
+mod authored_candidates;
+mod rpc;
+mod retry;
+mod collation_cache;
+
+pub use relay_chain_consensus::{RelayChainConsensus, ProvideExtrinsics, PoVBudget};
+pub use aura::{AuraConsensus, AuraVerifier, AuraEquivocation, ReportEquivocation};
+pub use parachain_inherent::{ProvideParachainInherentData, ParachainInherentDataProvider};
+pub use export_genesis::{ExportGenesisError, ExportGenesisStateCmd, ExportGenesisWasmCmd};
+pub use relay_chain_cli::{RelayChainCli, split_args};
+pub use purge_chain::PurgeChainCmd;
+pub use chain_spec::Extensions;
+pub use lookahead::{AsyncBackingParams, SelectParent, Lookahead};
+pub use retry::RetryPolicy;
+pub use inspect_pov::{InspectPovCmd, InspectPovError};
+pub use benchmark_pov::{BenchmarkPovCmd, BenchmarkFormat, ProofSizeResult};
+pub use metrics::Metrics;
+pub use authored_candidates::{AuthoredCandidates, AuthoredCandidate};
+pub use rpc::{CumulusApi, Cumulus, Metadata as RpcMetadata, TaskExecutor as RpcTaskExecutor};
+pub use registration::{
+	ParachainRegistration, genesis_head_data, genesis_validation_code, genesis_registration,
+	genesis_from_storage, genesis_registration_from_storage,
+};
+pub use collator_key::{
+	CollatorKeyError, CollatorParams, CollatorKeyCmd, insert_collator_key, load_collator_key,
+};
+
+/// A parachain block that was produced by a `ParachainConsensus`.
+pub struct ParachainCandidate<Block: BlockT> {
+	/// The produced block.
+	pub block: Block,
+	/// The data that is required to validate the block on the relay chain.
+	pub block_data: ParachainBlockData<Block>,
+	/// The outputs of the block, see `collation_info`.
+	///
+	/// `None` if the runtime does not provide `CollectCollationInfo` or the call failed.
+	pub collation_info: Option<CollationInfo>,
+}
+
+/// Returns the `CollationInfo` of the block with the given `header` that is imported in `client`.
+///
+/// Runtimes that only provide version 1 of `CollectCollationInfo` are supported, the head data
+/// is taken from `header` and there is no hrmp watermark.
+///
+/// Returns `None` if the runtime does not provide the API or if the call failed.
+pub fn collation_info<Block, C>(client: &C, header: &Block::Header) -> Option<CollationInfo> where
+	Block: BlockT,
+	C: ProvideRuntimeApi,
+	C::Api: CollectCollationInfo<Block> + ApiExt<Block>,
+{
+	let runtime_api = client.runtime_api();
+	let at = BlockId::hash(header.hash());
+
+	let version = runtime_api
+		.has_api_with::<dyn CollectCollationInfo<Block, Error = ()>, _>(&at, |v| v >= 2)
+		.and_then(|latest| if latest {
+			Ok(Some(2))
+		} else {
+			runtime_api.has_api::<dyn CollectCollationInfo<Block, Error = ()>>(&at)
+				.map(|legacy| if legacy { Some(1) } else { None })
+		});
+
+	let version = match version {
+		Ok(version) => version,
+		Err(e) => {
+			error!("Could not get the CollectCollationInfo version at {}: {:?}", header.hash(), e);
+			return None
+		},
+	};
+
+	#[allow(deprecated)]
+	let info = collect_with_version(
+		version,
+		header.encode(),
+		|| runtime_api.collect_collation_info(&at, header),
+		|| runtime_api.collect_collation_info_before_version_2(&at),
+	);
+
+	match info {
+		Some(info) => info
+			.map_err(|e| {
+				error!("Could not collect the collation info of {}: {:?}", header.hash(), e)
+			})
+			.ok(),
+		None => {
+			error!("The runtime at {} does not provide CollectCollationInfo", header.hash());
+			None
+		},
+	}
+}
+
+/// Collect the `CollationInfo` with `latest` or `before_version_2`, depending on the `version`
+/// of `CollectCollationInfo` that the runtime provides.
+///
+/// `head_data` is only used for version 1, which does not return it. Returns `None` if the
+/// runtime does not provide the API.
+fn collect_with_version<E>(
+	version: Option<u32>,
+	head_data: Vec<u8>,
+	latest: impl FnOnce() -> Result<CollationInfo, E>,
+	before_version_2: impl FnOnce() -> Result<CollationInfoV1, E>,
+) -> Option<Result<CollationInfo, E>> {
+	match version {
+		Some(2) => Some(latest()),
+		Some(_) => Some(before_version_2().map(|info| info.into_latest(head_data))),
+		None => None,
+	}
+}
+
+/// Returns the horizontal messages of the given `CollationInfo` as `OutgoingMessages`.
+///
+/// The relay chain requires the messages to be sorted by their recipient, the messages to the
+/// same recipient keep their order.
+fn outgoing_messages(collation_info: Option<&CollationInfo>) -> OutgoingMessages {
+	let mut outgoing_messages = collation_info
+		.map(|info| info.horizontal_messages.iter()
+			.map(|m| TargetedMessage { target: m.recipient.into(), data: m.data.clone() })
+			.collect::<Vec<_>>()
+		)
+		.unwrap_or_default();
+	outgoing_messages.sort_by_key(|m| m.target);
+
+	OutgoingMessages { outgoing_messages }
+}
+
+/// Decides when and how the collator produces a new parachain block.
+///
+/// This allows to plug in different consensus mechanisms, e.g. slot based authoring, without
+/// changing the `Collator`.
+pub trait ParachainConsensus<Block: BlockT>: Send + Sync {
+	/// Produce a new parachain block on top of `parent` for the given relay parent.
+	///
+	/// Returns `None` if no block should or could be produced.
+	///
+	/// The `downward_messages` must be passed to the runtime in the same way as
+	/// `validate_block` does, see `ProvidePolkadotInherent::create_downward_messages_inherent`.
+	fn produce_candidate(
+		&self,
+		parent: &Block::Header,
+		relay_parent: PHash,
+		validation_data: &ValidationData,
+		downward_messages: &[DownwardMessage],
+	) -> Option<ParachainCandidate<Block>>;
+}
+
+/// The collator that asks the `ParachainConsensus` for blocks and returns them as collations.
+pub struct Collator<Block: BlockT, PC, R> {
+	consensus: Arc<PC>,
+	relay_chain: Arc<R>,
+	select_parent: Arc<dyn SelectParent<Block>>,
+	timeout_tracker: Option<Arc<TimeoutTracker<Block::Hash>>>,
+	collation_cache: Arc<CollationCache<Block::Hash>>,
+	metrics: Option<Metrics>,
+	authored_candidates: Option<Arc<AuthoredCandidates<Block::Hash, NumberFor<Block>>>>,
+	_phantom: std::marker::PhantomData<Block>,
+}
+
+impl<Block: BlockT, PC, R> Clone for Collator<Block, PC, R> {
+	fn clone(&self) -> Self {
+		Self {
+			consensus: self.consensus.clone(),
+			relay_chain: self.relay_chain.clone(),
+			select_parent: self.select_parent.clone(),
+			timeout_tracker: self.timeout_tracker.clone(),
+			collation_cache: self.collation_cache.clone(),
+			metrics: self.metrics.clone(),
+			authored_candidates: self.authored_candidates.clone(),
+			_phantom: Default::default(),
+		}
+	}
+}
+
+impl<Block, PC, R> Collator<Block, PC, R> where
+	Block: BlockT,
+	PC: ParachainConsensus<Block>,
+	R: RelayChainInterface,
+{
+	/// Create a new instance.
+	///
+	/// The `relay_chain` is used to get the `ValidationData` of the relay parent.
+	pub fn new(consensus: Arc<PC>, relay_chain: Arc<R>) -> Self {
+		Self {
+			consensus,
+			relay_chain,
+			select_parent: Arc::new(()),
+			timeout_tracker: None,
+			collation_cache: Arc::new(CollationCache::new()),
+			metrics: None,
+			authored_candidates: None,
+			_phantom: Default::default(),
+		}
+	}
+
+	/// Use `select_parent` to select the parent of new blocks, e.g. `Lookahead`.
+	///
+	/// By default, new blocks are built on the parachain head that is given by the relay chain.
+	pub fn with_select_parent(mut self, select_parent: Arc<dyn SelectParent<Block>>) -> Self {
+		self.select_parent = select_parent;
+		self
+	}
+
+	/// Build on the included parachain head again, if no candidate on top of it was included
+	/// within the timeout of `retry_policy`.
+	///
+	/// Only required with a `SelectParent` that builds on blocks that are not included yet.
+	pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+		self.timeout_tracker = Some(Arc::new(TimeoutTracker::new(retry_policy)));
+		self
+	}
+
+	/// Record the `metrics` of every produced block.
+	pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+		self.metrics = Some(metrics);
+		self
+	}
+
+	/// Note every produced block in `authored_candidates`, e.g. to serve them over RPC.
+	pub fn with_authored_candidates(
+		mut self,
+		authored_candidates: Arc<AuthoredCandidates<Block::Hash, NumberFor<Block>>>,
+	) -> Self {
+		self.authored_candidates = Some(authored_candidates);
+		self
+	}
+
+	/// Produce a new parachain block on top of the given parachain head, or the descendant of
+	/// it that is selected by the `SelectParent`, and return it as collation for `relay_parent`.
+	///
+	/// The descendants are ignored if the candidates on top of the head timed out, see
+	/// `with_retry_policy`.
+	///
+	/// Only one collation is produced for the same relay parent and parachain head, it is
+	/// returned again if it is requested again. Otherwise two different collations would be
+	/// signed with the collator key, which is an equivocation.
+	///
+	/// The `downward_messages` are the messages that are passed to the parachain by the relay
+	/// chain. The horizontal messages of the block are returned as `OutgoingMessages`, if the
+	/// `ParachainConsensus` collected the `CollationInfo` of the block.
+	pub fn collate(
+		&self,
+		relay_parent: PHash,
+		parent_head: &HeadData,
+		downward_messages: &[DownwardMessage],
+	) -> Result<Collation, InvalidHead> {
+		let included = <Block::Header as Decode>::decode(&mut &parent_head.0[..])
+			.ok_or_else(|| {
+				error!("Could not decode the parachain head given by the relay chain");
+				InvalidHead
+			})?;
+
+		if let Some(collation) = self.collation_cache.get(&relay_parent, &included.hash()) {
+			debug!(
+				"Returning the collation that was already produced for relay parent {}",
+				relay_parent,
+			);
+			return Ok(collation)
+		}
+
+		let validation_data = match self.relay_chain.validation_data(relay_parent) {
+			Ok(Some(data)) => data,
+			Ok(None) => {
+				error!("Unknown relay parent {}", relay_parent);
+				return Err(InvalidHead)
+			},
+			Err(e) => {
+				error!("Could not get the relay parent {}: {}", relay_parent, e);
+				return Err(InvalidHead)
+			},
+		};
+
+		let timed_out = self.timeout_tracker.as_ref().map_or(false, |tracker| {
+			tracker.timed_out(included.hash(), validation_data.relay_parent_number)
+		});
+		let parent = if timed_out {
+			included.clone()
+		} else {
+			self.select_parent.select_parent(&included)
+		};
+
+		let timer = self.metrics.as_ref().map(Metrics::start_block_build);
+		let candidate = self.consensus
+			.produce_candidate(&parent, relay_parent, &validation_data, downward_messages)
+			.ok_or(InvalidHead)?;
+		drop(timer);
+
+		info!(
+			"Produced parachain block #{} ({}) on relay parent #{} ({})",
+			candidate.block.header().number(),
+			candidate.block.header().hash(),
+			validation_data.relay_parent_number,
+			relay_parent,
+		);
+
+		let head_data = HeadData(candidate.block.header().encode());
+		let block_data = BlockData(candidate.block_data.encode_versioned());
+		let outgoing_messages = outgoing_messages(candidate.collation_info.as_ref());
+
+		if let Some(ref metrics) = self.metrics {
+			let witness_size = candidate.block_data.witness_data().encode().len();
+			metrics.observe_block_sizes(witness_size, block_data.0.len());
+		}
+
+		if let Some(ref authored_candidates) = self.authored_candidates {
+			authored_candidates.note(AuthoredCandidate {
+				hash: candidate.block.header().hash(),
+				number: *candidate.block.header().number(),
+				relay_parent,
+				relay_parent_number: validation_data.relay_parent_number,
+				pov_size: block_data.0.len(),
+			});
+		}
+
+		let collation = (block_data, head_data, outgoing_messages);
+		self.collation_cache.insert(relay_parent, included.hash(), collation.clone());
+
+		Ok(collation)
+	}
+}
+
+impl<Block, PC, R> ParachainContext for Collator<Block, PC, R> where
+	Block: BlockT,
+	PC: ParachainConsensus<Block>,
+	R: RelayChainInterface,
+{
+	type ProduceCandidate = Result<(BlockData, HeadData, OutgoingMessages), InvalidHead>;
+
+	fn produce_candidate<I: IntoIterator<Item=(ParaId, Message)>>(
+		&self,
+		relay_parent: PHash,
+		status: ParachainStatus,
+		ingress: I,
+	) -> Self::ProduceCandidate {
+		let downward_messages = ingress.into_iter()
+			.map(|(source, message)| DownwardMessage { source: source.into(), data: message.0 })
+			.collect::<Vec<_>>();
+
+		self.collate(relay_parent, &status.head_data, &downward_messages)
+	}
+}
+
+impl<Block, PC, R> BuildParachainContext for Collator<Block, PC, R> where
+	Collator<Block, PC, R>: ParachainContext,
+{
+	type ParachainContext = Self;
+
+	fn build(self, _network: Arc<dyn Network>) -> Result<Self::ParachainContext, ()> {
+		Ok(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cumulus_runtime::OutboundHrmpMessage;
+
+	fn collation_info(horizontal_messages: Vec<OutboundHrmpMessage>) -> CollationInfo {
+		CollationInfo {
+			head_data: Vec::new(),
+			upward_messages: Vec::new(),
+			horizontal_messages,
+			processed_downward_messages: 0,
+			new_validation_code: None,
+			hrmp_watermark: None,
+		}
+	}
+
+	fn hrmp_message(recipient: u32, data: &[u8]) -> OutboundHrmpMessage {
+		OutboundHrmpMessage { recipient: recipient.into(), data: data.to_vec() }
+	}
+
+	fn targeted_message(target: u32, data: &[u8]) -> TargetedMessage {
+		TargetedMessage { target: target.into(), data: data.to_vec() }
+	}
+
+	#[test]
+	fn outgoing_messages_are_sorted_by_recipient() {
+		let info = collation_info(vec![
+			hrmp_message(300, b"a"),
+			hrmp_message(100, b"b"),
+			hrmp_message(300, b"c"),
+			hrmp_message(200, b"d"),
+		]);
+
+		assert_eq!(
+			outgoing_messages(Some(&info)).outgoing_messages,
+			vec![
+				targeted_message(100, b"b"),
+				targeted_message(200, b"d"),
+				targeted_message(300, b"a"),
+				targeted_message(300, b"c"),
+			],
+		);
+	}
+
+	#[test]
+	fn no_outgoing_messages_without_collation_info() {
+		assert!(outgoing_messages(None).outgoing_messages.is_empty());
+	}
+
+	fn collation_info_v1() -> CollationInfoV1 {
+		CollationInfoV1 {
+			upward_messages: vec![b"up".to_vec()],
+			horizontal_messages: vec![hrmp_message(100, b"h")],
+			processed_downward_messages: 2,
+			new_validation_code: Some(b"code".to_vec()),
+		}
+	}
+
+	#[test]
+	fn collect_with_version_2_calls_the_latest_api() {
+		let mut expected = collation_info(vec![hrmp_message(100, b"h")]);
+		expected.head_data = b"head".to_vec();
+		expected.hrmp_watermark = Some(5);
+
+		let info = collect_with_version::<()>(
+			Some(2),
+			b"other head".to_vec(),
+			|| Ok(expected.clone()),
+			|| panic!("Version 1 must not be called"),
+		);
+		assert_eq!(info, Some(Ok(expected)));
+	}
+
+	#[test]
+	fn collect_with_version_1_converts_the_legacy_info() {
+		let info = collect_with_version::<()>(
+			Some(1),
+			b"head".to_vec(),
+			|| panic!("Version 2 must not be called"),
+			|| Ok(collation_info_v1()),
+		);
+
+		assert_eq!(
+			info,
+			Some(Ok(CollationInfo {
+				head_data: b"head".to_vec(),
+				upward_messages: vec![b"up".to_vec()],
+				horizontal_messages: vec![hrmp_message(100, b"h")],
+				processed_downward_messages: 2,
+				new_validation_code: Some(b"code".to_vec()),
+				hrmp_watermark: None,
+			})),
+		);
+	}
+
+	#[test]
+	fn collect_with_version_passes_errors() {
+		let info = collect_with_version(
+			Some(1),
+			Vec::new(),
+			|| panic!("Version 2 must not be called"),
+			|| Err("failed"),
+		);
+		assert_eq!(info, Some(Err("failed")));
+	}
+
+	#[test]
+	fn collect_without_api_returns_none() {
+		let info = collect_with_version::<()>(
+			None,
+			Vec::new(),
+			|| panic!("Version 2 must not be called"),
+			|| panic!("Version 1 must not be called"),
+		);
+		assert!(info.is_none());
+	}
+}
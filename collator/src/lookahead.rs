@@ -0,0 +1,137 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Select the parent of a new parachain block with lookahead.
+//!
+//! Without lookahead every block is built on the parachain head that is included in the relay
+//! chain. With lookahead the collator builds on the best local descendant of the included head,
+//! as long as the new block stays within the unincluded segment that the runtime accepts, see
+//! `ConsensusHook::unincluded_segment_capacity` of the parachain-system module.
+
+use substrate_client::{Client, CallExecutor, backend::Backend, error::Result as ClientResult};
+use substrate_primitives::Blake2Hasher;
+use sr_primitives::{generic::BlockId, traits::{Block as BlockT, Header as HeaderT}};
+
+use log::{warn, debug};
+
+use std::sync::Arc;
+
+/// The async backing parameters of the relay chain.
+///
+/// The relay chain runtime does not provide them, they need to be configured to match the
+/// relay chain and the `ConsensusHook` of the parachain runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AsyncBackingParams {
+	/// The maximum number of blocks that are not included in the relay chain yet, including
+	/// the new block.
+	pub max_candidate_depth: u32,
+}
+
+impl Default for AsyncBackingParams {
+	fn default() -> Self {
+		Self { max_candidate_depth: 1 }
+	}
+}
+
+/// Selects the parent of a new parachain block.
+pub trait SelectParent<Block: BlockT>: Send + Sync {
+	/// Returns the parent of the new block, given the parachain head that is `included` in the
+	/// relay chain.
+	fn select_parent(&self, included: &Block::Header) -> Block::Header;
+}
+
+/// Always builds on the included head.
+impl<Block: BlockT> SelectParent<Block> for () {
+	fn select_parent(&self, included: &Block::Header) -> Block::Header {
+		included.clone()
+	}
+}
+
+/// Builds on the deepest descendant of the included head on the best chain of the local client
+/// that leaves room for the new block in the unincluded segment.
+pub struct Lookahead<B, E, Block: BlockT, RA> {
+	client: Arc<Client<B, E, Block, RA>>,
+	params: AsyncBackingParams,
+}
+
+impl<B, E, Block: BlockT, RA> Lookahead<B, E, Block, RA> {
+	/// Create a new instance.
+	pub fn new(client: Arc<Client<B, E, Block, RA>>, params: AsyncBackingParams) -> Self {
+		Self { client, params }
+	}
+}
+
+impl<B, E, Block, RA> Lookahead<B, E, Block, RA> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+	Block: BlockT,
+{
+	/// Returns the deepest block on the best chain that is a descendant of `included` and at
+	/// most `max_candidate_depth - 1` blocks above it.
+	///
+	/// Returns `None` if the best block is not a descendant of `included`.
+	fn deepest_descendant(
+		&self,
+		included: &Block::Header,
+	) -> ClientResult<Option<Block::Header>> {
+		let max_depth = self.params.max_candidate_depth.saturating_sub(1) as usize;
+		let included_hash = included.hash();
+		let mut hash = self.client.info().chain.best_hash;
+		// The last `max_depth` blocks on the way down from the best block to `included`.
+		let mut window = Vec::with_capacity(max_depth);
+
+		while hash != included_hash {
+			let header = match self.client.header(&BlockId::Hash(hash))? {
+				Some(header) => header,
+				None => return Ok(None),
+			};
+			if header.number() <= included.number() {
+				return Ok(None)
+			}
+
+			hash = *header.parent_hash();
+			if max_depth > 0 {
+				if window.len() == max_depth {
+					window.remove(0);
+				}
+				window.push(header);
+			}
+		}
+
+		Ok(Some(window.into_iter().next().unwrap_or_else(|| included.clone())))
+	}
+}
+
+impl<B, E, Block, RA> SelectParent<Block> for Lookahead<B, E, Block, RA> where
+	B: Backend<Block, Blake2Hasher> + Send + Sync,
+	E: CallExecutor<Block, Blake2Hasher> + Send + Sync,
+	Block: BlockT,
+	RA: Send + Sync,
+{
+	fn select_parent(&self, included: &Block::Header) -> Block::Header {
+		match self.deepest_descendant(included) {
+			Ok(Some(parent)) => parent,
+			Ok(None) => {
+				debug!("The best block is not a descendant of {}", included.hash());
+				included.clone()
+			},
+			Err(e) => {
+				warn!("Could not search the descendants of {}: {:?}", included.hash(), e);
+				included.clone()
+			},
+		}
+	}
+}
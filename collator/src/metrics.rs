@@ -0,0 +1,71 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Prometheus metrics of the collator.
+
+use prometheus::{Histogram, HistogramOpts, HistogramTimer, Registry, Error as PrometheusError};
+
+/// The buckets of the size histograms, from 1 KiB to 16 MiB.
+const SIZE_BUCKETS: [f64; 8] = [
+	1024.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 8388608.0, 16777216.0,
+];
+
+/// The Prometheus metrics of the `Collator`.
+#[derive(Clone)]
+pub struct Metrics {
+	block_build_time: Histogram,
+	witness_size: Histogram,
+	pov_size: Histogram,
+}
+
+impl Metrics {
+	/// Create the metrics and register them in the given `registry`.
+	///
+	/// All metrics are prefixed with `cumulus_`.
+	pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		let metrics = Self {
+			block_build_time: Histogram::with_opts(HistogramOpts::new(
+				"cumulus_block_build_time",
+				"Time in seconds to build a parachain block",
+			))?,
+			witness_size: Histogram::with_opts(HistogramOpts::new(
+				"cumulus_witness_size",
+				"Size in bytes of the witness data of a parachain block",
+			).buckets(SIZE_BUCKETS.to_vec()))?,
+			pov_size: Histogram::with_opts(HistogramOpts::new(
+				"cumulus_pov_size",
+				"Size in bytes of the PoV of a parachain block",
+			).buckets(SIZE_BUCKETS.to_vec()))?,
+		};
+
+		registry.register(Box::new(metrics.block_build_time.clone()))?;
+		registry.register(Box::new(metrics.witness_size.clone()))?;
+		registry.register(Box::new(metrics.pov_size.clone()))?;
+
+		Ok(metrics)
+	}
+
+	/// Start measuring the time to build a block, the time is recorded when the timer is dropped.
+	pub(crate) fn start_block_build(&self) -> HistogramTimer {
+		self.block_build_time.start_timer()
+	}
+
+	/// Record the sizes of a produced block.
+	pub(crate) fn observe_block_sizes(&self, witness_size: usize, pov_size: usize) {
+		self.witness_size.observe(witness_size as f64);
+		self.pov_size.observe(pov_size as f64);
+	}
+}
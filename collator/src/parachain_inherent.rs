@@ -0,0 +1,79 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Provides the `ParachainInherentData` of a relay parent for block authorship.
+
+use polkadot_primitives::{Hash as PHash, parachain::Id as ParaId};
+use cumulus_runtime::{
+	ParachainInherentData, PARACHAIN_INHERENT_IDENTIFIER, VALIDATION_DATA_IDENTIFIER,
+	relay_chain_keys,
+};
+use cumulus_relay_chain_interface::{RelayChainInterface, RelayChainResult};
+use substrate_inherents::InherentData;
+
+use std::sync::Arc;
+
+/// Provides the `ParachainInherentData` of a relay parent.
+pub trait ProvideParachainInherentData: Send + Sync {
+	/// Put the `ParachainInherentData` and the `ValidationData` of the given relay parent into
+	/// `inherent_data`.
+	fn provide_inherent_data(
+		&self,
+		relay_parent: PHash,
+		inherent_data: &mut InherentData,
+	) -> Result<(), String>;
+}
+
+/// Fetches the `ParachainInherentData` of a relay parent from the relay chain.
+pub struct ParachainInherentDataProvider<R> {
+	relay_chain: Arc<R>,
+	para_id: ParaId,
+}
+
+impl<R: RelayChainInterface> ParachainInherentDataProvider<R> {
+	/// Create a new instance for the given parachain.
+	pub fn new(relay_chain: Arc<R>, para_id: ParaId) -> Self {
+		Self { relay_chain, para_id }
+	}
+
+	/// Fetch the `ParachainInherentData` of the given relay parent.
+	pub fn create_at(&self, relay_parent: PHash) -> RelayChainResult<ParachainInherentData> {
+		let keys = relay_chain_keys::all(self.para_id.into());
+		let relay_chain_state = self.relay_chain.prove_read(relay_parent, &keys)?;
+
+		Ok(ParachainInherentData { relay_chain_state })
+	}
+}
+
+impl<R: RelayChainInterface> ProvideParachainInherentData for ParachainInherentDataProvider<R> {
+	fn provide_inherent_data(
+		&self,
+		relay_parent: PHash,
+		inherent_data: &mut InherentData,
+	) -> Result<(), String> {
+		let data = self.create_at(relay_parent)
+			.map_err(|e| format!("Could not create the parachain inherent data: {}", e))?;
+
+		inherent_data.put_data(PARACHAIN_INHERENT_IDENTIFIER, &data)
+			.map_err(|e| format!("Could not put the parachain inherent data: {:?}", e))?;
+
+		let validation_data = self.relay_chain.validation_data(relay_parent)
+			.map_err(|e| format!("Could not get the validation data: {}", e))?
+			.ok_or_else(|| format!("Unknown relay parent {}", relay_parent))?;
+		inherent_data.put_data(VALIDATION_DATA_IDENTIFIER, &validation_data)
+			.map_err(|e| format!("Could not put the validation data: {:?}", e))
+	}
+}
@@ -0,0 +1,83 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `purge-chain` subcommand of a collator that runs an embedded relay chain node.
+
+use structopt::StructOpt;
+
+use std::{fs, io::{self, Write}, path::Path};
+
+/// The `purge-chain` subcommand of a collator.
+///
+/// Removes the database of the parachain and the database of the embedded relay chain node.
+#[derive(Debug, Clone, StructOpt)]
+pub struct PurgeChainCmd {
+	/// Skip the interactive confirmation.
+	#[structopt(short = "y")]
+	pub yes: bool,
+
+	/// Keep the database of the parachain.
+	#[structopt(long = "keep-para")]
+	pub keep_para: bool,
+
+	/// Keep the database of the embedded relay chain node.
+	#[structopt(long = "keep-relay")]
+	pub keep_relay: bool,
+}
+
+impl PurgeChainCmd {
+	/// Remove the parachain database at `para_db` and the relay chain database at `relay_db`.
+	///
+	/// Prints every database that was removed. Databases that do not exist are skipped.
+	pub fn run(&self, para_db: &Path, relay_db: &Path) -> io::Result<()> {
+		let mut databases = Vec::new();
+		if !self.keep_para {
+			databases.push(("parachain", para_db));
+		}
+		if !self.keep_relay {
+			databases.push(("relay chain", relay_db));
+		}
+
+		for (name, path) in databases {
+			if !path.exists() {
+				println!("The {} database {} does not exist", name, path.display());
+				continue
+			}
+
+			let question = format!("Remove the {} database {}?", name, path.display());
+			if !self.yes && !confirm(&question)? {
+				println!("Aborted");
+				return Ok(())
+			}
+
+			fs::remove_dir_all(path)?;
+			println!("Removed the {} database {}", name, path.display());
+		}
+
+		Ok(())
+	}
+}
+
+/// Ask the user to confirm the given `question`, the default is no.
+fn confirm(question: &str) -> io::Result<bool> {
+	print!("{} [y/N]: ", question);
+	io::stdout().flush()?;
+
+	let mut input = String::new();
+	io::stdin().read_line(&mut input)?;
+
+	Ok(input.trim().eq_ignore_ascii_case("y"))
+}
@@ -0,0 +1,130 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reads the data that is required to register the parachain at the relay chain.
+//!
+//! The data is read from the genesis block of a client, or computed from the genesis storage,
+//! e.g. of a chain spec, without a client.
+
+use substrate_client::{
+	Client, CallExecutor, backend::Backend,
+	error::{Error as ClientError, Result as ClientResult},
+};
+use substrate_primitives::{Blake2Hasher, storage::{StorageKey, well_known_keys}};
+use sr_primitives::{
+	BuildStorage, generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, Hash as HashT, Zero},
+};
+use polkadot_primitives::parachain::{Id as ParaId, HeadData};
+
+use parity_codec::Encode;
+
+/// The data the relay chain requires to register a parachain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParachainRegistration {
+	/// The id of the parachain.
+	pub id: ParaId,
+	/// The head data of the genesis block, the encoded genesis header.
+	pub genesis_head: HeadData,
+	/// The validation code of the genesis block, the wasm runtime of the parachain.
+	pub validation_code: Vec<u8>,
+}
+
+/// Returns the head data of the genesis block of `client`.
+pub fn genesis_head_data<B, E, Block, RA>(
+	client: &Client<B, E, Block, RA>,
+) -> ClientResult<HeadData> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+	Block: BlockT,
+{
+	let genesis = BlockId::Number(Zero::zero());
+	let header = client.header(&genesis)?
+		.ok_or_else(|| ClientError::UnknownBlock(format!("{}", genesis)))?;
+
+	Ok(HeadData(header.encode()))
+}
+
+/// Returns the validation code of the genesis block of `client`.
+pub fn genesis_validation_code<B, E, Block, RA>(
+	client: &Client<B, E, Block, RA>,
+) -> ClientResult<Vec<u8>> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+	Block: BlockT,
+{
+	let genesis = BlockId::Number(Zero::zero());
+	client.storage(&genesis, &StorageKey(well_known_keys::CODE.to_vec()))?
+		.map(|code| code.0)
+		.ok_or_else(|| ClientError::Msg("The genesis block has no validation code".into()))
+}
+
+/// Returns the data that is required to register the parachain `id` with the genesis block of
+/// `client`.
+pub fn genesis_registration<B, E, Block, RA>(
+	client: &Client<B, E, Block, RA>,
+	id: ParaId,
+) -> ClientResult<ParachainRegistration> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+	Block: BlockT,
+{
+	Ok(ParachainRegistration {
+		id,
+		genesis_head: genesis_head_data(client)?,
+		validation_code: genesis_validation_code(client)?,
+	})
+}
+
+/// Returns the head data and the validation code of the genesis block that is built from the
+/// given genesis `storage`, e.g. a chain spec.
+///
+/// The genesis block is built like the client builds it, but no client or database is
+/// required.
+pub fn genesis_from_storage<Block, S>(storage: S) -> Result<(HeadData, Vec<u8>), String> where
+	Block: BlockT,
+	S: BuildStorage,
+{
+	let (mut top, children) = storage.build_storage()?;
+	let validation_code = top.get(well_known_keys::CODE)
+		.cloned()
+		.ok_or_else(|| String::from("The genesis storage has no validation code"))?;
+
+	for (child_key, child) in children {
+		if !child.is_empty() {
+			let root = <<Block::Header as HeaderT>::Hashing as HashT>::trie_root(child);
+			top.insert(child_key, root.encode());
+		}
+	}
+
+	let state_root = <<Block::Header as HeaderT>::Hashing as HashT>::trie_root(top);
+	let genesis: Block = substrate_client::genesis::construct_genesis_block(state_root);
+
+	Ok((HeadData(genesis.header().encode()), validation_code))
+}
+
+/// Returns the data that is required to register the parachain `id` with the genesis block
+/// that is built from the given genesis `storage`, see `genesis_from_storage`.
+pub fn genesis_registration_from_storage<Block, S>(
+	storage: S,
+	id: ParaId,
+) -> Result<ParachainRegistration, String> where
+	Block: BlockT,
+	S: BuildStorage,
+{
+	let (genesis_head, validation_code) = genesis_from_storage::<Block, _>(storage)?;
+	Ok(ParachainRegistration { id, genesis_head, validation_code })
+}
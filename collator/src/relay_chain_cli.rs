@@ -0,0 +1,74 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Command line parsing for a collator that runs an embedded relay chain node.
+//!
+//! The arguments before `--` are the arguments of the parachain node, the arguments after it
+//! are the arguments of the embedded relay chain node, e.g.
+//! `collator --collator -d /data -- --bootnodes <addr>`.
+
+use structopt::{StructOpt, clap};
+
+use std::path::{Path, PathBuf};
+
+/// The name of the directory of the relay chain node in the base path of the parachain node.
+const RELAY_CHAIN_DIR: &str = "polkadot";
+
+/// Split the command line `args` at the first `--`.
+///
+/// Returns the arguments of the parachain node, which include the binary name, and the
+/// arguments of the embedded relay chain node. The relay chain arguments are empty if there
+/// is no `--`.
+pub fn split_args<I: IntoIterator<Item = String>>(args: I) -> (Vec<String>, Vec<String>) {
+	let mut args = args.into_iter();
+	let parachain_args = args.by_ref().take_while(|arg| arg != "--").collect();
+
+	(parachain_args, args.collect())
+}
+
+/// The command line parameters of the embedded relay chain node.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct RelayChainCli {
+	/// The base path of the relay chain node.
+	///
+	/// Defaults to `polkadot` in the base path of the parachain node.
+	#[structopt(long = "base-path", short = "d", parse(from_os_str))]
+	pub base_path: Option<PathBuf>,
+
+	/// The boot nodes of the relay chain network.
+	#[structopt(long = "bootnodes")]
+	pub bootnodes: Vec<String>,
+
+	/// The number of relay chain blocks to keep the state of, or `archive` to keep all.
+	#[structopt(long = "pruning")]
+	pub pruning: Option<String>,
+}
+
+impl RelayChainCli {
+	/// Parse the arguments of the relay chain node, as returned by `split_args`.
+	///
+	/// `binary` is used as the name of the relay chain node in the help and error messages.
+	pub fn from_relay_args(binary: &str, args: Vec<String>) -> Result<Self, clap::Error> {
+		Self::from_iter_safe(Some(binary.to_string()).into_iter().chain(args))
+	}
+
+	/// Returns the base path of the relay chain node.
+	///
+	/// Uses `polkadot` in `parachain_base_path` if no base path is given.
+	pub fn base_path(&self, parachain_base_path: &Path) -> PathBuf {
+		self.base_path.clone().unwrap_or_else(|| parachain_base_path.join(RELAY_CHAIN_DIR))
+	}
+}
@@ -0,0 +1,341 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A `ParachainConsensus` that builds a block whenever it is asked for one.
+//!
+//! There is no author selection, every collator builds a block for every relay parent and the
+//! relay chain decides which one is included. This is meant for testnets and chains that
+//! restrict their collators by other means, e.g. sudo.
+
+use crate::{ParachainConsensus, ParachainCandidate, ProvideParachainInherentData};
+use substrate_client::{
+	Client, CallExecutor, backend::Backend, block_builder::api::BlockBuilder as BlockBuilderApi,
+	runtime_api::ApiExt,
+};
+use substrate_primitives::{Blake2Hasher, H256};
+use sr_primitives::generic::BlockId;
+use sr_primitives::traits::{
+	Block as BlockT, Header as HeaderT, ProvideRuntimeApi, DigestFor, DigestItemFor,
+};
+use substrate_consensus_common::{
+	BlockImport, BlockOrigin, ImportBlock, ImportResult, ForkChoiceStrategy,
+};
+use polkadot_primitives::Hash as PHash;
+use cumulus_runtime::{
+	ValidationData, ProvidePolkadotInherent, DownwardMessage, CollationInfo, CollectCollationInfo,
+	validate_block::DEFAULT_MAX_POV_SIZE,
+};
+use cumulus_client_proof_recorder::{ProofRecorder, RecordedBlock};
+use substrate_inherents::InherentDataProviders;
+
+use parity_codec::Encode;
+use log::{error, warn, debug};
+
+use std::{sync::Arc, marker::PhantomData};
+
+/// Provides the extrinsics that should be included in a new block on top of the given parent.
+pub type ProvideExtrinsics<Block> =
+	Arc<dyn Fn(&BlockId<Block>) -> Vec<<Block as BlockT>::Extrinsic> + Send + Sync>;
+
+/// The part of the maximum PoV size that the transactions of a block can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoVBudget {
+	/// The maximum PoV size in bytes that the relay chain accepts.
+	pub max_pov_size: usize,
+	/// The percentage of `max_pov_size` that the encoded transactions can use.
+	///
+	/// The rest is left to the inherents and the witness data.
+	pub transactions_percent: u8,
+}
+
+impl PoVBudget {
+	/// Returns the number of bytes the encoded transactions can use.
+	fn transactions_size(&self) -> usize {
+		self.max_pov_size / 100 * self.transactions_percent.min(100) as usize
+	}
+}
+
+impl Default for PoVBudget {
+	fn default() -> Self {
+		Self { max_pov_size: DEFAULT_MAX_POV_SIZE, transactions_percent: 75 }
+	}
+}
+
+/// Builds a new parachain block whenever the collator asks for one.
+///
+/// The first extrinsic of every block is the `PolkadotInherent` that is created by `I` from
+/// the `ValidationData` of the relay parent. It is followed by the inherents of the runtime,
+/// which are created from the inherent data of the `InherentDataProviders` and the
+/// `ParachainInherentData` of the relay parent.
+///
+/// Every block is imported into the client after it was built, so its `CollationInfo` can be
+/// collected. The runtime must implement the `CollectCollationInfo` runtime API.
+pub struct RelayChainConsensus<Block: BlockT, B, E, RA, I> {
+	proof_recorder: Arc<ProofRecorder<B, E, Block, RA>>,
+	provide_extrinsics: ProvideExtrinsics<Block>,
+	inherent_data_providers: InherentDataProviders,
+	parachain_inherent_data: Option<Arc<dyn ProvideParachainInherentData>>,
+	pov_budget: PoVBudget,
+	_phantom: PhantomData<fn() -> I>,
+}
+
+impl<Block, B, E, RA, I> RelayChainConsensus<Block, B, E, RA, I> where Block: BlockT {
+	/// Create a new instance.
+	pub fn new(
+		proof_recorder: Arc<ProofRecorder<B, E, Block, RA>>,
+		provide_extrinsics: ProvideExtrinsics<Block>,
+	) -> Self {
+		Self {
+			proof_recorder,
+			provide_extrinsics,
+			inherent_data_providers: InherentDataProviders::new(),
+			parachain_inherent_data: None,
+			pov_budget: Default::default(),
+			_phantom: PhantomData,
+		}
+	}
+
+	/// Use the given `InherentDataProviders` to create the inherents of the runtime.
+	pub fn with_inherent_data_providers(mut self, providers: InherentDataProviders) -> Self {
+		self.inherent_data_providers = providers;
+		self
+	}
+
+	/// Put the `ParachainInherentData` of the relay parent into the inherent data.
+	pub fn with_parachain_inherent_data(
+		mut self,
+		provider: Arc<dyn ProvideParachainInherentData>,
+	) -> Self {
+		self.parachain_inherent_data = Some(provider);
+		self
+	}
+
+	/// Use the given `PoVBudget` when building blocks.
+	pub fn with_pov_budget(mut self, pov_budget: PoVBudget) -> Self {
+		self.pov_budget = pov_budget;
+		self
+	}
+}
+
+impl<Block, B, E, RA, I> RelayChainConsensus<Block, B, E, RA, I> where
+	I: ProvidePolkadotInherent<Block>,
+	B: Backend<Block, Blake2Hasher> + Send + Sync,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
+{
+	/// Build a new block on top of `parent` for `relay_parent` with the given
+	/// `inherent_digests`.
+	///
+	/// The first extrinsics of the block are the inherents that are injected by the
+	/// validators, the `PolkadotInherent` and the downward messages inherent. They are
+	/// followed by the inherents of the runtime. Extrinsics that can not be pushed into the
+	/// block are skipped, as are all extrinsics once the transactions part of the `PoVBudget`
+	/// is used up. If the block still exceeds the maximum PoV size, it is built again without
+	/// extrinsics.
+	///
+	/// Returns the block and the number of inherents that are injected by the validators.
+	pub fn build_block(
+		&self,
+		parent: &Block::Header,
+		relay_parent: PHash,
+		validation_data: &ValidationData,
+		downward_messages: &[DownwardMessage],
+		inherent_digests: DigestFor<Block>,
+	) -> Option<(RecordedBlock<Block>, usize)> {
+		let parent_id = BlockId::hash(parent.hash());
+		let extrinsics = (self.provide_extrinsics)(&parent_id);
+
+		let (recorded, validator_inherents) = self.build_block_with_extrinsics(
+			parent,
+			relay_parent,
+			validation_data,
+			downward_messages,
+			inherent_digests.clone(),
+			extrinsics,
+		)?;
+
+		let pov_size = recorded.block.encode().len() + recorded.witness_data.encode().len();
+		if pov_size <= self.pov_budget.max_pov_size {
+			return Some((recorded, validator_inherents))
+		}
+
+		warn!(
+			"Parachain block with a PoV of {} bytes exceeds the maximum of {} bytes, \
+			building it again without extrinsics",
+			pov_size,
+			self.pov_budget.max_pov_size,
+		);
+		self.build_block_with_extrinsics(
+			parent,
+			relay_parent,
+			validation_data,
+			downward_messages,
+			inherent_digests,
+			Vec::new(),
+		)
+	}
+
+	/// Build a new block on top of `parent` with the given `extrinsics`, see `build_block`.
+	fn build_block_with_extrinsics(
+		&self,
+		parent: &Block::Header,
+		relay_parent: PHash,
+		validation_data: &ValidationData,
+		downward_messages: &[DownwardMessage],
+		inherent_digests: DigestFor<Block>,
+		extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+	) -> Option<(RecordedBlock<Block>, usize)> {
+		let parent = BlockId::hash(parent.hash());
+
+		let mut inherent_data = self.inherent_data_providers.create_inherent_data()
+			.map_err(|e| error!("Could not create the inherent data: {:?}", e))
+			.ok()?;
+		if let Some(ref provider) = self.parachain_inherent_data {
+			provider.provide_inherent_data(relay_parent, &mut inherent_data)
+				.map_err(|e| error!("{}", e))
+				.ok()?;
+		}
+
+		let inherents = self.proof_recorder.client()
+			.runtime_api()
+			.inherent_extrinsics(&parent, inherent_data)
+			.map_err(|e| error!("Could not create the inherent extrinsics: {:?}", e))
+			.ok()?;
+
+		let mut validator_inherents = vec![I::create_inherent(validation_data)];
+		validator_inherents.extend(I::create_downward_messages_inherent(downward_messages));
+		let validator_inherents_count = validator_inherents.len();
+
+		let transactions_size = self.pov_budget.transactions_size();
+		self.proof_recorder.build(&parent, inherent_digests, |builder| {
+			for inherent in validator_inherents {
+				builder.push(inherent)?;
+			}
+
+			for inherent in inherents {
+				builder.push(inherent)?;
+			}
+
+			let mut used_size = 0;
+			for extrinsic in extrinsics {
+				let size = extrinsic.encode().len();
+				if used_size + size > transactions_size {
+					debug!("The transactions of the block reached the PoV budget");
+					break
+				}
+
+				match builder.push(extrinsic) {
+					Ok(()) => used_size += size,
+					Err(e) => warn!("Skipping extrinsic that could not be pushed: {:?}", e),
+				}
+			}
+			Ok(())
+		})
+		.map(|recorded| (recorded, validator_inherents_count))
+		.map_err(|e| error!("Could not build the parachain block: {:?}", e))
+		.ok()
+	}
+}
+
+impl<Block, B, E, RA, I> RelayChainConsensus<Block, B, E, RA, I> where
+	B: Backend<Block, Blake2Hasher> + Send + Sync,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api:
+		CollectCollationInfo<Block> + ApiExt<Block>,
+{
+	/// Import the block with the given `header` and `body` and return its `CollationInfo`.
+	///
+	/// The outputs of a block are collected on its state, which is only available once the
+	/// block is imported. The block does not become the best block, this is left to the relay
+	/// chain, see `cumulus_consensus`. The `post_digests` are added to the header after the
+	/// block was built, e.g. a seal.
+	///
+	/// Returns `None` if the block could not be imported or the outputs could not be collected.
+	pub fn import_and_collect(
+		&self,
+		header: Block::Header,
+		body: Vec<<Block as BlockT>::Extrinsic>,
+		post_digests: Vec<DigestItemFor<Block>>,
+	) -> Option<CollationInfo> {
+		let import_block = ImportBlock {
+			origin: BlockOrigin::Own,
+			header,
+			justification: None,
+			post_digests,
+			body: Some(body),
+			finalized: false,
+			auxiliary: Vec::new(),
+			fork_choice: ForkChoiceStrategy::Custom(false),
+		};
+		let post_header = import_block.post_header().into_owned();
+
+		let client = self.proof_recorder.client();
+		match client.import_block(import_block, None) {
+			Ok(ImportResult::Imported(_)) | Ok(ImportResult::AlreadyInChain) => {},
+			Ok(res) => {
+				error!("Could not import the parachain block {}: {:?}", post_header.hash(), res);
+				return None
+			},
+			Err(e) => {
+				error!("Could not import the parachain block {}: {:?}", post_header.hash(), e);
+				return None
+			},
+		}
+
+		crate::collation_info::<Block, _>(&**client, &post_header)
+	}
+}
+
+impl<Block, B, E, RA, I> ParachainConsensus<Block> for RelayChainConsensus<Block, B, E, RA, I> where
+	I: ProvidePolkadotInherent<Block>,
+	B: Backend<Block, Blake2Hasher> + Send + Sync,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api:
+		BlockBuilderApi<Block> + CollectCollationInfo<Block> + ApiExt<Block>,
+{
+	fn produce_candidate(
+		&self,
+		parent: &Block::Header,
+		relay_parent: PHash,
+		validation_data: &ValidationData,
+		downward_messages: &[DownwardMessage],
+	) -> Option<ParachainCandidate<Block>> {
+		debug!("Building parachain block on {} for relay parent {}", parent.hash(), relay_parent);
+
+		let (recorded, validator_inherents) = self.build_block(
+			parent,
+			relay_parent,
+			validation_data,
+			downward_messages,
+			Default::default(),
+		)?;
+		let block = recorded.block.clone();
+		let (header, extrinsics) = block.clone().deconstruct();
+		let collation_info = self.import_and_collect(header, extrinsics, Vec::new());
+		let block_data = recorded.into_parachain_block_data(validator_inherents);
+
+		Some(ParachainCandidate { block, block_data, collation_info })
+	}
+}
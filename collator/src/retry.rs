@@ -0,0 +1,102 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Re-proposal of candidates that are not included by the relay chain.
+//!
+//! With lookahead the collator builds on blocks that are not included yet. If one of these
+//! blocks is dropped by the relay chain, e.g. because no validator backed it, every block on
+//! top of it is dropped as well. `RetryPolicy` detects this from the included parachain head
+//! and lets the collator build on the included head again.
+
+use parking_lot::Mutex;
+use log::{warn, error};
+
+use std::fmt::Display;
+
+/// When the collator re-proposes a block on top of the included parachain head.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+	/// The number of relay chain blocks after which the candidates on top of the included head
+	/// are considered timed out, if the included head did not change.
+	pub timeout: u32,
+	/// The number of re-proposals on top of the same included head after which every further
+	/// re-proposal is logged as error, as the parachain is most likely stalled.
+	pub max_retries: u32,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self { timeout: 10, max_retries: 3 }
+	}
+}
+
+struct State<Hash> {
+	included: Hash,
+	/// The relay chain block number since which the included head did not change, or the
+	/// number of the last re-proposal.
+	since: u32,
+	retries: u32,
+}
+
+/// Tracks the included parachain head to detect timed out candidates.
+pub(crate) struct TimeoutTracker<Hash> {
+	policy: RetryPolicy,
+	state: Mutex<Option<State<Hash>>>,
+}
+
+impl<Hash: PartialEq + Display> TimeoutTracker<Hash> {
+	/// Create a new instance.
+	pub(crate) fn new(policy: RetryPolicy) -> Self {
+		Self { policy, state: Mutex::new(None) }
+	}
+
+	/// Returns `true` if the candidates on top of `included` timed out and the collator should
+	/// build on `included` for the relay parent with the given number.
+	pub(crate) fn timed_out(&self, included: Hash, relay_parent_number: u32) -> bool {
+		let mut state = self.state.lock();
+
+		let state = match *state {
+			Some(ref mut state) if state.included == included => state,
+			_ => {
+				*state = Some(State { included, since: relay_parent_number, retries: 0 });
+				return false
+			},
+		};
+
+		if relay_parent_number < state.since.saturating_add(self.policy.timeout) {
+			return false
+		}
+
+		state.since = relay_parent_number;
+		state.retries += 1;
+		if state.retries > self.policy.max_retries {
+			error!(
+				"The parachain head {} was not updated after {} re-proposals",
+				state.included,
+				state.retries - 1,
+			);
+		} else {
+			warn!(
+				"No candidate on top of {} was included within {} relay chain blocks, \
+				building on it again",
+				state.included,
+				self.policy.timeout,
+			);
+		}
+
+		true
+	}
+}
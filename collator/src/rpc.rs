@@ -0,0 +1,165 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `cumulus` RPC namespace of a parachain node.
+//!
+//! `cumulus_collationInfo` returns the most recent candidates that were authored by the
+//! collator, `cumulus_subscribeCandidates` sends every new one.
+
+use crate::authored_candidates::{AuthoredCandidates, AuthoredCandidate};
+use futures::{prelude::*, future::Executor};
+use jsonrpc_core::{Result as RpcResult, Error as RpcError};
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId, Session, PubSubMetadata};
+use parking_lot::Mutex;
+use serde::Serialize;
+use log::warn;
+
+use std::{collections::HashMap, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+
+/// The executor that drives the subscriptions.
+pub type TaskExecutor =
+	Arc<dyn Executor<Box<dyn Future<Item=(), Error=()> + Send>> + Send + Sync>;
+
+/// The RPC metadata, holds the session of a pub-sub connection.
+#[derive(Default, Clone)]
+pub struct Metadata {
+	session: Option<Arc<Session>>,
+}
+
+impl jsonrpc_core::Metadata for Metadata {}
+
+impl PubSubMetadata for Metadata {
+	fn session(&self) -> Option<Arc<Session>> {
+		self.session.clone()
+	}
+}
+
+impl From<Arc<Session>> for Metadata {
+	fn from(session: Arc<Session>) -> Self {
+		Metadata { session: Some(session) }
+	}
+}
+
+/// The `cumulus` RPC namespace.
+#[rpc]
+pub trait CumulusApi<Hash, Number> {
+	/// The RPC metadata.
+	type Metadata;
+
+	/// Returns the most recent candidates that were authored by the collator, the newest last.
+	#[rpc(name = "cumulus_collationInfo")]
+	fn collation_info(&self) -> RpcResult<Vec<AuthoredCandidate<Hash, Number>>>;
+
+	/// Subscribe to the candidates that are authored by the collator.
+	#[pubsub(
+		subscription = "cumulus_candidate",
+		subscribe,
+		name = "cumulus_subscribeCandidates"
+	)]
+	fn subscribe_candidates(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<AuthoredCandidate<Hash, Number>>,
+	);
+
+	/// Unsubscribe from the candidates that are authored by the collator.
+	#[pubsub(
+		subscription = "cumulus_candidate",
+		unsubscribe,
+		name = "cumulus_unsubscribeCandidates"
+	)]
+	fn unsubscribe_candidates(
+		&self,
+		metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> RpcResult<bool>;
+}
+
+/// The implementation of the `cumulus` RPC namespace.
+pub struct Cumulus<Hash, Number> {
+	candidates: Arc<AuthoredCandidates<Hash, Number>>,
+	executor: TaskExecutor,
+	next_id: AtomicU64,
+	/// Dropping the sender of a subscription ends it.
+	subscriptions: Mutex<HashMap<u64, futures::sync::oneshot::Sender<()>>>,
+}
+
+impl<Hash, Number> Cumulus<Hash, Number> {
+	/// Create a new instance that serves the given `candidates`.
+	///
+	/// The subscriptions are spawned on `executor`.
+	pub fn new(candidates: Arc<AuthoredCandidates<Hash, Number>>, executor: TaskExecutor) -> Self {
+		Self {
+			candidates,
+			executor,
+			next_id: AtomicU64::new(1),
+			subscriptions: Mutex::new(HashMap::new()),
+		}
+	}
+}
+
+impl<Hash, Number> CumulusApi<Hash, Number> for Cumulus<Hash, Number> where
+	Hash: Clone + Serialize + Send + Sync + 'static,
+	Number: Clone + Serialize + Send + Sync + 'static,
+{
+	type Metadata = Metadata;
+
+	fn collation_info(&self) -> RpcResult<Vec<AuthoredCandidate<Hash, Number>>> {
+		Ok(self.candidates.recent())
+	}
+
+	fn subscribe_candidates(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<AuthoredCandidate<Hash, Number>>,
+	) {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let sink = match subscriber.assign_id(SubscriptionId::Number(id)) {
+			Ok(sink) => sink,
+			Err(()) => return,
+		};
+
+		let (cancel, cancelled) = futures::sync::oneshot::channel();
+		let candidates = self.candidates.subscribe().map(Ok::<_, RpcError>);
+		let forward = sink
+			.sink_map_err(|e| warn!("Could not send a candidate to a subscriber: {:?}", e))
+			.send_all(candidates)
+			.map(|_| ())
+			.select(cancelled.then(|_| Ok::<_, ()>(())))
+			.map(|_| ())
+			.map_err(|_| ());
+
+		if self.executor.execute(Box::new(forward)).is_err() {
+			warn!("Could not spawn the candidate subscription {}", id);
+			return
+		}
+		self.subscriptions.lock().insert(id, cancel);
+	}
+
+	fn unsubscribe_candidates(
+		&self,
+		_metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> RpcResult<bool> {
+		let removed = match id {
+			SubscriptionId::Number(id) => self.subscriptions.lock().remove(&id).is_some(),
+			SubscriptionId::String(_) => false,
+		};
+
+		Ok(removed)
+	}
+}
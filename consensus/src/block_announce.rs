@@ -0,0 +1,159 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Validation of parachain block announcements.
+//!
+//! A collator that announces a new parachain block attaches the statement of a relay chain
+//! validator that seconded the block as candidate. Full nodes only accept announcements of
+//! blocks that are already included in the relay chain or that carry such a statement.
+
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT};
+use polkadot_primitives::Hash as PHash;
+use polkadot_primitives::parachain::Id as ParaId;
+use cumulus_relay_chain_interface::RelayChainInterface;
+use polkadot_statement_table::{SignedStatement, generic::Statement};
+use polkadot_validation::check_statement;
+
+use parity_codec::{Encode, Decode};
+use log::{warn, debug};
+
+use std::sync::Arc;
+
+/// The result of validating a block announcement.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Validation {
+	/// The announcement is valid.
+	Success,
+	/// The announcement is invalid and the block should not be requested.
+	Failure,
+}
+
+/// Validates the block announcements that are received from the network.
+pub trait BlockAnnounceValidator<B: BlockT> {
+	/// Validate the announcement of `header` with the `data` that is attached to it.
+	fn validate(&mut self, header: &B::Header, data: &[u8]) -> Validation;
+}
+
+/// The data that is attached to a parachain block announcement.
+#[derive(Encode, Decode)]
+pub struct BlockAnnounceData {
+	/// The relay parent the candidate was seconded at.
+	pub relay_parent: PHash,
+	/// The statement of the relay chain validator that seconded the candidate.
+	pub statement: SignedStatement,
+}
+
+/// A `BlockAnnounceValidator` that requires the announced block to be seconded on the relay
+/// chain.
+///
+/// Announcements of blocks that are older than the parachain head included in the best relay
+/// chain block are rejected as stale.
+pub struct ParachainBlockAnnounceValidator<Block, R> {
+	relay_chain: Arc<R>,
+	para_id: ParaId,
+	_phantom: std::marker::PhantomData<Block>,
+}
+
+impl<Block, R> ParachainBlockAnnounceValidator<Block, R> {
+	/// Create a new instance that validates the announcements of the given parachain.
+	pub fn new(relay_chain: Arc<R>, para_id: ParaId) -> Self {
+		Self { relay_chain, para_id, _phantom: Default::default() }
+	}
+}
+
+impl<Block, R> ParachainBlockAnnounceValidator<Block, R> where
+	Block: BlockT,
+	R: RelayChainInterface,
+{
+	/// Returns the parachain head that is included in the best relay chain block.
+	fn included_head(&self) -> Result<Option<Block::Header>, String> {
+		let head = self.relay_chain.best_block_hash()
+			.and_then(|best| self.relay_chain.parachain_head(best, self.para_id))
+			.map_err(|e| format!("Could not get the included parachain head: {}", e))?;
+
+		Ok(head.and_then(|h| Decode::decode(&mut &h[..])))
+	}
+
+	/// Check that `data` contains a valid statement seconding `header`.
+	fn check_seconded(&self, header: &Block::Header, data: &[u8]) -> Result<(), String> {
+		let data = BlockAnnounceData::decode(&mut &data[..])
+			.ok_or_else(|| String::from("Could not decode the block announce data"))?;
+
+		let receipt = match data.statement.statement {
+			Statement::Candidate(ref receipt) => receipt,
+			_ => return Err("The statement does not second a candidate".into()),
+		};
+
+		if receipt.parachain_index != self.para_id {
+			return Err(format!("The candidate is for parachain {:?}", receipt.parachain_index))
+		}
+
+		if receipt.head_data.0 != header.encode() {
+			return Err("The candidate is for a different block".into())
+		}
+
+		let validators = self.relay_chain.validators(data.relay_parent)
+			.map_err(|e| format!("Could not get the validators: {}", e))?;
+
+		if !validators.contains(&data.statement.sender) {
+			return Err(format!("{:?} is not a validator", data.statement.sender))
+		}
+
+		if !check_statement(
+			&data.statement.statement,
+			&data.statement.signature,
+			data.statement.sender,
+			&data.relay_parent,
+		) {
+			return Err("The statement has an invalid signature".into())
+		}
+
+		Ok(())
+	}
+}
+
+impl<Block, R> BlockAnnounceValidator<Block> for ParachainBlockAnnounceValidator<Block, R> where
+	Block: BlockT,
+	R: RelayChainInterface,
+{
+	fn validate(&mut self, header: &Block::Header, data: &[u8]) -> Validation {
+		match self.included_head() {
+			Ok(Some(included)) => {
+				if included.hash() == header.hash() {
+					return Validation::Success
+				}
+
+				if header.number() <= included.number() {
+					debug!("Rejecting stale announcement of block {}", header.hash());
+					return Validation::Failure
+				}
+			},
+			Ok(None) => {},
+			Err(e) => {
+				warn!("{}", e);
+				return Validation::Failure
+			},
+		}
+
+		match self.check_seconded(header, data) {
+			Ok(()) => Validation::Success,
+			Err(e) => {
+				debug!("Rejecting announcement of block {}: {}", header.hash(), e);
+				Validation::Failure
+			},
+		}
+	}
+}
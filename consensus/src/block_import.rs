@@ -0,0 +1,110 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A block import that leaves the best and the finalized block to the relay chain.
+
+use crate::LocalClient;
+use substrate_client::error::Result as ClientResult;
+use substrate_consensus_common::{BlockImport, ImportBlock, ImportResult, ForkChoiceStrategy};
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT, AuthorityIdFor};
+
+use parking_lot::Mutex;
+
+use std::sync::Arc;
+
+/// The heads the relay chain reported before the blocks were imported.
+struct PendingHeads<Block: BlockT> {
+	best: Option<Block::Hash>,
+	finalized: Option<Block::Hash>,
+}
+
+/// Wraps a `BlockImport` and imports all parachain blocks as non-best and non-finalized.
+///
+/// The best and the finalized block are set by `follow_polkadot` when the relay chain
+/// includes or finalizes a block. For this, the instance is passed as `LocalClient` to
+/// `follow_polkadot`. If the relay chain reports a block before it is imported, the block is
+/// imported as best or finalized block.
+pub struct ParachainBlockImport<I, L: LocalClient> {
+	inner: I,
+	local: Arc<L>,
+	pending: Mutex<PendingHeads<L::Block>>,
+}
+
+impl<I, L: LocalClient> ParachainBlockImport<I, L> {
+	/// Create a new instance that imports the blocks with `inner` into `local`.
+	pub fn new(inner: I, local: Arc<L>) -> Self {
+		Self { inner, local, pending: Mutex::new(PendingHeads { best: None, finalized: None }) }
+	}
+}
+
+impl<I, L> BlockImport<L::Block> for ParachainBlockImport<I, L> where
+	I: BlockImport<L::Block>,
+	L: LocalClient,
+{
+	type Error = I::Error;
+
+	fn check_block(
+		&self,
+		hash: <L::Block as BlockT>::Hash,
+		parent_hash: <L::Block as BlockT>::Hash,
+	) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(hash, parent_hash)
+	}
+
+	fn import_block(
+		&self,
+		mut block: ImportBlock<L::Block>,
+		new_authorities: Option<Vec<AuthorityIdFor<L::Block>>>,
+	) -> Result<ImportResult, Self::Error> {
+		let hash = block.post_header().hash();
+		let mut pending = self.pending.lock();
+
+		let best = pending.best == Some(hash);
+		if best {
+			pending.best = None;
+		}
+
+		let finalized = pending.finalized == Some(hash);
+		if finalized {
+			pending.finalized = None;
+		}
+
+		block.fork_choice = ForkChoiceStrategy::Custom(best);
+		block.finalized = finalized;
+
+		self.inner.import_block(block, new_authorities)
+	}
+}
+
+impl<I, L: LocalClient> LocalClient for ParachainBlockImport<I, L> {
+	type Block = L::Block;
+
+	fn mark_best(&self, hash: <Self::Block as BlockT>::Hash) -> ClientResult<bool> {
+		let mut pending = self.pending.lock();
+		let known = self.local.mark_best(hash)?;
+		pending.best = if known { None } else { Some(hash) };
+
+		Ok(known)
+	}
+
+	fn finalize(&self, hash: <Self::Block as BlockT>::Hash) -> ClientResult<bool> {
+		let mut pending = self.pending.lock();
+		let known = self.local.finalize(hash)?;
+		pending.finalized = if known { None } else { Some(hash) };
+
+		Ok(known)
+	}
+}
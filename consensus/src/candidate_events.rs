@@ -0,0 +1,96 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Typed notifications about the parachain blocks that the relay chain includes.
+//!
+//! The relay chain does not emit events for candidates, the notifications are derived from the
+//! parachain head in the state of the new best and the finalized relay chain blocks.
+
+use crate::{Error, PolkadotClient};
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT};
+use polkadot_primitives::{Hash as PHash, parachain::Id as ParaId};
+
+use futures::prelude::*;
+use parity_codec::Decode;
+
+/// A notification about a parachain block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CandidateEvent<Hash> {
+	/// The block is the parachain head in the given new best relay chain block.
+	Included {
+		/// The hash of the parachain block.
+		block_hash: Hash,
+		/// The hash of the relay chain block.
+		relay_hash: PHash,
+	},
+	/// The block is the parachain head in the last finalized relay chain block.
+	Finalized {
+		/// The hash of the parachain block.
+		block_hash: Hash,
+	},
+}
+
+/// The stream that is returned by `candidate_events`.
+pub type CandidateEvents<Block, E> = Box<
+	dyn Stream<Item=CandidateEvent<<Block as BlockT>::Hash>, Error=Error<E>> + Send
+>;
+
+/// Returns a stream of the `CandidateEvent`s of the given parachain.
+///
+/// An event is only sent when the parachain head changes, a relay chain block that includes the
+/// same head as its parent does not yield an event.
+pub fn candidate_events<Block, P>(para_id: ParaId, polkadot: &P) -> CandidateEvents<Block, P::Error>
+	where
+		Block: BlockT,
+		P: PolkadotClient,
+		P::Error: 'static,
+		P::HeadUpdates: 'static,
+		P::Finalized: 'static,
+{
+	let mut last_included = None;
+	let included = polkadot.head_updates(para_id)
+		.map_err(Error::Polkadot)
+		.and_then(|update| decode_head_hash::<Block, _>(&update.head_data)
+			.map(|hash| hash.map(|block_hash| (block_hash, update.relay_hash))))
+		.filter_map(|h| h)
+		.filter(move |(block_hash, _)| {
+			let changed = last_included.as_ref() != Some(block_hash);
+			last_included = Some(block_hash.clone());
+			changed
+		})
+		.map(|(block_hash, relay_hash)| CandidateEvent::Included { block_hash, relay_hash });
+
+	let mut last_finalized = None;
+	let finalized = polkadot.finalized_heads(para_id)
+		.map_err(Error::Polkadot)
+		.and_then(|head_data| decode_head_hash::<Block, _>(&head_data))
+		.filter_map(|h| h)
+		.filter(move |block_hash| {
+			let changed = last_finalized.as_ref() != Some(block_hash);
+			last_finalized = Some(block_hash.clone());
+			changed
+		})
+		.map(|block_hash| CandidateEvent::Finalized { block_hash });
+
+	Box::new(included.select(finalized))
+}
+
+/// Decode the given parachain head and return its hash.
+fn decode_head_hash<Block: BlockT, E>(head_data: &[u8]) -> Result<Option<Block::Hash>, Error<E>> {
+	<Option<Block::Header>>::decode(&mut &head_data[..])
+		.map(|head| head.map(|h| h.hash()))
+		.ok_or_else(|| Error::InvalidHeadData)
+}
@@ -14,20 +14,33 @@
 // You should have received a copy of the GNU General Public License
 // along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
 
-use substrate_client::{backend::Backend, CallExecutor, Client, BlockchainEvents};
+use substrate_client::{backend::Backend, CallExecutor, Client};
 use substrate_client::error::{Error as ClientError, Result as ClientResult};
 use substrate_primitives::{Blake2Hasher, H256};
 use sr_primitives::generic::BlockId;
-use sr_primitives::traits::{Block as BlockT, Header as HeaderT, ProvideRuntimeApi};
-use polkadot_primitives::{Hash as PHash, Block as PBlock};
-use polkadot_primitives::parachain::{Id as ParaId, ParachainHost};
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT};
+use polkadot_primitives::Hash as PHash;
+use polkadot_primitives::parachain::Id as ParaId;
+use cumulus_relay_chain_interface::{RelayChainInterface, RelayChainError};
 
 use futures::{prelude::*, stream};
-use parity_codec::{Encode, Decode};
-use log::warn;
+use parity_codec::Decode;
+use log::{info, warn, debug};
 
 use std::sync::Arc;
 
+mod block_announce;
+mod block_import;
+mod proof_size_import;
+mod candidate_events;
+
+pub use block_announce::{
+	BlockAnnounceValidator, BlockAnnounceData, ParachainBlockAnnounceValidator, Validation,
+};
+pub use block_import::ParachainBlockImport;
+pub use proof_size_import::ProofSizeImport;
+pub use candidate_events::{CandidateEvent, CandidateEvents, candidate_events};
+
 /// Helper for the local client.
 pub trait LocalClient {
 	/// The block type of the local client.
@@ -42,6 +55,17 @@ pub trait LocalClient {
 	fn finalize(&self, hash: <Self::Block as BlockT>::Hash) -> ClientResult<bool>;
 }
 
+/// Requests the network to sync a parachain block that is not known locally.
+pub trait SyncTarget<Block: BlockT>: Send + Sync {
+	/// Sync the block with the given header and its ancestry, e.g. with a fork sync request of
+	/// the network.
+	fn sync_to(&self, header: &Block::Header);
+}
+
+impl<Block: BlockT> SyncTarget<Block> for () {
+	fn sync_to(&self, _: &Block::Header) {}
+}
+
 /// Errors that can occur while following the polkadot relay-chain.
 #[derive(Debug)]
 pub enum Error<P> {
@@ -67,7 +91,7 @@ pub trait PolkadotClient: Clone {
 	/// The error type for interacting with the Polkadot client.
 	type Error: std::fmt::Debug + Send;
 
-	/// A stream that yields updates to the parachain head.
+	/// A stream that yields the parachain head of every new best relay chain block.
 	type HeadUpdates: Stream<Item=HeadUpdate, Error=Self::Error> + Send;
 	/// A stream that yields finalized head-data for a certain parachain.
 	type Finalized: Stream<Item=Vec<u8>, Error=Self::Error> + Send;
@@ -76,6 +100,8 @@ pub trait PolkadotClient: Clone {
 	fn head_updates(&self, para_id: ParaId) -> Self::HeadUpdates;
 	/// Get a stream of finalized heads.
 	fn finalized_heads(&self, para_id: ParaId) -> Self::Finalized;
+	/// Get the head-data of the last finalized relay chain block for a certain parachain.
+	fn finalized_head(&self, para_id: ParaId) -> Result<Option<Vec<u8>>, Self::Error>;
 }
 
 /// Spawns a future that follows the Polkadot relay chain for the given parachain.
@@ -84,27 +110,68 @@ pub fn follow_polkadot<'a, L: 'a, P: 'a>(para_id: ParaId, local: Arc<L>, polkado
 	where
 		L: LocalClient + Send + Sync,
 		P: PolkadotClient + Send + Sync,
+{
+	follow_polkadot_with_sync(para_id, local, polkadot, Arc::new(()))
+}
+
+/// Spawns a future that follows the Polkadot relay chain for the given parachain like
+/// `follow_polkadot`.
+///
+/// Starts with the parachain head of the last finalized relay chain block. Every finalized
+/// head that is not known locally is passed to `sync_target`, so a freshly started node syncs
+/// towards it instead of waiting for a block announcement. The ancestry of the head is verified
+/// by importing it.
+///
+/// Logs every parachain block that is included in a new best relay chain block and every
+/// parachain block that is finalized, together with the relay chain block.
+pub fn follow_polkadot_with_sync<'a, L: 'a, P: 'a, S: 'a>(
+	para_id: ParaId,
+	local: Arc<L>,
+	polkadot: P,
+	sync_target: Arc<S>,
+) -> impl Future<Item=(),Error=()> + Send + 'a
+	where
+		L: LocalClient + Send + Sync,
+		P: PolkadotClient + Send + Sync,
+		S: SyncTarget<L::Block> + ?Sized,
 {
 	let head_updates = polkadot.head_updates(para_id);
-	let finalized_heads = polkadot.finalized_heads(para_id);
+	let finalized_heads = stream::once(polkadot.finalized_head(para_id))
+		.filter_map(|h| h)
+		.chain(polkadot.finalized_heads(para_id));
 
 	let follow_best = {
 		let local = local.clone();
+		let mut last_included = None;
 
 		head_updates
 			.map_err(Error::Polkadot)
-			.and_then(|update| -> Result<Option<<L::Block as BlockT>::Header>, _> {
-				Decode::decode(&mut &update.head_data[..]).ok_or_else(|| Error::InvalidHeadData)
+			.and_then(|update| {
+				<Option<<L::Block as BlockT>::Header>>::decode(&mut &update.head_data[..])
+					.ok_or_else(|| Error::InvalidHeadData)
+					.map(|head| head.map(|head| (head, update.relay_hash)))
 			})
 			.filter_map(|h| h)
-			.for_each(move |p_head| {
-				let _synced = local.mark_best(p_head.hash()).map_err(Error::Client)?;
+			.for_each(move |(p_head, relay_hash)| {
+				let hash = p_head.hash();
+				if last_included != Some(hash) {
+					info!(
+						"Parachain block #{} ({}) is included in relay chain block {}",
+						p_head.number(),
+						hash,
+						relay_hash,
+					);
+					last_included = Some(hash);
+				}
+
+				let _synced = local.mark_best(hash).map_err(Error::Client)?;
 				Ok(())
 			})
 	};
 
 	let follow_finalized = {
 		let local = local.clone();
+		let mut last_finalized = None;
 
 		finalized_heads
 			.map_err(Error::Polkadot)
@@ -113,7 +180,14 @@ pub fn follow_polkadot<'a, L: 'a, P: 'a>(para_id: ParaId, local: Arc<L>, polkado
 			})
 			.filter_map(|h| h)
 			.for_each(move |p_head| {
-				let _synced = local.finalize(p_head.hash()).map_err(Error::Client)?;
+				if last_finalized != Some(p_head.hash()) {
+					info!("Parachain block #{} ({}) is finalized", p_head.number(), p_head.hash());
+					last_finalized = Some(p_head.hash());
+				}
+				if !local.finalize(p_head.hash()).map_err(Error::Client)? {
+					debug!("Syncing to the finalized parachain block {}", p_head.hash());
+					sync_target.sync_to(&p_head);
+				}
 				Ok(())
 			})
 	};
@@ -151,58 +225,59 @@ impl<B, E, Block, RA> LocalClient for Client<B, E, Block, RA> where
 	}
 }
 
-fn parachain_key(para_id: ParaId) -> substrate_primitives::storage::StorageKey {
-	const PREFIX: &[u8] = &*b"Parachains Heads";
-	para_id.using_encoded(|s| {
-		let mut v = PREFIX.to_vec();
-		v.extend(s);
-		substrate_primitives::storage::StorageKey(v)
-	})
+/// A `PolkadotClient` on top of a `RelayChainInterface`.
+pub struct RelayChainPolkadotClient<R> {
+	relay_chain: Arc<R>,
 }
 
-impl<B, E, RA> PolkadotClient for Arc<Client<B, E, PBlock, RA>> where
-	B: Backend<PBlock, Blake2Hasher> + Send + Sync + 'static,
-	E: CallExecutor<PBlock, Blake2Hasher> + Send + Sync + 'static,
-	RA: ProvideRuntimeApi + Send + Sync + 'static,
-	RA::Api: ParachainHost<PBlock>,
-{
-	type Error = ClientError;
+impl<R> RelayChainPolkadotClient<R> {
+	/// Create a new instance.
+	pub fn new(relay_chain: Arc<R>) -> Self {
+		Self { relay_chain }
+	}
+}
+
+impl<R> Clone for RelayChainPolkadotClient<R> {
+	fn clone(&self) -> Self {
+		Self { relay_chain: self.relay_chain.clone() }
+	}
+}
+
+impl<R: RelayChainInterface + 'static> PolkadotClient for RelayChainPolkadotClient<R> {
+	type Error = RelayChainError;
 
 	type HeadUpdates = Box<dyn Stream<Item=HeadUpdate, Error=Self::Error> + Send>;
 	type Finalized = Box<dyn Stream<Item=Vec<u8>, Error=Self::Error> + Send>;
 
 	fn head_updates(&self, para_id: ParaId) -> Self::HeadUpdates {
-		let parachain_key = parachain_key(para_id);
-		let stream = stream::once(self.storage_changes_notification_stream(Some(&[parachain_key.clone()]), None))
-			.map(|s| s.map_err(|()| panic!("unbounded receivers never yield errors; qed")))
-			.flatten();
-
-		let s = stream.filter_map(move |(hash, changes)| {
-			let head_data = changes.iter()
-				.filter_map(|(_, k, v)| if k == &parachain_key { Some(v) } else { None })
-				.next();
-
-			match head_data {
-				Some(Some(head_data)) => Some(HeadUpdate {
-					relay_hash: hash,
-					head_data: head_data.0.clone(),
-				}),
-				Some(None) | None => None,
-			}
-		});
+		let relay_chain = self.relay_chain.clone();
+
+		// Only new best relay chain blocks are considered, blocks imported on other forks must
+		// not change the best parachain block. The head is read for every new best block, as
+		// the parachain head changes on a reorg even if the new best block did not change it.
+		let s = self.relay_chain.new_best_notification_stream()
+			.and_then(move |header| {
+				let relay_hash = header.hash();
+				relay_chain.parachain_head(relay_hash, para_id)
+					.map(|d| d.map(|head_data| HeadUpdate { relay_hash, head_data }))
+			})
+			.filter_map(|u| u);
 
 		Box::new(s)
 	}
 
 	fn finalized_heads(&self, para_id: ParaId) -> Self::Finalized {
-		let polkadot = self.clone();
-		let parachain_key = parachain_key(para_id);
+		let relay_chain = self.relay_chain.clone();
 
-		let s = self.finality_notification_stream()
-			.map_err(|()| panic!("unbounded receivers never yield errors; qed"))
-			.and_then(move |n| polkadot.storage(&BlockId::hash(n.hash), &parachain_key))
-			.filter_map(|d| d.map(|d| d.0));
+		let s = self.relay_chain.finality_notification_stream()
+			.and_then(move |header| relay_chain.parachain_head(header.hash(), para_id))
+			.filter_map(|d| d);
 
 		Box::new(s)
 	}
-}
\ No newline at end of file
+
+	fn finalized_head(&self, para_id: ParaId) -> Result<Option<Vec<u8>>, Self::Error> {
+		let finalized = self.relay_chain.finalized_block_hash()?;
+		self.relay_chain.parachain_head(finalized, para_id)
+	}
+}
@@ -0,0 +1,142 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A block import that records the witness data of every imported block.
+
+use substrate_client::{
+	Client, CallExecutor, backend::Backend, block_builder::api::BlockBuilder as BlockBuilderApi,
+};
+use substrate_consensus_common::{BlockImport, ImportBlock, ImportResult};
+use substrate_primitives::{Blake2Hasher, H256};
+use sr_primitives::generic::BlockId;
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT, ProvideRuntimeApi, AuthorityIdFor};
+use cumulus_client_proof_recorder::ProofRecorder;
+use cumulus_runtime::validate_block::DEFAULT_MAX_POV_SIZE;
+
+use parity_codec::Encode;
+use log::{warn, debug};
+
+use std::sync::Arc;
+
+/// Wraps a `BlockImport` and builds every imported block a second time while recording its
+/// witness data.
+///
+/// This lets full nodes check the PoV size of the blocks they import. A warning is logged if the
+/// PoV exceeds the maximum PoV size or if the recorded execution does not reach the state root
+/// of the imported block. Blocks are executed twice, so this is meant for debugging.
+pub struct ProofSizeImport<I, B, E, Block: BlockT, RA> {
+	inner: I,
+	proof_recorder: Arc<ProofRecorder<B, E, Block, RA>>,
+	max_pov_size: usize,
+}
+
+impl<I, B, E, Block: BlockT, RA> ProofSizeImport<I, B, E, Block, RA> {
+	/// Create a new instance that imports the blocks with `inner` and records them with
+	/// `proof_recorder`.
+	pub fn new(inner: I, proof_recorder: Arc<ProofRecorder<B, E, Block, RA>>) -> Self {
+		Self { inner, proof_recorder, max_pov_size: DEFAULT_MAX_POV_SIZE }
+	}
+
+	/// Warn about PoVs that are bigger than `max_pov_size` bytes.
+	pub fn with_max_pov_size(mut self, max_pov_size: usize) -> Self {
+		self.max_pov_size = max_pov_size;
+		self
+	}
+}
+
+impl<I, B, E, Block, RA> ProofSizeImport<I, B, E, Block, RA> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
+{
+	/// Build the block with the given `header` and `extrinsics` again and log its PoV size.
+	fn record(&self, header: &Block::Header, extrinsics: Vec<<Block as BlockT>::Extrinsic>) {
+		let hash = header.hash();
+		let parent = BlockId::Hash(*header.parent_hash());
+		let recorded = match self.proof_recorder.build(&parent, header.digest().clone(), |b| {
+			extrinsics.into_iter().try_for_each(|e| b.push(e))
+		}) {
+			Ok(recorded) => recorded,
+			Err(e) => {
+				warn!("Could not record the witness data of block {}: {:?}", hash, e);
+				return
+			},
+		};
+
+		if recorded.block.header().state_root() != header.state_root() {
+			warn!(
+				"Recording block {} resulted in the state root {}, expected {}",
+				hash,
+				recorded.block.header().state_root(),
+				header.state_root(),
+			);
+		}
+
+		let pov_size = recorded.block.encode().len() + recorded.witness_data.encode().len();
+		if pov_size > self.max_pov_size {
+			warn!(
+				"Block {} has a PoV of {} bytes, more than the maximum of {} bytes",
+				hash,
+				pov_size,
+				self.max_pov_size,
+			);
+		} else {
+			debug!("Block {} has a PoV of {} bytes", hash, pov_size);
+		}
+	}
+}
+
+impl<I, B, E, Block, RA> BlockImport<Block> for ProofSizeImport<I, B, E, Block, RA> where
+	I: BlockImport<Block>,
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
+{
+	type Error = I::Error;
+
+	fn check_block(
+		&self,
+		hash: Block::Hash,
+		parent_hash: Block::Hash,
+	) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(hash, parent_hash)
+	}
+
+	fn import_block(
+		&self,
+		block: ImportBlock<Block>,
+		new_authorities: Option<Vec<AuthorityIdFor<Block>>>,
+	) -> Result<ImportResult, Self::Error> {
+		// The header of the `ImportBlock` does not contain the post digests, e.g. the seal, so
+		// it is the header the block was built with.
+		let header = block.header.clone();
+		let body = block.body.clone();
+
+		let res = self.inner.import_block(block, new_authorities)?;
+
+		if let (ImportResult::Imported(_), Some(body)) = (&res, body) {
+			self.record(&header, body);
+		}
+
+		Ok(res)
+	}
+}
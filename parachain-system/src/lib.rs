@@ -0,0 +1,591 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Runtime module that makes the relay chain validation data available to a parachain runtime.
+//!
+//! Every block must contain exactly one `set_validation_data` inherent, which stores the
+//! `ValidationData` of the relay parent. Other modules can read it through
+//! `relay_parent_number` and `relay_storage_root`.
+//!
+//! The runtime should create the `PolkadotInherent` of `validate_block` as
+//! `set_validation_data` call, so that the validators inject the same inherent the collator
+//! created. As every block requires it, `ProvidePolkadotInherent::REQUIRED` should be `true`.
+//!
+//! The `ParachainInherentData` that is fetched by the collator is included with the
+//! `set_parachain_inherent_data` inherent, which is created by `ProvideInherent`. Its relay
+//! chain state proof is checked against the relay storage root, other modules read the relay
+//! chain state through `relay_state_proof`.
+//!
+//! The downward messages are passed to the runtime by the `receive_downward_messages`
+//! inherent, which should be created by
+//! `ProvidePolkadotInherent::create_downward_messages_inherent`. Like the `PolkadotInherent`,
+//! it is injected by the validators, so the messages are the ones of the relay chain. Every
+//! message is passed to the `DownwardMessageHandler` of the runtime. The received messages
+//! are hashed into the `MessageQueueChain` that is returned by `received_message_queue_chain`.
+//!
+//! Runtime upgrades are done in two phases. Root authorizes the hash of the new code with
+//! `authorize_upgrade`, afterwards anyone can provide the code with `enact_authorized_upgrade`.
+//! The code is scheduled and replaces the current code in the first block whose relay parent
+//! is at least `ValidationUpgradeDelay` blocks after the relay parent of the enacting block.
+//! `validate_block` puts the new code into the validation result of this block. No upgrade can
+//! be scheduled while one is scheduled, and every step emits an `Event`.
+//!
+//! The number of parachain blocks that can be built on one relay parent is decided by the
+//! `ConsensusHook` of the runtime, e.g. `OneBlockPerRelayParent`. `set_validation_data` fails
+//! for blocks beyond it, which makes these blocks invalid. The relay parent of a block can not
+//! be older than the relay parent of its parent, and a block on the same relay parent must
+//! have the same relay storage root.
+//!
+//! Blocks that are built on top of blocks that are not yet included in the relay chain form
+//! the unincluded segment. It is tracked in every block that sets the `ParachainInherentData`,
+//! as its relay chain state proof contains the included parachain head. The segment, including
+//! the current block, can not be longer than `ConsensusHook::unincluded_segment_capacity`.
+//! Blocks that do not set the `ParachainInherentData` reset the tracking.
+//!
+//! Other modules send messages to the relay chain with `send_upward_message`. The messages are
+//! queued until the end of the block and then written to `UPWARD_MESSAGES_KEY`, from where
+//! `validate_block` puts them into the validation result. The number and size of the messages
+//! per block are limited by `MaxUpwardMessageCount` and `MaxUpwardMessageSize`.
+//!
+//! The BABE randomness of the relay chain is read from the relay chain state proof of every
+//! block and stored. The module implements `Randomness` with the randomness of one epoch ago,
+//! so other modules can use the randomness of the relay chain.
+//!
+//! The runtime should implement the `CollectCollationInfo` runtime API with
+//! `collect_collation_info`, so the collator gets the outputs of a block it built.
+
+use srml_support::{
+	decl_module, decl_storage, decl_event, ensure, StorageValue, traits::{Get, Randomness},
+};
+use system::{ensure_none, ensure_root, ensure_signed};
+use runtime_primitives::traits::Hash;
+use primitives::storage::well_known_keys;
+use inherents::{InherentIdentifier, InherentData, ProvideInherent, MakeFatalError, RuntimeString};
+use primitives::H256;
+use rstd::{vec::Vec, marker::PhantomData};
+use cumulus_runtime::{
+	ValidationData, ParachainInherentData, PARACHAIN_INHERENT_IDENTIFIER, RelayChainStateProof,
+	VALIDATION_DATA_IDENTIFIER,
+	DownwardMessage, CollationInfo, MessageQueueChain, OutboundHrmpMessage, UPWARD_MESSAGES_KEY,
+	HORIZONTAL_MESSAGES_KEY, HRMP_WATERMARK_KEY, ParaId,
+};
+use codec::{Encode, Decode};
+
+#[cfg(test)]
+mod tests;
+
+/// Handles the downward messages that are received by the parachain.
+pub trait DownwardMessageHandler {
+	/// Handle the given downward message.
+	fn handle_downward_message(message: DownwardMessage);
+}
+
+impl DownwardMessageHandler for () {
+	fn handle_downward_message(_: DownwardMessage) {}
+}
+
+/// Decides how many parachain blocks can be built on one relay parent.
+pub trait ConsensusHook {
+	/// Returns the number of parachain blocks that can be built on the relay parent with the
+	/// given number.
+	fn blocks_per_relay_parent(relay_parent_number: u32) -> u32;
+
+	/// Returns the maximum number of blocks that are not included in the relay chain yet,
+	/// including the current block.
+	///
+	/// With the default of `1`, every block must be built on a parent that is included.
+	fn unincluded_segment_capacity() -> u32 {
+		1
+	}
+}
+
+/// A `ConsensusHook` that allows one parachain block per relay parent.
+pub struct OneBlockPerRelayParent;
+
+impl ConsensusHook for OneBlockPerRelayParent {
+	fn blocks_per_relay_parent(_: u32) -> u32 {
+		1
+	}
+}
+
+/// A `ConsensusHook` that allows `V` parachain blocks per relay parent and `C` blocks in the
+/// unincluded segment.
+pub struct FixedVelocity<V, C>(PhantomData<(V, C)>);
+
+impl<V: Get<u32>, C: Get<u32>> ConsensusHook for FixedVelocity<V, C> {
+	fn blocks_per_relay_parent(_: u32) -> u32 {
+		V::get()
+	}
+
+	fn unincluded_segment_capacity() -> u32 {
+		C::get()
+	}
+}
+
+/// The bandwidth a block used for messages.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct UsedBandwidth {
+	/// The number of upward messages that were sent.
+	pub upward_messages: u32,
+	/// The total size in bytes of the upward messages that were sent.
+	pub upward_bytes: u32,
+	/// The number of downward messages that were processed.
+	pub downward_messages: u32,
+}
+
+impl UsedBandwidth {
+	/// Add the bandwidth of `other`.
+	fn add(&mut self, other: &Self) {
+		self.upward_messages = self.upward_messages.saturating_add(other.upward_messages);
+		self.upward_bytes = self.upward_bytes.saturating_add(other.upward_bytes);
+		self.downward_messages = self.downward_messages.saturating_add(other.downward_messages);
+	}
+}
+
+/// A block of the unincluded segment.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Ancestor<Hash> {
+	/// The hash of the block, set by its child, as a block does not know its own hash.
+	pub head_hash: Option<Hash>,
+	/// The bandwidth the block used.
+	pub used_bandwidth: UsedBandwidth,
+}
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+	/// The handler of the downward messages.
+	type DownwardMessageHandler: DownwardMessageHandler;
+	/// Decides how many parachain blocks can be built on one relay parent.
+	type ConsensusHook: ConsensusHook;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as ParachainSystem {
+		/// The id of this parachain.
+		ParachainId get(parachain_id) config(): ParaId;
+		/// The number of the relay parent of the current block.
+		RelayParentNumber get(relay_parent_number): u32;
+		/// The storage root of the relay parent of the current block.
+		RelayStorageRoot get(relay_storage_root): H256;
+		/// The number of the relay parent of the last block and the number of blocks that were
+		/// built on it.
+		RelayParentBlocks get(relay_parent_blocks): (u32, u32);
+		/// Was the `ValidationData` set in the current block?
+		///
+		/// Removed at the end of every block.
+		DidSetValidationData: bool;
+		/// The proof of the relay chain state of the current block.
+		///
+		/// Removed at the end of every block.
+		RelayChainState get(relay_chain_state): Vec<Vec<u8>>;
+		/// Was the `ParachainInherentData` set in the current block?
+		///
+		/// Removed at the end of every block.
+		DidSetParachainInherentData: bool;
+		/// Were the downward messages received in the current block?
+		///
+		/// Removed at the end of every block.
+		DidReceiveDownwardMessages: bool;
+		/// The number of downward messages that were received in the current block.
+		///
+		/// Removed at the start of every block, so it is available to `collect_collation_info`.
+		BlockDownwardMessages: u32;
+		/// Was the validation code upgraded in the current block?
+		///
+		/// Removed at the start of every block, so it is available to `collect_collation_info`.
+		DidUpgradeCode: bool;
+		/// The blocks that are not included in the relay chain yet, oldest first, without the
+		/// current block.
+		UnincludedSegment get(unincluded_segment): Vec<Ancestor<T::Hash>>;
+		/// The number of downward messages that were processed since genesis.
+		ProcessedDownwardMessages get(processed_downward_messages): u64;
+		/// The message queue chain over all downward messages that were processed since genesis.
+		ReceivedMessageQueueChain get(received_message_queue_chain): MessageQueueChain;
+		/// The BABE randomness of the relay chain of one epoch before the epoch of the relay
+		/// parent of the last block that set the `ParachainInherentData`.
+		OneEpochAgoRandomness get(one_epoch_ago_randomness): Option<H256>;
+		/// The BABE randomness of the relay chain of two epochs before the epoch of the relay
+		/// parent of the last block that set the `ParachainInherentData`.
+		TwoEpochsAgoRandomness get(two_epochs_ago_randomness): Option<H256>;
+		/// The BABE slot of the relay parent of the last block that set the
+		/// `ParachainInherentData`.
+		RelaySlot get(relay_slot): u64;
+
+		/// The number of relay chain blocks between enacting an upgrade and applying it.
+		ValidationUpgradeDelay get(validation_upgrade_delay) config(): u32;
+		/// The hash of the code that is authorized to be enacted.
+		AuthorizedUpgrade get(authorized_upgrade): Option<T::Hash>;
+		/// The code of the scheduled upgrade and the relay chain block number from which on it
+		/// is applied.
+		PendingValidationCode get(pending_validation_code): Option<(u32, Vec<u8>)>;
+
+		/// The maximum number of upward messages that can be sent in one block.
+		MaxUpwardMessageCount get(max_upward_message_count) config(): u32;
+		/// The maximum size in bytes of one upward message.
+		MaxUpwardMessageSize get(max_upward_message_size) config(): u32;
+		/// The upward messages that were sent in the current block.
+		///
+		/// Removed at the end of every block.
+		PendingUpwardMessages get(pending_upward_messages): Vec<Vec<u8>>;
+	}
+}
+
+decl_event! {
+	pub enum Event<T> where Hash = <T as system::Trait>::Hash {
+		/// The upgrade to the code with the given hash was authorized.
+		UpgradeAuthorized(Hash),
+		/// The upgrade was scheduled to be applied at the given relay chain block number.
+		ValidationFunctionStored(u32),
+		/// The scheduled upgrade was applied in a block with the given relay parent number.
+		ValidationFunctionApplied(u32),
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Set the `ValidationData` of the relay parent of the current block.
+		///
+		/// Must be called exactly once per block as inherent.
+		fn set_validation_data(origin, data: ValidationData) {
+			ensure_none(origin)?;
+			ensure!(!DidSetValidationData::exists(), "ValidationData is already set in this block");
+
+			let (last_relay_parent, blocks) = Self::relay_parent_blocks();
+			ensure!(
+				data.relay_parent_number >= last_relay_parent,
+				"The relay parent is older than the relay parent of the parent block"
+			);
+			ensure!(
+				data.relay_parent_number != last_relay_parent || blocks == 0
+					|| data.relay_storage_root == Self::relay_storage_root(),
+				"The relay storage root does not match the one of the parent block"
+			);
+
+			let blocks = if last_relay_parent == data.relay_parent_number { blocks + 1 } else { 1 };
+			ensure!(
+				blocks <= T::ConsensusHook::blocks_per_relay_parent(data.relay_parent_number),
+				"No capacity left for another block on the relay parent"
+			);
+			RelayParentBlocks::put((data.relay_parent_number, blocks));
+
+			<UnincludedSegment<T>>::mutate(|segment| {
+				if let Some(parent) = segment.last_mut() {
+					parent.head_hash = Some(<system::Module<T>>::parent_hash());
+				}
+			});
+
+			RelayParentNumber::put(data.relay_parent_number);
+			RelayStorageRoot::put(data.relay_storage_root);
+			DidSetValidationData::put(true);
+
+			Self::apply_pending_upgrade(data.relay_parent_number);
+		}
+
+		/// Receive the downward messages of the relay parent of the current block.
+		///
+		/// Must be called at most once per block as inherent, after `set_validation_data`.
+		fn receive_downward_messages(origin, messages: Vec<DownwardMessage>) {
+			ensure_none(origin)?;
+			ensure!(
+				!DidReceiveDownwardMessages::exists(),
+				"Downward messages are already received in this block"
+			);
+			ensure!(DidSetValidationData::exists(), "ValidationData must be set first");
+
+			let count = messages.len() as u64;
+			let mut chain = Self::received_message_queue_chain();
+			for message in messages {
+				chain.extend_downward(&message.data);
+				T::DownwardMessageHandler::handle_downward_message(message);
+			}
+			ReceivedMessageQueueChain::put(chain);
+
+			ProcessedDownwardMessages::mutate(|processed| *processed += count);
+			BlockDownwardMessages::put(count as u32);
+			DidReceiveDownwardMessages::put(true);
+		}
+
+		/// Authorize an upgrade to the code with the given hash.
+		///
+		/// Replaces any previously authorized upgrade that was not enacted yet.
+		fn authorize_upgrade(origin, code_hash: T::Hash) {
+			ensure_root(origin)?;
+
+			<AuthorizedUpgrade<T>>::put(code_hash);
+			Self::deposit_event(RawEvent::UpgradeAuthorized(code_hash));
+		}
+
+		/// Schedule the upgrade to the given code, which must match the authorized upgrade.
+		fn enact_authorized_upgrade(origin, code: Vec<u8>) {
+			ensure_signed(origin)?;
+			ensure!(!PendingValidationCode::exists(), "An upgrade is already scheduled");
+
+			let code_hash = Self::authorized_upgrade().ok_or("No upgrade is authorized")?;
+			ensure!(
+				T::Hashing::hash(&code) == code_hash,
+				"The code does not match the authorized upgrade"
+			);
+
+			let apply_at = Self::relay_parent_number()
+				.saturating_add(Self::validation_upgrade_delay());
+			<AuthorizedUpgrade<T>>::kill();
+			PendingValidationCode::put((apply_at, code));
+			Self::deposit_event(RawEvent::ValidationFunctionStored(apply_at));
+		}
+
+		/// Set the `ParachainInherentData` of the relay parent of the current block.
+		///
+		/// Must be called at most once per block as inherent, after `set_validation_data`.
+		fn set_parachain_inherent_data(origin, data: ParachainInherentData) {
+			ensure_none(origin)?;
+			ensure!(
+				!DidSetParachainInherentData::exists(),
+				"ParachainInherentData is already set in this block"
+			);
+			ensure!(DidSetValidationData::exists(), "ValidationData must be set first");
+
+			let proof = RelayChainStateProof::new(
+				Self::parachain_id(),
+				Self::relay_storage_root(),
+				data.relay_chain_state.clone(),
+			).map_err(|_| "The relay chain state proof does not match the relay storage root")?;
+
+			// Read everything from the proof first, so a failing read does not leave a partially
+			// written state behind.
+			let included_head = proof.para_head()
+				.map_err(|_| "The relay chain state proof does not contain the parachain head")?;
+			let one_epoch_ago = proof.one_epoch_ago_randomness()
+				.map_err(|_| "The relay chain state proof does not contain the randomness")?;
+			let two_epochs_ago = proof.two_epochs_ago_randomness()
+				.map_err(|_| "The relay chain state proof does not contain the randomness")?;
+			let slot = proof.current_slot()
+				.map_err(|_| "The relay chain state proof does not contain the slot")?;
+
+			if let Some(included_head) = included_head {
+				Self::prune_unincluded_segment(T::Hashing::hash(&included_head));
+			}
+			if let Some(randomness) = one_epoch_ago {
+				OneEpochAgoRandomness::put(randomness);
+			}
+			if let Some(randomness) = two_epochs_ago {
+				TwoEpochsAgoRandomness::put(randomness);
+			}
+			if let Some(slot) = slot {
+				RelaySlot::put(slot);
+			}
+
+			RelayChainState::put(data.relay_chain_state);
+			DidSetParachainInherentData::put(true);
+		}
+
+		fn on_initialize() {
+			BlockDownwardMessages::kill();
+			DidUpgradeCode::kill();
+		}
+
+		fn on_finalize() {
+			assert!(DidSetValidationData::take(), "ValidationData must be set in every block");
+			DidReceiveDownwardMessages::kill();
+			RelayChainState::kill();
+
+			let upward_messages = PendingUpwardMessages::take();
+			let used_bandwidth = UsedBandwidth {
+				upward_messages: upward_messages.len() as u32,
+				upward_bytes: upward_messages.iter().map(|m| m.len() as u32).sum(),
+				downward_messages: BlockDownwardMessages::get(),
+			};
+
+			if DidSetParachainInherentData::take() {
+				let mut segment = Self::unincluded_segment();
+				segment.push(Ancestor { head_hash: None, used_bandwidth });
+				assert!(
+					segment.len() <= T::ConsensusHook::unincluded_segment_capacity() as usize,
+					"The unincluded segment exceeds its capacity",
+				);
+				<UnincludedSegment<T>>::put(segment);
+			} else {
+				<UnincludedSegment<T>>::kill();
+			}
+
+			if upward_messages.is_empty() {
+				runtime_io::clear_storage(UPWARD_MESSAGES_KEY);
+			} else {
+				runtime_io::set_storage(UPWARD_MESSAGES_KEY, &upward_messages.encode());
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Apply the scheduled upgrade if its relay chain block number is reached.
+	fn apply_pending_upgrade(relay_parent_number: u32) {
+		match Self::pending_validation_code() {
+			Some((apply_at, _)) if apply_at <= relay_parent_number => Self::apply_upgrade(),
+			_ => {},
+		}
+	}
+
+	/// Apply the scheduled upgrade, if there is one.
+	fn apply_upgrade() {
+		if let Some((_, code)) = PendingValidationCode::take() {
+			runtime_io::set_storage(well_known_keys::CODE, &code);
+			DidUpgradeCode::put(true);
+			Self::deposit_event(RawEvent::ValidationFunctionApplied(Self::relay_parent_number()));
+		}
+	}
+
+	/// Returns the `CollationInfo` of the block with the given `header`.
+	///
+	/// Must be called on the state after the block was executed.
+	pub fn collect_collation_info(header: &T::Header) -> CollationInfo {
+		let upward_messages = runtime_io::storage(UPWARD_MESSAGES_KEY)
+			.and_then(|m| Decode::decode(&mut &m[..]))
+			.unwrap_or_default();
+		let horizontal_messages = runtime_io::storage(HORIZONTAL_MESSAGES_KEY)
+			.and_then(|m| Vec::<OutboundHrmpMessage>::decode(&mut &m[..]))
+			.unwrap_or_default();
+		let new_validation_code = if DidUpgradeCode::get() {
+			runtime_io::storage(well_known_keys::CODE)
+		} else {
+			None
+		};
+
+		CollationInfo {
+			head_data: header.encode(),
+			upward_messages,
+			horizontal_messages,
+			processed_downward_messages: BlockDownwardMessages::get(),
+			new_validation_code,
+			hrmp_watermark: runtime_io::storage(HRMP_WATERMARK_KEY)
+				.and_then(|w| Decode::decode(&mut &w[..])),
+		}
+	}
+
+	/// Send the given message to the relay chain.
+	///
+	/// Fails if the message is larger than `MaxUpwardMessageSize` or if
+	/// `MaxUpwardMessageCount` messages were already sent in the current block and the
+	/// unincluded segment.
+	pub fn send_upward_message(message: Vec<u8>) -> Result<(), &'static str> {
+		ensure!(
+			message.len() <= Self::max_upward_message_size() as usize,
+			"The upward message is too large"
+		);
+
+		let unincluded = Self::unincluded_bandwidth().upward_messages as usize;
+		PendingUpwardMessages::mutate(|messages| {
+			ensure!(
+				unincluded + messages.len() < Self::max_upward_message_count() as usize,
+				"Too many upward messages in this block"
+			);
+			messages.push(message);
+			Ok(())
+		})
+	}
+
+	/// Remove the included block with the given hash and its ancestors from the unincluded
+	/// segment.
+	fn prune_unincluded_segment(included: T::Hash) {
+		<UnincludedSegment<T>>::mutate(|segment| {
+			if let Some(pos) = segment.iter().position(|a| a.head_hash == Some(included)) {
+				segment.drain(..=pos);
+			}
+		});
+	}
+
+	/// Returns the bandwidth that is used by the unincluded segment, without the current block.
+	pub fn unincluded_bandwidth() -> UsedBandwidth {
+		Self::unincluded_segment().iter().fold(Default::default(), |mut used, ancestor| {
+			used.add(&ancestor.used_bandwidth);
+			used
+		})
+	}
+
+	/// Returns the number of parachain blocks that can still be built on the relay parent of
+	/// the current block.
+	pub fn remaining_capacity() -> u32 {
+		let (relay_parent, blocks) = Self::relay_parent_blocks();
+		T::ConsensusHook::blocks_per_relay_parent(relay_parent).saturating_sub(blocks)
+	}
+
+	/// Returns the proof of the relay chain state of the current block.
+	///
+	/// Returns `None` if the `ParachainInherentData` is not set in the current block.
+	pub fn relay_state_proof() -> Option<RelayChainStateProof> {
+		if !DidSetParachainInherentData::exists() {
+			return None
+		}
+
+		RelayChainStateProof::new(
+			Self::parachain_id(),
+			Self::relay_storage_root(),
+			Self::relay_chain_state(),
+		).ok()
+	}
+}
+
+/// The randomness of the relay chain of one epoch ago.
+///
+/// The random value is the hash of the randomness and the `subject`. It is known to the relay
+/// chain one epoch in advance and only changes once per epoch, so it must not be used where an
+/// unpredictable value is required. Before any randomness was received, the zero hash is used.
+impl<T: Trait> Randomness<T::Hash> for Module<T> {
+	fn random(subject: &[u8]) -> T::Hash {
+		let randomness = Self::one_epoch_ago_randomness().unwrap_or_default();
+		T::Hashing::hash(&(randomness, subject).encode())
+	}
+}
+
+impl<T: Trait> ProvideInherent for Module<T> {
+	type Call = Call<T>;
+	type Error = MakeFatalError<RuntimeString>;
+	const INHERENT_IDENTIFIER: InherentIdentifier = PARACHAIN_INHERENT_IDENTIFIER;
+
+	fn create_inherent(data: &InherentData) -> Option<Self::Call> {
+		let data: ParachainInherentData = data.get_data(&PARACHAIN_INHERENT_IDENTIFIER)
+			.expect("ParachainInherentData is not correctly encoded")?;
+
+		Some(Call::set_parachain_inherent_data(data))
+	}
+
+	/// Check that the `ValidationData` of the block is exactly the one of the relay parent.
+	///
+	/// The check is skipped if the inherent data does not contain the `ValidationData`, as the
+	/// node that checks the block does not know the relay parent.
+	fn check_inherent(call: &Self::Call, data: &InherentData) -> Result<(), Self::Error> {
+		let block_data = match call {
+			Call::set_validation_data(ref block_data) => block_data,
+			_ => return Ok(()),
+		};
+
+		let expected: Option<ValidationData> = data.get_data(&VALIDATION_DATA_IDENTIFIER)
+			.map_err(|_| RuntimeString::from("ValidationData is not correctly encoded"))?;
+		match expected {
+			Some(ref expected) if expected != block_data => Err(
+				RuntimeString::from("The ValidationData does not match the relay parent").into()
+			),
+			_ => Ok(()),
+		}
+	}
+}
@@ -0,0 +1,692 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use srml_support::{impl_outer_origin, parameter_types, assert_ok, assert_noop};
+use runtime_io::{with_externalities, TestExternalities};
+use primitives::Blake2Hasher;
+use runtime_primitives::{
+	traits::{BlakeTwo256, IdentityLookup, OnInitialize, OnFinalize}, testing::Header,
+};
+
+use cumulus_test_relay_sproof_builder::RelayStateSproofBuilder;
+
+use std::cell::RefCell;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+}
+
+thread_local! {
+	static BLOCKS_PER_RELAY_PARENT: RefCell<u32> = RefCell::new(1);
+	static UNINCLUDED_SEGMENT_CAPACITY: RefCell<u32> = RefCell::new(1);
+	static HANDLED_MESSAGES: RefCell<Vec<DownwardMessage>> = RefCell::new(Vec::new());
+}
+
+/// A `ConsensusHook` whose limits are set per test.
+pub struct TestConsensusHook;
+
+impl TestConsensusHook {
+	fn set(blocks_per_relay_parent: u32, unincluded_segment_capacity: u32) {
+		BLOCKS_PER_RELAY_PARENT.with(|v| *v.borrow_mut() = blocks_per_relay_parent);
+		UNINCLUDED_SEGMENT_CAPACITY.with(|v| *v.borrow_mut() = unincluded_segment_capacity);
+	}
+}
+
+impl ConsensusHook for TestConsensusHook {
+	fn blocks_per_relay_parent(_: u32) -> u32 {
+		BLOCKS_PER_RELAY_PARENT.with(|v| *v.borrow())
+	}
+
+	fn unincluded_segment_capacity() -> u32 {
+		UNINCLUDED_SEGMENT_CAPACITY.with(|v| *v.borrow())
+	}
+}
+
+/// A `DownwardMessageHandler` that stores all handled messages.
+pub struct SaveDownwardMessages;
+
+impl SaveDownwardMessages {
+	fn take() -> Vec<DownwardMessage> {
+		HANDLED_MESSAGES.with(|m| m.borrow_mut().drain(..).collect())
+	}
+}
+
+impl DownwardMessageHandler for SaveDownwardMessages {
+	fn handle_downward_message(message: DownwardMessage) {
+		HANDLED_MESSAGES.with(|m| m.borrow_mut().push(message));
+	}
+}
+
+impl Trait for Test {
+	type Event = ();
+	type DownwardMessageHandler = SaveDownwardMessages;
+	type ConsensusHook = TestConsensusHook;
+}
+
+type System = system::Module<Test>;
+type ParachainSystem = Module<Test>;
+
+fn new_test_ext() -> TestExternalities<Blake2Hasher> {
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap().0;
+	t.extend(GenesisConfig {
+		parachain_id: 200.into(),
+		validation_upgrade_delay: 5,
+		max_upward_message_count: 2,
+		max_upward_message_size: 8,
+	}.build_storage::<Test>().unwrap().0);
+	TestExternalities::new(t)
+}
+
+fn set_validation_data(
+	relay_parent_number: u32,
+	relay_storage_root: H256,
+) -> Result<(), &'static str> {
+	ParachainSystem::set_validation_data(
+		Origin::NONE,
+		ValidationData { relay_parent_number, relay_storage_root },
+	)
+}
+
+/// Finalize the current block and initialize the next one on top of the block with the
+/// given `parent_hash`.
+fn next_block(parent_hash: H256) {
+	let number = System::block_number();
+	ParachainSystem::on_finalize(number);
+
+	System::set_block_number(number + 1);
+	System::set_parent_hash(parent_hash);
+	ParachainSystem::on_initialize(number + 1);
+}
+
+#[test]
+fn set_validation_data_works() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::repeat_byte(1)));
+
+		assert_eq!(ParachainSystem::relay_parent_number(), 10);
+		assert_eq!(ParachainSystem::relay_storage_root(), H256::repeat_byte(1));
+		assert_eq!(ParachainSystem::relay_parent_blocks(), (10, 1));
+
+		ParachainSystem::on_finalize(1);
+	});
+}
+
+#[test]
+fn set_validation_data_requires_none_origin() {
+	with_externalities(&mut new_test_ext(), || {
+		assert!(ParachainSystem::set_validation_data(
+			Origin::signed(1),
+			ValidationData { relay_parent_number: 10, relay_storage_root: H256::zero() },
+		).is_err());
+		assert!(ParachainSystem::set_validation_data(
+			Origin::ROOT,
+			ValidationData { relay_parent_number: 10, relay_storage_root: H256::zero() },
+		).is_err());
+	});
+}
+
+#[test]
+fn set_validation_data_twice_fails() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_noop!(
+			set_validation_data(11, H256::zero()),
+			"ValidationData is already set in this block"
+		);
+	});
+}
+
+#[test]
+#[should_panic(expected = "ValidationData must be set in every block")]
+fn validation_data_is_required_in_every_block() {
+	with_externalities(&mut new_test_ext(), || {
+		ParachainSystem::on_finalize(1);
+	});
+}
+
+#[test]
+fn relay_parent_must_not_decrease() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		next_block(H256::repeat_byte(1));
+
+		assert_noop!(
+			set_validation_data(9, H256::zero()),
+			"The relay parent is older than the relay parent of the parent block"
+		);
+		assert_ok!(set_validation_data(11, H256::zero()));
+	});
+}
+
+#[test]
+fn blocks_on_the_same_relay_parent_need_the_same_storage_root() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(2, 1);
+
+		assert_ok!(set_validation_data(10, H256::repeat_byte(1)));
+		next_block(H256::repeat_byte(1));
+
+		assert_noop!(
+			set_validation_data(10, H256::repeat_byte(2)),
+			"The relay storage root does not match the one of the parent block"
+		);
+		assert_ok!(set_validation_data(10, H256::repeat_byte(1)));
+	});
+}
+
+#[test]
+fn consensus_hook_limits_the_blocks_per_relay_parent() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(2, 1);
+
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_eq!(ParachainSystem::remaining_capacity(), 1);
+		next_block(H256::repeat_byte(1));
+
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_eq!(ParachainSystem::relay_parent_blocks(), (10, 2));
+		assert_eq!(ParachainSystem::remaining_capacity(), 0);
+		next_block(H256::repeat_byte(2));
+
+		assert_noop!(
+			set_validation_data(10, H256::zero()),
+			"No capacity left for another block on the relay parent"
+		);
+
+		// A new relay parent resets the count.
+		assert_ok!(set_validation_data(11, H256::zero()));
+		assert_eq!(ParachainSystem::relay_parent_blocks(), (11, 1));
+	});
+}
+
+#[test]
+fn one_block_per_relay_parent_by_default() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		next_block(H256::repeat_byte(1));
+
+		assert_noop!(
+			set_validation_data(10, H256::zero()),
+			"No capacity left for another block on the relay parent"
+		);
+	});
+}
+
+#[test]
+fn check_inherent_compares_the_validation_data() {
+	let data = ValidationData { relay_parent_number: 10, relay_storage_root: H256::zero() };
+	let call = Call::<Test>::set_validation_data(data.clone());
+
+	let mut inherent_data = InherentData::new();
+	assert!(ParachainSystem::check_inherent(&call, &inherent_data).is_ok());
+
+	inherent_data.put_data(VALIDATION_DATA_IDENTIFIER, &data).unwrap();
+	assert!(ParachainSystem::check_inherent(&call, &inherent_data).is_ok());
+
+	let other = ValidationData { relay_parent_number: 11, ..data };
+	let mut inherent_data = InherentData::new();
+	inherent_data.put_data(VALIDATION_DATA_IDENTIFIER, &other).unwrap();
+	assert!(ParachainSystem::check_inherent(&call, &inherent_data).is_err());
+}
+
+fn downward_message(source: u32, data: &[u8]) -> DownwardMessage {
+	DownwardMessage { source: source.into(), data: data.to_vec() }
+}
+
+fn receive_downward_messages(messages: Vec<DownwardMessage>) -> Result<(), &'static str> {
+	ParachainSystem::receive_downward_messages(Origin::NONE, messages)
+}
+
+#[test]
+fn receive_downward_messages_works() {
+	with_externalities(&mut new_test_ext(), || {
+		let messages = vec![downward_message(100, b"hello"), downward_message(300, b"world")];
+
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(receive_downward_messages(messages.clone()));
+
+		assert_eq!(SaveDownwardMessages::take(), messages);
+		assert_eq!(ParachainSystem::processed_downward_messages(), 2);
+		assert_eq!(
+			ParachainSystem::collect_collation_info(&Default::default())
+				.processed_downward_messages,
+			2,
+		);
+
+		let mut expected = MessageQueueChain::default();
+		expected.extend_downward(b"hello").extend_downward(b"world");
+		assert_eq!(ParachainSystem::received_message_queue_chain(), expected);
+	});
+}
+
+#[test]
+fn message_queue_chain_continues_over_blocks() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(receive_downward_messages(vec![downward_message(100, b"hello")]));
+		next_block(H256::repeat_byte(1));
+
+		// The count of the last block is reset, the total count is kept.
+		assert_eq!(
+			ParachainSystem::collect_collation_info(&Default::default())
+				.processed_downward_messages,
+			0,
+		);
+
+		assert_ok!(set_validation_data(11, H256::zero()));
+		assert_ok!(receive_downward_messages(vec![downward_message(100, b"world")]));
+
+		assert_eq!(ParachainSystem::processed_downward_messages(), 2);
+		let mut expected = MessageQueueChain::default();
+		expected.extend_downward(b"hello").extend_downward(b"world");
+		assert_eq!(ParachainSystem::received_message_queue_chain(), expected);
+	});
+}
+
+#[test]
+fn receive_no_downward_messages_keeps_the_chain() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(receive_downward_messages(Vec::new()));
+
+		assert!(SaveDownwardMessages::take().is_empty());
+		assert_eq!(ParachainSystem::processed_downward_messages(), 0);
+		assert_eq!(ParachainSystem::received_message_queue_chain(), Default::default());
+	});
+}
+
+#[test]
+fn receive_downward_messages_requires_validation_data() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_noop!(
+			receive_downward_messages(vec![downward_message(100, b"hello")]),
+			"ValidationData must be set first"
+		);
+		assert!(SaveDownwardMessages::take().is_empty());
+	});
+}
+
+#[test]
+fn receive_downward_messages_twice_fails() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(receive_downward_messages(Vec::new()));
+		assert_noop!(
+			receive_downward_messages(vec![downward_message(100, b"hello")]),
+			"Downward messages are already received in this block"
+		);
+	});
+}
+
+#[test]
+fn receive_downward_messages_requires_none_origin() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert!(ParachainSystem::receive_downward_messages(
+			Origin::signed(1),
+			vec![downward_message(100, b"hello")],
+		).is_err());
+		assert!(SaveDownwardMessages::take().is_empty());
+	});
+}
+
+fn upward_messages_in_storage() -> Option<Vec<Vec<u8>>> {
+	runtime_io::storage(UPWARD_MESSAGES_KEY).map(|m| Decode::decode(&mut &m[..]).unwrap())
+}
+
+#[test]
+fn send_upward_message_works() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(ParachainSystem::send_upward_message(b"hello".to_vec()));
+		assert_ok!(ParachainSystem::send_upward_message(b"world".to_vec()));
+		assert_eq!(
+			ParachainSystem::pending_upward_messages(),
+			vec![b"hello".to_vec(), b"world".to_vec()],
+		);
+
+		ParachainSystem::on_finalize(1);
+
+		assert!(ParachainSystem::pending_upward_messages().is_empty());
+		assert_eq!(upward_messages_in_storage(), Some(vec![b"hello".to_vec(), b"world".to_vec()]));
+		assert_eq!(
+			ParachainSystem::collect_collation_info(&Default::default()).upward_messages,
+			vec![b"hello".to_vec(), b"world".to_vec()],
+		);
+	});
+}
+
+#[test]
+fn upward_messages_are_removed_in_the_next_block() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::zero()));
+		assert_ok!(ParachainSystem::send_upward_message(b"hello".to_vec()));
+		next_block(H256::repeat_byte(1));
+
+		assert_ok!(set_validation_data(11, H256::zero()));
+		ParachainSystem::on_finalize(2);
+
+		assert_eq!(upward_messages_in_storage(), None);
+		assert!(ParachainSystem::collect_collation_info(&Default::default())
+			.upward_messages
+			.is_empty());
+	});
+}
+
+#[test]
+fn send_upward_message_rejects_too_large_messages() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(ParachainSystem::send_upward_message(vec![0; 8]));
+		assert_noop!(
+			ParachainSystem::send_upward_message(vec![0; 9]),
+			"The upward message is too large"
+		);
+	});
+}
+
+#[test]
+fn send_upward_message_rejects_too_many_messages() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(ParachainSystem::send_upward_message(b"1".to_vec()));
+		assert_ok!(ParachainSystem::send_upward_message(b"2".to_vec()));
+		assert_noop!(
+			ParachainSystem::send_upward_message(b"3".to_vec()),
+			"Too many upward messages in this block"
+		);
+		assert_eq!(ParachainSystem::pending_upward_messages().len(), 2);
+	});
+}
+
+fn head_hash(head: &[u8]) -> H256 {
+	BlakeTwo256::hash(head)
+}
+
+fn set_parachain_inherent_data(sproof: RelayStateSproofBuilder) -> Result<(), &'static str> {
+	let (_, relay_chain_state) = sproof.into_state_root_and_proof();
+	ParachainSystem::set_parachain_inherent_data(
+		Origin::NONE,
+		ParachainInherentData { relay_chain_state },
+	)
+}
+
+/// Set the validation data and the parachain inherent data of a relay chain state in which
+/// `included_head` is the head of the parachain.
+fn set_relay_state(relay_parent_number: u32, included_head: Option<&[u8]>) {
+	let sproof = RelayStateSproofBuilder {
+		included_para_head: included_head.map(|h| h.to_vec()),
+		..Default::default()
+	};
+	let (relay_storage_root, _) = sproof.clone().into_state_root_and_proof();
+
+	assert_ok!(set_validation_data(relay_parent_number, relay_storage_root));
+	assert_ok!(set_parachain_inherent_data(sproof));
+}
+
+fn unincluded_head_hashes() -> Vec<Option<H256>> {
+	ParachainSystem::unincluded_segment().into_iter().map(|a| a.head_hash).collect()
+}
+
+#[test]
+fn unincluded_segment_is_pruned_by_the_included_head() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(1, 3);
+		System::set_parent_hash(head_hash(b"head0"));
+
+		set_relay_state(10, Some(b"head0"));
+		next_block(head_hash(b"head1"));
+		assert_eq!(unincluded_head_hashes(), vec![None]);
+
+		set_relay_state(11, Some(b"head0"));
+		assert_eq!(unincluded_head_hashes(), vec![Some(head_hash(b"head1"))]);
+		next_block(head_hash(b"head2"));
+		assert_eq!(unincluded_head_hashes(), vec![Some(head_hash(b"head1")), None]);
+
+		// The relay chain included the first block.
+		set_relay_state(12, Some(b"head1"));
+		assert_eq!(unincluded_head_hashes(), vec![Some(head_hash(b"head2"))]);
+		next_block(head_hash(b"head3"));
+		assert_eq!(unincluded_head_hashes(), vec![Some(head_hash(b"head2")), None]);
+
+		// The relay chain included both blocks.
+		set_relay_state(13, Some(b"head3"));
+		assert!(unincluded_head_hashes().is_empty());
+	});
+}
+
+#[test]
+#[should_panic(expected = "The unincluded segment exceeds its capacity")]
+fn unincluded_segment_can_not_exceed_its_capacity() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(1, 1);
+		System::set_parent_hash(head_hash(b"head0"));
+
+		set_relay_state(10, Some(b"head0"));
+		next_block(head_hash(b"head1"));
+
+		// The parent is not included yet.
+		set_relay_state(11, Some(b"head0"));
+		ParachainSystem::on_finalize(2);
+	});
+}
+
+#[test]
+fn unincluded_segment_of_capacity_one_requires_an_included_parent() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(1, 1);
+		System::set_parent_hash(head_hash(b"head0"));
+
+		set_relay_state(10, Some(b"head0"));
+		next_block(head_hash(b"head1"));
+
+		set_relay_state(11, Some(b"head1"));
+		next_block(head_hash(b"head2"));
+		assert_eq!(unincluded_head_hashes(), vec![None]);
+	});
+}
+
+#[test]
+fn unincluded_segment_is_reset_without_parachain_inherent_data() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(1, 3);
+
+		set_relay_state(10, None);
+		next_block(head_hash(b"head1"));
+		assert_eq!(unincluded_head_hashes().len(), 1);
+
+		assert_ok!(set_validation_data(11, H256::zero()));
+		next_block(head_hash(b"head2"));
+		assert!(unincluded_head_hashes().is_empty());
+	});
+}
+
+#[test]
+fn unincluded_segment_tracks_the_used_bandwidth() {
+	with_externalities(&mut new_test_ext(), || {
+		TestConsensusHook::set(1, 3);
+
+		set_relay_state(10, None);
+		assert_ok!(ParachainSystem::send_upward_message(b"hello".to_vec()));
+		assert_ok!(receive_downward_messages(vec![downward_message(100, b"hello")]));
+		next_block(head_hash(b"head1"));
+
+		assert_eq!(
+			ParachainSystem::unincluded_bandwidth(),
+			UsedBandwidth { upward_messages: 1, upward_bytes: 5, downward_messages: 1 },
+		);
+
+		// The message of the unincluded parent counts against the limit of two messages.
+		set_relay_state(11, None);
+		assert_ok!(ParachainSystem::send_upward_message(b"world".to_vec()));
+		assert_noop!(
+			ParachainSystem::send_upward_message(b"again".to_vec()),
+			"Too many upward messages in this block"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_stores_the_relay_chain_state() {
+	with_externalities(&mut new_test_ext(), || {
+		let sproof = RelayStateSproofBuilder {
+			one_epoch_ago_randomness: Some(H256::repeat_byte(1)),
+			two_epochs_ago_randomness: Some(H256::repeat_byte(2)),
+			current_slot: Some(42),
+			..Default::default()
+		};
+		let (relay_storage_root, relay_chain_state) = sproof.clone().into_state_root_and_proof();
+
+		assert_ok!(set_validation_data(10, relay_storage_root));
+		assert!(ParachainSystem::relay_state_proof().is_none());
+		assert_ok!(set_parachain_inherent_data(sproof));
+
+		assert_eq!(ParachainSystem::one_epoch_ago_randomness(), Some(H256::repeat_byte(1)));
+		assert_eq!(ParachainSystem::two_epochs_ago_randomness(), Some(H256::repeat_byte(2)));
+		assert_eq!(ParachainSystem::relay_slot(), 42);
+		assert_eq!(ParachainSystem::relay_chain_state(), relay_chain_state);
+		assert_eq!(
+			ParachainSystem::relay_state_proof().unwrap().current_slot(),
+			Ok(Some(42)),
+		);
+		assert_eq!(
+			<ParachainSystem as Randomness<H256>>::random(b"subject"),
+			BlakeTwo256::hash(&(H256::repeat_byte(1), &b"subject"[..]).encode()),
+		);
+
+		// The proof is only available in the block that set it, the values are kept.
+		next_block(H256::repeat_byte(1));
+		assert!(ParachainSystem::relay_state_proof().is_none());
+		assert!(ParachainSystem::relay_chain_state().is_empty());
+		assert_eq!(ParachainSystem::one_epoch_ago_randomness(), Some(H256::repeat_byte(1)));
+		assert_eq!(ParachainSystem::relay_slot(), 42);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_rejects_a_proof_of_another_root() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_ok!(set_validation_data(10, H256::repeat_byte(1)));
+		assert_noop!(
+			set_parachain_inherent_data(RelayStateSproofBuilder {
+				current_slot: Some(42),
+				..Default::default()
+			}),
+			"The relay chain state proof does not match the relay storage root"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_rejects_an_incomplete_proof() {
+	with_externalities(&mut new_test_ext(), || {
+		// The head is large enough to be stored in its own trie node.
+		let sproof = RelayStateSproofBuilder {
+			included_para_head: Some(vec![1; 64]),
+			current_slot: Some(42),
+			..Default::default()
+		};
+		let (relay_storage_root, proof) = sproof.into_state_root_and_proof();
+		let relay_chain_state = proof.into_iter()
+			.filter(|node| BlakeTwo256::hash(node) == relay_storage_root)
+			.collect();
+
+		assert_ok!(set_validation_data(10, relay_storage_root));
+		assert_noop!(
+			ParachainSystem::set_parachain_inherent_data(
+				Origin::NONE,
+				ParachainInherentData { relay_chain_state },
+			),
+			"The relay chain state proof does not contain the parachain head"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_rejects_undecodable_values() {
+	with_externalities(&mut new_test_ext(), || {
+		// The randomness is read before the slot, but must not be stored either.
+		let sproof = RelayStateSproofBuilder {
+			one_epoch_ago_randomness: Some(H256::repeat_byte(1)),
+			additional_key_values: vec![
+				(cumulus_runtime::relay_chain_keys::current_slot(), vec![1, 2, 3]),
+			],
+			..Default::default()
+		};
+		let (relay_storage_root, _) = sproof.clone().into_state_root_and_proof();
+
+		assert_ok!(set_validation_data(10, relay_storage_root));
+		assert_noop!(
+			set_parachain_inherent_data(sproof),
+			"The relay chain state proof does not contain the slot"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_requires_validation_data() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_noop!(
+			set_parachain_inherent_data(Default::default()),
+			"ValidationData must be set first"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_twice_fails() {
+	with_externalities(&mut new_test_ext(), || {
+		set_relay_state(10, None);
+		assert_noop!(
+			set_parachain_inherent_data(Default::default()),
+			"ParachainInherentData is already set in this block"
+		);
+	});
+}
+
+#[test]
+fn set_parachain_inherent_data_requires_none_origin() {
+	with_externalities(&mut new_test_ext(), || {
+		let sproof = RelayStateSproofBuilder::default();
+		let (relay_storage_root, relay_chain_state) = sproof.into_state_root_and_proof();
+		assert_ok!(set_validation_data(10, relay_storage_root));
+
+		assert!(ParachainSystem::set_parachain_inherent_data(
+			Origin::signed(1),
+			ParachainInherentData { relay_chain_state },
+		).is_err());
+	});
+}
@@ -0,0 +1,166 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Record the witness data of parachain blocks while building them.
+//!
+//! The witness data contains all trie nodes that are accessed while building a block. This is
+//! exactly the data that `validate_block` requires to validate the block on the relay chain.
+
+use substrate_client::{
+	Client, CallExecutor, backend::Backend,
+	block_builder::{BlockBuilder, api::BlockBuilder as BlockBuilderApi},
+	error::{Error as ClientError, Result as ClientResult},
+};
+use substrate_primitives::{Blake2Hasher, H256};
+use sr_primitives::generic::BlockId;
+use sr_primitives::traits::{Block as BlockT, Header as HeaderT, ProvideRuntimeApi, DigestFor};
+use cumulus_runtime::{ParachainBlockData, WitnessData};
+
+use parity_codec::Encode;
+
+use std::sync::Arc;
+
+/// The encoding of the recorded witness data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WitnessEncoding {
+	/// Keep all recorded trie nodes as they are.
+	Full,
+	/// Use the compact encoding, which omits the hashes that can be recalculated.
+	Compact,
+}
+
+impl Default for WitnessEncoding {
+	fn default() -> Self {
+		WitnessEncoding::Compact
+	}
+}
+
+/// A block that was built while recording its witness data.
+pub struct RecordedBlock<Block: BlockT> {
+	/// The block that was built.
+	pub block: Block,
+	/// The witness data that was recorded while building the block.
+	pub witness_data: WitnessData,
+	/// The storage root of the parent block, which is the root of the `witness_data`.
+	pub witness_data_storage_root: Block::Hash,
+}
+
+impl<Block: BlockT> RecordedBlock<Block> {
+	/// Convert into the `ParachainBlockData` that is passed to `validate_block`.
+	///
+	/// The first `validator_inherents` extrinsics of the block are the inherents that are
+	/// injected by the validators, e.g. the `PolkadotInherent`. They are removed, as the
+	/// validators inject them again.
+	pub fn into_parachain_block_data(
+		self,
+		validator_inherents: usize,
+	) -> ParachainBlockData<Block> {
+		let (header, mut extrinsics) = self.block.deconstruct();
+		let validator_inherents = validator_inherents.min(extrinsics.len());
+		extrinsics.drain(..validator_inherents);
+
+		ParachainBlockData::new(
+			header,
+			extrinsics,
+			self.witness_data,
+			self.witness_data_storage_root,
+		)
+	}
+}
+
+/// Builds blocks on top of a Substrate client while recording all storage accesses.
+pub struct ProofRecorder<B, E, Block: BlockT, RA> {
+	client: Arc<Client<B, E, Block, RA>>,
+	encoding: WitnessEncoding,
+}
+
+impl<B, E, Block, RA> ProofRecorder<B, E, Block, RA> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher> + Clone + Send + Sync,
+	Block: BlockT<Hash=H256>,
+	RA: Send + Sync,
+	Client<B, E, Block, RA>: ProvideRuntimeApi,
+	<Client<B, E, Block, RA> as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
+{
+	/// Create a new instance that records the witness data in the default encoding.
+	pub fn new(client: Arc<Client<B, E, Block, RA>>) -> Self {
+		Self { client, encoding: Default::default() }
+	}
+
+	/// Returns the client the blocks are built on.
+	pub fn client(&self) -> &Arc<Client<B, E, Block, RA>> {
+		&self.client
+	}
+
+	/// Use the given encoding for the recorded witness data.
+	pub fn with_encoding(mut self, encoding: WitnessEncoding) -> Self {
+		self.encoding = encoding;
+		self
+	}
+
+	/// Build a new block on top of `parent` and record its witness data.
+	///
+	/// `build` is called with the `BlockBuilder` to push the extrinsics of the block.
+	pub fn build<F>(
+		&self,
+		parent: &BlockId<Block>,
+		inherent_digests: DigestFor<Block>,
+		build: F,
+	) -> ClientResult<RecordedBlock<Block>> where
+		F: FnOnce(&mut BlockBuilder<Block, Client<B, E, Block, RA>>) -> ClientResult<()>,
+	{
+		let witness_data_storage_root = *self.client.header(parent)?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("{}", parent)))?
+			.state_root();
+
+		let mut builder = self.client.new_block_at_with_proof_recording(parent, inherent_digests)?;
+		build(&mut builder)?;
+
+		let (block, proof) = builder.bake_and_extract_proof()?;
+		let proof = proof.ok_or_else(|| ClientError::Msg("Proof recording is not enabled".into()))?;
+
+		let witness_data = match self.encoding {
+			WitnessEncoding::Full => proof.into(),
+			WitnessEncoding::Compact =>
+				WitnessData::compact::<Blake2Hasher>(proof, &witness_data_storage_root),
+		};
+
+		Ok(RecordedBlock { block, witness_data, witness_data_storage_root })
+	}
+
+	/// Estimate the size of the witness data that is required for `extrinsic` on top of
+	/// `parent`.
+	///
+	/// Builds an empty block and a block with only `extrinsic` on top of `parent`, without
+	/// importing them, and returns the difference of the encoded sizes of their witness data.
+	/// Fails if the extrinsic can not be applied.
+	pub fn estimate_proof_size(
+		&self,
+		parent: &BlockId<Block>,
+		extrinsic: <Block as BlockT>::Extrinsic,
+	) -> ClientResult<usize> {
+		let empty = self.build(parent, Default::default(), |_| Ok(()))?
+			.witness_data
+			.encode()
+			.len();
+		let with_extrinsic = self.build(parent, Default::default(), |b| b.push(extrinsic))?
+			.witness_data
+			.encode()
+			.len();
+
+		Ok(with_extrinsic.saturating_sub(empty))
+	}
+}
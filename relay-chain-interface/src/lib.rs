@@ -0,0 +1,233 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Access to the Polkadot relay chain.
+//!
+//! All components of a parachain node access the relay chain through `RelayChainInterface`.
+//! `RelayChainInProcessInterface` implements it on top of a relay chain `Client` that runs in
+//! the same process, `RelayChainRpcInterface` on top of the JSON-RPC interface of an external
+//! relay chain node.
+
+use substrate_client::{backend::Backend, CallExecutor, Client, BlockchainEvents};
+use substrate_client::error::Error as ClientError;
+use substrate_primitives::{Blake2Hasher, storage::StorageKey};
+use sr_primitives::generic::BlockId;
+use sr_primitives::traits::{Header as HeaderT, ProvideRuntimeApi};
+use polkadot_primitives::{Hash as PHash, Header as PHeader, Block as PBlock, SessionKey};
+use polkadot_primitives::parachain::{Id as ParaId, ParachainHost};
+use cumulus_runtime::{ValidationData, relay_chain_keys};
+
+use futures::prelude::*;
+
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+mod rpc;
+
+pub use rpc::RelayChainRpcInterface;
+
+/// An error that occurred while accessing the relay chain.
+#[derive(Debug)]
+pub enum RelayChainError {
+	/// An error of the relay chain client.
+	Client(ClientError),
+	/// A runtime api call failed.
+	RuntimeApi(String),
+	/// An error of the JSON-RPC connection to the relay chain node.
+	Rpc(String),
+}
+
+impl fmt::Display for RelayChainError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RelayChainError::Client(e) => write!(f, "Relay chain client error: {}", e),
+			RelayChainError::RuntimeApi(e) => write!(f, "Relay chain runtime api error: {}", e),
+			RelayChainError::Rpc(e) => write!(f, "Relay chain RPC error: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for RelayChainError {}
+
+impl From<ClientError> for RelayChainError {
+	fn from(e: ClientError) -> Self {
+		RelayChainError::Client(e)
+	}
+}
+
+/// The result of accessing the relay chain.
+pub type RelayChainResult<T> = Result<T, RelayChainError>;
+
+/// A stream of relay chain headers.
+pub type HeaderStream = Box<dyn Stream<Item=PHeader, Error=RelayChainError> + Send>;
+
+/// Returns the storage key of the head of the given parachain in the relay chain state.
+pub fn parachain_head_key(para_id: ParaId) -> StorageKey {
+	StorageKey(relay_chain_keys::para_head(para_id.into()))
+}
+
+/// Provides all access to the relay chain that is required by a parachain node.
+///
+/// This is expected to be a lightweight handle.
+pub trait RelayChainInterface: Send + Sync {
+	/// Returns the hash of the best relay chain block.
+	fn best_block_hash(&self) -> RelayChainResult<PHash>;
+
+	/// Returns the hash of the last finalized relay chain block.
+	fn finalized_block_hash(&self) -> RelayChainResult<PHash>;
+
+	/// Returns the header of the given relay chain block.
+	fn header(&self, at: PHash) -> RelayChainResult<Option<PHeader>>;
+
+	/// Returns the encoded head of the given parachain in the state of the given relay chain
+	/// block.
+	fn parachain_head(&self, at: PHash, para_id: ParaId) -> RelayChainResult<Option<Vec<u8>>>;
+
+	/// Returns the validators at the given relay chain block.
+	fn validators(&self, at: PHash) -> RelayChainResult<Vec<SessionKey>>;
+
+	/// Returns the proof of the given `keys` in the state of the given relay chain block.
+	///
+	/// The proof contains the trie nodes of all keys, without duplicates.
+	fn prove_read(&self, at: PHash, keys: &[Vec<u8>]) -> RelayChainResult<Vec<Vec<u8>>>;
+
+	/// Returns the `ValidationData` for a parachain block built on the given relay parent.
+	fn validation_data(&self, relay_parent: PHash) -> RelayChainResult<Option<ValidationData>> {
+		Ok(self.header(relay_parent)?.map(|h| ValidationData {
+			relay_parent_number: *h.number(),
+			relay_storage_root: *h.state_root(),
+		}))
+	}
+
+	/// Returns a stream of the headers of all new best relay chain blocks.
+	fn new_best_notification_stream(&self) -> HeaderStream;
+
+	/// Returns a stream of the headers of all finalized relay chain blocks.
+	fn finality_notification_stream(&self) -> HeaderStream;
+}
+
+impl<T: RelayChainInterface + ?Sized> RelayChainInterface for Arc<T> {
+	fn best_block_hash(&self) -> RelayChainResult<PHash> {
+		(**self).best_block_hash()
+	}
+
+	fn finalized_block_hash(&self) -> RelayChainResult<PHash> {
+		(**self).finalized_block_hash()
+	}
+
+	fn header(&self, at: PHash) -> RelayChainResult<Option<PHeader>> {
+		(**self).header(at)
+	}
+
+	fn parachain_head(&self, at: PHash, para_id: ParaId) -> RelayChainResult<Option<Vec<u8>>> {
+		(**self).parachain_head(at, para_id)
+	}
+
+	fn validators(&self, at: PHash) -> RelayChainResult<Vec<SessionKey>> {
+		(**self).validators(at)
+	}
+
+	fn prove_read(&self, at: PHash, keys: &[Vec<u8>]) -> RelayChainResult<Vec<Vec<u8>>> {
+		(**self).prove_read(at, keys)
+	}
+
+	fn validation_data(&self, relay_parent: PHash) -> RelayChainResult<Option<ValidationData>> {
+		(**self).validation_data(relay_parent)
+	}
+
+	fn new_best_notification_stream(&self) -> HeaderStream {
+		(**self).new_best_notification_stream()
+	}
+
+	fn finality_notification_stream(&self) -> HeaderStream {
+		(**self).finality_notification_stream()
+	}
+}
+
+/// A `RelayChainInterface` on top of a relay chain `Client` that runs in the same process.
+pub struct RelayChainInProcessInterface<B, E, RA> {
+	client: Arc<Client<B, E, PBlock, RA>>,
+}
+
+impl<B, E, RA> RelayChainInProcessInterface<B, E, RA> {
+	/// Create a new instance.
+	pub fn new(client: Arc<Client<B, E, PBlock, RA>>) -> Self {
+		Self { client }
+	}
+}
+
+impl<B, E, RA> Clone for RelayChainInProcessInterface<B, E, RA> {
+	fn clone(&self) -> Self {
+		Self { client: self.client.clone() }
+	}
+}
+
+impl<B, E, RA> RelayChainInterface for RelayChainInProcessInterface<B, E, RA> where
+	B: Backend<PBlock, Blake2Hasher> + Send + Sync + 'static,
+	E: CallExecutor<PBlock, Blake2Hasher> + Send + Sync + 'static,
+	RA: Send + Sync + 'static,
+	Client<B, E, PBlock, RA>: ProvideRuntimeApi,
+	<Client<B, E, PBlock, RA> as ProvideRuntimeApi>::Api: ParachainHost<PBlock>,
+{
+	fn best_block_hash(&self) -> RelayChainResult<PHash> {
+		Ok(self.client.info()?.chain.best_hash)
+	}
+
+	fn finalized_block_hash(&self) -> RelayChainResult<PHash> {
+		Ok(self.client.info()?.chain.finalized_hash)
+	}
+
+	fn header(&self, at: PHash) -> RelayChainResult<Option<PHeader>> {
+		Ok(self.client.header(&BlockId::hash(at))?)
+	}
+
+	fn parachain_head(&self, at: PHash, para_id: ParaId) -> RelayChainResult<Option<Vec<u8>>> {
+		let head = self.client.storage(&BlockId::hash(at), &parachain_head_key(para_id))?;
+		Ok(head.map(|h| h.0))
+	}
+
+	fn validators(&self, at: PHash) -> RelayChainResult<Vec<SessionKey>> {
+		self.client.runtime_api()
+			.validators(&BlockId::hash(at))
+			.map_err(|e| RelayChainError::RuntimeApi(format!("{:?}", e)))
+	}
+
+	fn prove_read(&self, at: PHash, keys: &[Vec<u8>]) -> RelayChainResult<Vec<Vec<u8>>> {
+		let at = BlockId::hash(at);
+		let mut nodes = BTreeSet::new();
+		for key in keys {
+			nodes.extend(self.client.read_proof(&at, key)?);
+		}
+
+		Ok(nodes.into_iter().collect())
+	}
+
+	fn new_best_notification_stream(&self) -> HeaderStream {
+		let s = self.client.import_notification_stream()
+			.map_err(|()| panic!("unbounded receivers never yield errors; qed"))
+			.filter(|n| n.is_new_best)
+			.map(|n| n.header);
+
+		Box::new(s)
+	}
+
+	fn finality_notification_stream(&self) -> HeaderStream {
+		let s = self.client.finality_notification_stream()
+			.map_err(|()| panic!("unbounded receivers never yield errors; qed"))
+			.map(|n| n.header);
+
+		Box::new(s)
+	}
+}
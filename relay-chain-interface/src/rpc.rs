@@ -0,0 +1,339 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A `RelayChainInterface` that talks to external relay chain nodes over WebSocket JSON-RPC.
+
+use crate::{
+	RelayChainInterface, RelayChainError, RelayChainResult, HeaderStream, parachain_head_key,
+};
+use substrate_primitives::{Bytes, storage::StorageData};
+use polkadot_primitives::{Hash as PHash, Header as PHeader, SessionKey};
+use polkadot_primitives::parachain::Id as ParaId;
+
+use futures::{prelude::*, future, stream, sync::oneshot, try_ready};
+use jsonrpc_core::Params;
+use jsonrpc_core_client::{RawClient, transports::ws};
+use parity_codec::Decode;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::{runtime::{Runtime, TaskExecutor}, timer::Timeout};
+use log::warn;
+
+use std::{sync::Arc, time::Duration};
+
+/// The time after which an endpoint that did not answer a call is considered stalled.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// The time after which a header subscription that did not yield a header is considered
+/// stalled.
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+type BoxFuture<T> = Box<dyn Future<Item=T, Error=RelayChainError> + Send>;
+type BoxStream<T> = Box<dyn Stream<Item=T, Error=RelayChainError> + Send>;
+
+fn rpc_error<E: std::fmt::Debug>(e: E) -> RelayChainError {
+	RelayChainError::Rpc(format!("{:?}", e))
+}
+
+/// Call the given RPC `method` on `client`.
+fn call_method(client: &RawClient, method: &str, params: Params) -> BoxFuture<Value> {
+	Box::new(Timeout::new(client.call_method(method, params), CALL_TIMEOUT).map_err(rpc_error))
+}
+
+/// Connect to the endpoint at `url` and check that it is healthy.
+fn connect(url: &str) -> BoxFuture<RawClient> {
+	let url = match url.parse() {
+		Ok(url) => url,
+		Err(e) => return Box::new(future::err(rpc_error(e))),
+	};
+
+	let f = ws::connect::<RawClient>(&url)
+		.map_err(rpc_error)
+		.and_then(|client| call_method(&client, "system_health", Params::None).map(|_| client));
+
+	Box::new(f)
+}
+
+/// The connection to one of the endpoints.
+#[derive(Clone)]
+struct Connection {
+	/// The index of the endpoint in the list of URLs.
+	index: usize,
+	client: RawClient,
+}
+
+struct Inner {
+	urls: Vec<String>,
+	connection: RwLock<Connection>,
+}
+
+impl Inner {
+	/// Connect to the first healthy endpoint, starting with the one after `failed`.
+	fn connect_next(urls: &[String], failed: usize) -> BoxFuture<Connection> {
+		let indices = (1..=urls.len()).map(|i| (failed + i) % urls.len()).collect::<Vec<_>>();
+		let urls = urls.to_vec();
+
+		let f = stream::iter_ok(indices)
+			.and_then(move |index| {
+				let url = urls[index].clone();
+				connect(&url).then(move |r| match r {
+					Ok(client) => Ok(Some(Connection { index, client })),
+					Err(e) => {
+						warn!("Relay chain RPC endpoint {} is not available: {}", url, e);
+						Ok(None)
+					},
+				})
+			})
+			.filter_map(|c| c)
+			.into_future()
+			.map_err(|(e, _)| e)
+			.and_then(|(connection, _)| {
+				connection.ok_or_else(|| {
+					RelayChainError::Rpc("No relay chain RPC endpoint is available".into())
+				})
+			});
+
+		Box::new(f)
+	}
+
+	/// Replace the connection to the endpoint `failed` with a connection to the next healthy
+	/// endpoint.
+	fn reconnect(self: &Arc<Self>, failed: usize) -> BoxFuture<Connection> {
+		let current = self.connection.read().clone();
+		if current.index != failed {
+			// Someone else already switched to another endpoint.
+			return Box::new(future::ok(current))
+		}
+
+		let this = self.clone();
+		let f = Self::connect_next(&self.urls, failed).map(move |connection| {
+			*this.connection.write() = connection.clone();
+			connection
+		});
+
+		Box::new(f)
+	}
+
+	/// Subscribe with the given `methods` on the current connection, or if `failed` is given,
+	/// on the next healthy endpoint.
+	fn subscribe(
+		self: &Arc<Self>,
+		methods: SubscriptionMethods,
+		failed: Option<usize>,
+	) -> BoxFuture<(usize, BoxStream<Value>)> {
+		let connection = match failed {
+			Some(failed) => self.reconnect(failed),
+			None => Box::new(future::ok(self.connection.read().clone())),
+		};
+
+		let f = connection.and_then(move |Connection { index, client }| {
+			client.subscribe(methods.subscribe, Params::None, methods.topic, methods.unsubscribe)
+				.map_err(rpc_error)
+				.map(move |s| {
+					let s = Timeout::new(s.map_err(rpc_error), SUBSCRIPTION_TIMEOUT)
+						.map_err(rpc_error);
+					(index, Box::new(s) as BoxStream<_>)
+				})
+		});
+
+		Box::new(f)
+	}
+}
+
+/// The RPC methods of a subscription.
+#[derive(Clone, Copy)]
+struct SubscriptionMethods {
+	subscribe: &'static str,
+	topic: &'static str,
+	unsubscribe: &'static str,
+}
+
+enum SubscriptionState {
+	Subscribing(BoxFuture<(usize, BoxStream<Value>)>),
+	Active(usize, BoxStream<Value>),
+}
+
+/// A header subscription that subscribes again on the next healthy endpoint when the current
+/// endpoint fails or stalls.
+struct HeaderSubscription {
+	inner: Arc<Inner>,
+	executor: TaskExecutor,
+	methods: SubscriptionMethods,
+	state: SubscriptionState,
+}
+
+impl HeaderSubscription {
+	fn subscribe(&self, failed: Option<usize>) -> SubscriptionState {
+		let f = oneshot::spawn(self.inner.subscribe(self.methods, failed), &self.executor);
+		SubscriptionState::Subscribing(Box::new(f))
+	}
+}
+
+impl Stream for HeaderSubscription {
+	type Item = PHeader;
+	type Error = RelayChainError;
+
+	fn poll(&mut self) -> Poll<Option<PHeader>, RelayChainError> {
+		loop {
+			let next = match self.state {
+				SubscriptionState::Subscribing(ref mut f) => {
+					let (index, s) = try_ready!(f.poll());
+					SubscriptionState::Active(index, s)
+				},
+				SubscriptionState::Active(index, ref mut s) => match s.poll() {
+					Ok(Async::Ready(Some(header))) => {
+						let header = serde_json::from_value(header).map_err(rpc_error)?;
+						return Ok(Async::Ready(Some(header)))
+					},
+					Ok(Async::NotReady) => return Ok(Async::NotReady),
+					Ok(Async::Ready(None)) | Err(_) => {
+						warn!(
+							"Relay chain RPC subscription {} on {} failed, subscribing again",
+							self.methods.subscribe,
+							self.inner.urls[index],
+						);
+						self.subscribe(Some(index))
+					},
+				},
+			};
+			self.state = next;
+		}
+	}
+}
+
+/// A `RelayChainInterface` on top of the JSON-RPC interface of relay chain nodes.
+///
+/// Running a full relay chain node in the same process is not required for a collator. The
+/// connection is driven by a runtime that is owned by this instance, the calls block the
+/// current thread until the node answered.
+///
+/// Multiple endpoints can be given. Only one of them is used at a time, if it fails or
+/// stalls, the next healthy endpoint is used and the header subscriptions are renewed.
+pub struct RelayChainRpcInterface {
+	inner: Arc<Inner>,
+	executor: TaskExecutor,
+	_runtime: Runtime,
+}
+
+impl RelayChainRpcInterface {
+	/// Connect to the first healthy relay chain node of the given WebSocket `urls`.
+	pub fn new(urls: Vec<String>) -> RelayChainResult<Self> {
+		if urls.is_empty() {
+			return Err(RelayChainError::Rpc("No relay chain RPC URL given".into()))
+		}
+
+		let runtime = Runtime::new().map_err(rpc_error)?;
+		let executor = runtime.executor();
+		let connection = oneshot::spawn(Inner::connect_next(&urls, urls.len() - 1), &executor)
+			.wait()?;
+
+		let inner = Arc::new(Inner { urls, connection: RwLock::new(connection) });
+		Ok(Self { inner, executor, _runtime: runtime })
+	}
+
+	/// Call the given RPC `method` and deserialize the result.
+	///
+	/// If the current endpoint fails, the call is repeated on the next healthy endpoint.
+	fn call<T: DeserializeOwned>(&self, method: &str, params: Vec<Value>) -> RelayChainResult<T> {
+		let Connection { index, client } = self.inner.connection.read().clone();
+		let result = oneshot::spawn(
+			call_method(&client, method, Params::Array(params.clone())),
+			&self.executor,
+		).wait();
+
+		let result = match result {
+			Ok(result) => result,
+			Err(e) => {
+				let url = &self.inner.urls[index];
+				warn!("Relay chain RPC call {} on {} failed: {}", method, url, e);
+				let method = method.to_owned();
+				let f = self.inner.reconnect(index).and_then(move |connection| {
+					call_method(&connection.client, &method, Params::Array(params))
+				});
+				oneshot::spawn(f, &self.executor).wait()?
+			},
+		};
+
+		serde_json::from_value(result).map_err(rpc_error)
+	}
+
+	/// Subscribe to the headers that are yielded by the given RPC subscription.
+	fn subscribe_headers(&self, methods: SubscriptionMethods) -> HeaderStream {
+		let mut subscription = HeaderSubscription {
+			inner: self.inner.clone(),
+			executor: self.executor.clone(),
+			methods,
+			state: SubscriptionState::Subscribing(Box::new(future::empty())),
+		};
+		subscription.state = subscription.subscribe(None);
+
+		Box::new(subscription)
+	}
+}
+
+impl RelayChainInterface for RelayChainRpcInterface {
+	fn best_block_hash(&self) -> RelayChainResult<PHash> {
+		self.call("chain_getBlockHash", Vec::new())?
+			.ok_or_else(|| RelayChainError::Rpc("The relay chain node has no best block".into()))
+	}
+
+	fn finalized_block_hash(&self) -> RelayChainResult<PHash> {
+		self.call("chain_getFinalizedHead", Vec::new())
+	}
+
+	fn header(&self, at: PHash) -> RelayChainResult<Option<PHeader>> {
+		self.call("chain_getHeader", vec![Value::from(format!("{:?}", at))])
+	}
+
+	fn parachain_head(&self, at: PHash, para_id: ParaId) -> RelayChainResult<Option<Vec<u8>>> {
+		let key = serde_json::to_value(parachain_head_key(para_id)).map_err(rpc_error)?;
+		let head: Option<StorageData> =
+			self.call("state_getStorage", vec![key, Value::from(format!("{:?}", at))])?;
+
+		Ok(head.map(|h| h.0))
+	}
+
+	fn validators(&self, at: PHash) -> RelayChainResult<Vec<SessionKey>> {
+		let data = serde_json::to_value(Bytes(Vec::new())).map_err(rpc_error)?;
+		let result: Bytes = self.call(
+			"state_call",
+			vec![Value::from("ParachainHost_validators"), data, Value::from(format!("{:?}", at))],
+		)?;
+
+		Decode::decode(&mut &result[..])
+			.ok_or_else(|| RelayChainError::Rpc("Could not decode the validators".into()))
+	}
+
+	fn prove_read(&self, _: PHash, _: &[Vec<u8>]) -> RelayChainResult<Vec<Vec<u8>>> {
+		Err(RelayChainError::Rpc("The relay chain RPC does not provide read proofs".into()))
+	}
+
+	fn new_best_notification_stream(&self) -> HeaderStream {
+		self.subscribe_headers(SubscriptionMethods {
+			subscribe: "chain_subscribeNewHead",
+			topic: "chain_newHead",
+			unsubscribe: "chain_unsubscribeNewHead",
+		})
+	}
+
+	fn finality_notification_stream(&self) -> HeaderStream {
+		self.subscribe_headers(SubscriptionMethods {
+			subscribe: "chain_subscribeFinalizedHeads",
+			topic: "chain_finalizedHead",
+			unsubscribe: "chain_unsubscribeFinalizedHeads",
+		})
+	}
+}
@@ -0,0 +1,204 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Runtime module that derives the timestamp of a parachain block from the relay chain.
+//!
+//! The timestamp is the BABE slot of the relay parent, as read by the parachain system module
+//! from the relay chain state proof, multiplied by `RelaySlotDuration`. The proof is checked
+//! against the relay storage root that is injected by the validators, so unlike a timestamp
+//! inherent the timestamp can not be chosen by the collator.
+//!
+//! The module implements `Time`, so it can be used instead of the timestamp module by other
+//! modules. The timestamp of the last block is stored in `Now`.
+
+use srml_support::{decl_module, decl_storage, StorageValue, traits::{Get, Time}};
+use cumulus_pallet_parachain_system::Module as ParachainSystem;
+
+/// The module's configuration trait.
+pub trait Trait: cumulus_pallet_parachain_system::Trait {
+	/// The duration of a relay chain slot in milliseconds.
+	type RelaySlotDuration: Get<u64>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as RelayTimestamp {
+		/// The timestamp of the last block in milliseconds.
+		Now get(last_timestamp): u64;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn on_finalize() {
+			let now = Self::timestamp();
+			assert!(now >= Self::last_timestamp(), "The timestamp must not decrease");
+			Now::put(now);
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Returns the timestamp of the current block in milliseconds.
+	///
+	/// Before the `ParachainInherentData` is set in the current block, this is the timestamp
+	/// of the last block that set it.
+	pub fn timestamp() -> u64 {
+		<ParachainSystem<T>>::relay_slot().saturating_mul(T::RelaySlotDuration::get())
+	}
+}
+
+impl<T: Trait> Time for Module<T> {
+	type Moment = u64;
+
+	fn now() -> u64 {
+		Self::timestamp()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use srml_support::{impl_outer_origin, parameter_types, assert_ok};
+	use runtime_io::{with_externalities, TestExternalities};
+	use primitives::{H256, Blake2Hasher};
+	use runtime_primitives::{
+		traits::{BlakeTwo256, IdentityLookup, OnFinalize}, testing::Header,
+	};
+	use cumulus_runtime::{ValidationData, ParachainInherentData};
+	use cumulus_pallet_parachain_system::{self as parachain_system, FixedVelocity};
+	use cumulus_test_relay_sproof_builder::RelayStateSproofBuilder;
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, PartialEq, Eq, Debug)]
+	pub struct Test;
+
+	parameter_types! {
+		pub const BlockHashCount: u64 = 250;
+		pub const BlocksPerRelayParent: u32 = 1;
+		pub const UnincludedSegmentCapacity: u32 = 10;
+		pub const RelaySlotDuration: u64 = 6000;
+	}
+
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type BlockHashCount = BlockHashCount;
+	}
+
+	impl parachain_system::Trait for Test {
+		type Event = ();
+		type DownwardMessageHandler = ();
+		type ConsensusHook = FixedVelocity<BlocksPerRelayParent, UnincludedSegmentCapacity>;
+	}
+
+	impl Trait for Test {
+		type RelaySlotDuration = RelaySlotDuration;
+	}
+
+	type ParachainSystem = parachain_system::Module<Test>;
+	type RelayTimestamp = Module<Test>;
+
+	fn new_test_ext() -> TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap().0;
+		t.extend(parachain_system::GenesisConfig {
+			parachain_id: 200.into(),
+			validation_upgrade_delay: 0,
+			max_upward_message_count: 0,
+			max_upward_message_size: 0,
+		}.build_storage::<Test>().unwrap().0);
+		TestExternalities::new(t)
+	}
+
+	/// Set the validation data and a relay chain state proof with the given `slot`.
+	fn set_relay_slot(relay_parent_number: u32, slot: u64) {
+		let (relay_storage_root, relay_chain_state) = RelayStateSproofBuilder {
+			current_slot: Some(slot),
+			..Default::default()
+		}.into_state_root_and_proof();
+
+		assert_ok!(ParachainSystem::set_validation_data(
+			Origin::NONE,
+			ValidationData { relay_parent_number, relay_storage_root },
+		));
+		assert_ok!(ParachainSystem::set_parachain_inherent_data(
+			Origin::NONE,
+			ParachainInherentData { relay_chain_state },
+		));
+	}
+
+	fn finalize_block(number: u64) {
+		RelayTimestamp::on_finalize(number);
+		ParachainSystem::on_finalize(number);
+	}
+
+	#[test]
+	fn timestamp_is_derived_from_the_relay_slot() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_eq!(RelayTimestamp::timestamp(), 0);
+
+			set_relay_slot(1, 10);
+			assert_eq!(RelayTimestamp::timestamp(), 60_000);
+			assert_eq!(<RelayTimestamp as Time>::now(), 60_000);
+			finalize_block(1);
+			assert_eq!(RelayTimestamp::last_timestamp(), 60_000);
+
+			set_relay_slot(2, 11);
+			assert_eq!(RelayTimestamp::timestamp(), 66_000);
+			finalize_block(2);
+			assert_eq!(RelayTimestamp::last_timestamp(), 66_000);
+		});
+	}
+
+	#[test]
+	fn timestamp_is_kept_without_relay_slot() {
+		with_externalities(&mut new_test_ext(), || {
+			set_relay_slot(1, 10);
+			finalize_block(1);
+
+			assert_ok!(ParachainSystem::set_validation_data(
+				Origin::NONE,
+				ValidationData { relay_parent_number: 2, relay_storage_root: H256::zero() },
+			));
+			assert_eq!(RelayTimestamp::timestamp(), 60_000);
+			finalize_block(2);
+			assert_eq!(RelayTimestamp::last_timestamp(), 60_000);
+		});
+	}
+
+	#[test]
+	#[should_panic(expected = "The timestamp must not decrease")]
+	fn timestamp_must_not_decrease() {
+		with_externalities(&mut new_test_ext(), || {
+			set_relay_slot(1, 10);
+			finalize_block(1);
+
+			set_relay_slot(2, 9);
+			finalize_block(2);
+		});
+	}
+}
@@ -21,6 +21,7 @@
 use rstd::vec::Vec;
 use codec::{Encode, Decode};
 use runtime_primitives::traits::Block as BlockT;
+use primitives::H256;
 
 #[cfg(not(feature = "std"))]
 #[doc(hidden)]
@@ -28,34 +29,424 @@ pub use rstd::slice;
 
 #[macro_use]
 pub mod validate_block;
+mod witness_data;
+pub mod relay_chain_keys;
+mod relay_chain_state_proof;
+mod message_queue_chain;
 
-/// The witness data type.
-type WitnessData = Vec<Vec<u8>>;
+pub use witness_data::WitnessData;
+pub use relay_chain_state_proof::{RelayChainStateProof, RelayChainStateProofError};
+pub use message_queue_chain::MessageQueueChain;
 
-/// The parachain block that is created on a collator and validated by a validator.
+/// The versioned `ParachainBlockData`, as it is passed to `validate_block`.
+///
+/// New versions are added as new variants, so validators can still decode the block data of
+/// older versions.
 #[derive(Encode, Decode)]
-pub struct ParachainBlockData<B: BlockT> {
-	/// The header of the parachain block.
+pub enum VersionedParachainBlockData<B: BlockT> {
+	/// The first version of the block data, containing exactly one block.
+	V1(ParachainBlockDataV1<B>),
+	/// The block data containing multiple consecutive blocks.
+	V2(ParachainBlockData<B>),
+}
+
+impl<B: BlockT> VersionedParachainBlockData<B> {
+	/// Convert into the latest version of the block data.
+	pub fn into_latest(self) -> ParachainBlockData<B> {
+		match self {
+			VersionedParachainBlockData::V1(data) => ParachainBlockData {
+				blocks: rstd::iter::once((data.header, data.extrinsics)).collect(),
+				witness_data: data.witness_data,
+				witness_data_storage_root: data.witness_data_storage_root,
+			},
+			VersionedParachainBlockData::V2(data) => data,
+		}
+	}
+}
+
+/// The first version of the `ParachainBlockData`, containing exactly one block.
+#[derive(Encode, Decode)]
+pub struct ParachainBlockDataV1<B: BlockT> {
 	header: <B as BlockT>::Header,
-	/// The extrinsics of the parachain block without the `PolkadotInherent`.
 	extrinsics: Vec<<B as BlockT>::Extrinsic>,
+	witness_data: WitnessData,
+	witness_data_storage_root: <B as BlockT>::Hash,
+}
+
+/// The parachain blocks that are created on a collator and validated by a validator.
+///
+/// All blocks share the same witness data and are executed in sequence.
+#[derive(Encode, Decode)]
+pub struct ParachainBlockData<B: BlockT> {
+	/// The headers and extrinsics of the parachain blocks.
+	///
+	/// The extrinsics do not contain the `PolkadotInherent`.
+	blocks: Vec<(<B as BlockT>::Header, Vec<<B as BlockT>::Extrinsic>)>,
 	/// The data that is required to emulate the storage accesses executed by all extrinsics.
 	witness_data: WitnessData,
+	/// The storage root before executing the first block.
 	witness_data_storage_root: <B as BlockT>::Hash,
 }
 
 impl<B: BlockT> ParachainBlockData<B> {
+	/// Create the block data for a single block.
 	pub fn new(
 		header: <B as BlockT>::Header,
 		extrinsics: Vec<<B as BlockT>::Extrinsic>,
 		witness_data: WitnessData,
 		witness_data_storage_root: <B as BlockT>::Hash,
+	) -> Self {
+		Self::with_blocks(
+			rstd::iter::once((header, extrinsics)).collect(),
+			witness_data,
+			witness_data_storage_root,
+		)
+	}
+
+	/// Create the block data for multiple consecutive blocks.
+	///
+	/// The `witness_data` needs to cover the execution of all blocks.
+	pub fn with_blocks(
+		blocks: Vec<(<B as BlockT>::Header, Vec<<B as BlockT>::Extrinsic>)>,
+		witness_data: WitnessData,
+		witness_data_storage_root: <B as BlockT>::Hash,
 	) -> Self {
 		Self {
-			header,
-			extrinsics,
+			blocks,
 			witness_data,
 			witness_data_storage_root,
 		}
 	}
+
+	/// Encode the block data as the latest version of `VersionedParachainBlockData`.
+	pub fn encode_versioned(self) -> Vec<u8> {
+		VersionedParachainBlockData::V2(self).encode()
+	}
+
+	/// Decode the block data from any version of `VersionedParachainBlockData`.
+	pub fn decode_versioned(data: &[u8]) -> Option<Self> {
+		VersionedParachainBlockData::<B>::decode(&mut &data[..]).map(|data| data.into_latest())
+	}
+
+	/// Returns the headers and extrinsics of the parachain blocks.
+	pub fn blocks(&self) -> &[(<B as BlockT>::Header, Vec<<B as BlockT>::Extrinsic>)] {
+		&self.blocks
+	}
+
+	/// Returns the witness data of the blocks.
+	pub fn witness_data(&self) -> &WitnessData {
+		&self.witness_data
+	}
+
+	/// Returns the storage root before executing the first block.
+	pub fn witness_data_storage_root(&self) -> &<B as BlockT>::Hash {
+		&self.witness_data_storage_root
+	}
+}
+
+/// The relay chain data that is required to validate a parachain block.
+///
+/// Passed by the validator to `validate_block` and injected into the block as the
+/// `PolkadotInherent`.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ValidationData {
+	/// The number of the relay chain block the parachain block is built on.
+	pub relay_parent_number: u32,
+	/// The storage root of the relay chain block the parachain block is built on.
+	pub relay_storage_root: H256,
+}
+
+/// Create the `PolkadotInherent` extrinsic of a parachain block.
+pub trait ProvidePolkadotInherent<B: BlockT> {
+	/// Does the runtime require the `PolkadotInherent` in every block?
+	///
+	/// If `true`, `validate_block` fails when the validator does not pass the `ValidationData`.
+	/// Otherwise, the blocks are executed as they are, and the relay chain data in them is not
+	/// checked against the relay chain.
+	const REQUIRED: bool = false;
+
+	/// Create the inherent that makes the given `data` available to the runtime.
+	fn create_inherent(data: &ValidationData) -> <B as BlockT>::Extrinsic;
+
+	/// Create the inherent that passes the downward `messages` to the runtime.
+	///
+	/// The inherent follows the `PolkadotInherent` in the first block of a candidate and, like
+	/// the `PolkadotInherent`, is injected by the validators. Returns `None` if the runtime
+	/// does not process downward messages.
+	fn create_downward_messages_inherent(
+		_messages: &[DownwardMessage],
+	) -> Option<<B as BlockT>::Extrinsic> {
+		None
+	}
+}
+
+/// The id of a parachain.
+///
+/// Encoded like the `u32` it wraps, so it is compatible with the id of the relay chain.
+#[derive(Encode, Decode, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "std", derive(Debug, Hash, serde::Serialize, serde::Deserialize))]
+pub struct ParaId(u32);
+
+impl From<u32> for ParaId {
+	fn from(id: u32) -> Self {
+		ParaId(id)
+	}
+}
+
+impl From<ParaId> for u32 {
+	fn from(id: ParaId) -> Self {
+		id.0
+	}
+}
+
+impl From<parachain::Id> for ParaId {
+	fn from(id: parachain::Id) -> Self {
+		ParaId(id.into())
+	}
+}
+
+impl From<ParaId> for parachain::Id {
+	fn from(id: ParaId) -> Self {
+		id.0.into()
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for ParaId {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+/// A message that is sent to the parachain through the relay chain.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct DownwardMessage {
+	/// The parachain that sent the message.
+	pub source: ParaId,
+	/// The message.
+	pub data: Vec<u8>,
+}
+
+/// The identifier of the `ParachainInherentData` in the inherent data.
+pub const PARACHAIN_INHERENT_IDENTIFIER: [u8; 8] = *b"parachn0";
+
+/// The identifier of the `ValidationData` of the relay parent in the inherent data.
+///
+/// Only used to check the `ValidationData` of a block, the inherent that sets it is injected
+/// by the validators.
+pub const VALIDATION_DATA_IDENTIFIER: [u8; 8] = *b"valdata0";
+
+/// The storage key under which the runtime puts the upward messages of the current block.
+///
+/// The messages are stored as encoded `Vec<Vec<u8>>`. `validate_block` collects them from
+/// every block of the candidate into `ValidationResult::upward_messages`.
+pub const UPWARD_MESSAGES_KEY: &[u8] = b":cumulus_upward_messages:";
+
+/// The storage key under which the runtime puts the horizontal messages of the current block.
+///
+/// The messages are stored as encoded `Vec<OutboundHrmpMessage>`. `validate_block` collects
+/// them from every block of the candidate into `ValidationResult::horizontal_messages`.
+pub const HORIZONTAL_MESSAGES_KEY: &[u8] = b":cumulus_horizontal_messages:";
+
+/// The storage key under which the runtime puts the hrmp watermark of the current block.
+///
+/// The watermark is stored as encoded `u32`, the number of the relay chain block up to which
+/// all horizontal messages to the parachain were processed. `validate_block` puts the
+/// watermark of the last block of the candidate into `ValidationResult::hrmp_watermark`.
+pub const HRMP_WATERMARK_KEY: &[u8] = b":cumulus_hrmp_watermark:";
+
+/// A message that is sent by the parachain to another parachain.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct OutboundHrmpMessage {
+	/// The parachain that receives the message.
+	pub recipient: ParaId,
+	/// The message.
+	pub data: Vec<u8>,
+}
+
+/// The outputs of a parachain block that the relay chain checks, as `validate_block` computes
+/// them.
+///
+/// Returned by `CollectCollationInfo::collect_collation_info` for a block that was built. For a
+/// candidate with multiple blocks, the messages of the blocks are concatenated in order.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct CollationInfo {
+	/// The new head data of the parachain, the encoded header of the block.
+	pub head_data: Vec<u8>,
+	/// The messages that the block sends to the relay chain.
+	pub upward_messages: Vec<Vec<u8>>,
+	/// The messages that the block sends to other parachains.
+	pub horizontal_messages: Vec<OutboundHrmpMessage>,
+	/// The number of downward messages that were processed by the block.
+	pub processed_downward_messages: u32,
+	/// The new validation code, if the block changed `:code`.
+	pub new_validation_code: Option<Vec<u8>>,
+	/// The hrmp watermark of the block, if the runtime sets one.
+	pub hrmp_watermark: Option<u32>,
+}
+
+/// The outputs of a parachain block as version 1 of `CollectCollationInfo` returns them.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct CollationInfoV1 {
+	/// The messages that the block sends to the relay chain.
+	pub upward_messages: Vec<Vec<u8>>,
+	/// The messages that the block sends to other parachains.
+	pub horizontal_messages: Vec<OutboundHrmpMessage>,
+	/// The number of downward messages that were processed by the block.
+	pub processed_downward_messages: u32,
+	/// The new validation code, if the block changed `:code`.
+	pub new_validation_code: Option<Vec<u8>>,
+}
+
+impl CollationInfoV1 {
+	/// Convert into a `CollationInfo` with the given `head_data` and without hrmp watermark.
+	pub fn into_latest(self, head_data: Vec<u8>) -> CollationInfo {
+		CollationInfo {
+			head_data,
+			upward_messages: self.upward_messages,
+			horizontal_messages: self.horizontal_messages,
+			processed_downward_messages: self.processed_downward_messages,
+			new_validation_code: self.new_validation_code,
+			hrmp_watermark: None,
+		}
+	}
+}
+
+client::decl_runtime_apis! {
+	/// Collect the outputs of a parachain block.
+	///
+	/// The collator calls it on the state of a block it built, so the collation contains the
+	/// outputs that `validate_block` computes from the same block.
+	#[api_version(2)]
+	pub trait CollectCollationInfo {
+		/// Returns the outputs of the current block, without the head data.
+		#[changed_in(2)]
+		fn collect_collation_info() -> CollationInfoV1;
+
+		/// Returns the `CollationInfo` of the block with the given `header`.
+		fn collect_collation_info(header: &<Block as BlockT>::Header) -> CollationInfo;
+	}
+}
+
+/// The relay chain data that the collator fetches for a parachain block.
+///
+/// In contrast to the `ValidationData`, this data is not provided by the validators. The
+/// collator includes it into the block as an inherent of the runtime.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ParachainInherentData {
+	/// The proof of the `relay_chain_keys` in the state of the relay parent.
+	pub relay_chain_state: Vec<Vec<u8>>,
+}
+
+/// Errors that can occur while validating a parachain block.
+///
+/// Hashes are given as raw bytes, as the hash types are not required to implement `Debug`
+/// in `no_std`.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+	/// The `ValidationParams` could not be decoded.
+	InvalidValidationParams,
+	/// The `ValidationData` appended to the `ValidationParams` could not be decoded.
+	InvalidValidationData,
+	/// The runtime requires the `ValidationData`, but it was not appended to the
+	/// `ValidationParams`.
+	MissingValidationData,
+	/// The `VersionedParachainBlockData` could not be decoded or has an unknown version.
+	InvalidBlockData,
+	/// The parent head could not be decoded.
+	InvalidParentHead,
+	/// The block data or the decoded witness data exceeds the maximum size.
+	PoVTooLarge {
+		/// The maximum size in bytes.
+		max: usize,
+		/// The actual size in bytes.
+		size: usize,
+	},
+	/// The block data does not contain any block.
+	NoBlocks,
+	/// The parent hash of a block does not match the hash of the parent head or the previous
+	/// block.
+	InvalidParentHash,
+	/// The extrinsics of a block do not match the extrinsics root of the header.
+	InvalidExtrinsicsRoot,
+	/// The storage root after executing a block does not match the state root of the header.
+	InvalidStorageRoot {
+		/// The state root of the header.
+		expected: Vec<u8>,
+		/// The storage root after executing the block.
+		got: Vec<u8>,
+	},
+	/// The witness data could not be decoded.
+	InvalidWitnessData,
+	/// The witness data contains an invalid trie node with the given hash.
+	InvalidWitnessNode(Vec<u8>),
+	/// The witness data does not contain the given storage root.
+	StorageRootNotInWitness(Vec<u8>),
+	/// The witness data misses trie nodes that are required to access the given key.
+	IncompleteWitness(Vec<u8>),
+	/// The witness data misses trie nodes that are required to access the given key
+	/// in the given child trie.
+	///
+	/// The `key` is empty when the whole child trie was accessed.
+	IncompleteChildWitness {
+		/// The storage key of the child trie.
+		storage_key: Vec<u8>,
+		/// The key in the child trie.
+		key: Vec<u8>,
+	},
+	/// The storage host functions allocated more than the given number of bytes.
+	AllocationBudgetExceeded(usize),
+	/// The validated blocks removed the validation code from `:code`.
+	ValidationCodeRemoved,
+	/// The upward messages under `UPWARD_MESSAGES_KEY` could not be decoded.
+	InvalidUpwardMessages,
+	/// The horizontal messages under `HORIZONTAL_MESSAGES_KEY` could not be decoded.
+	InvalidHorizontalMessages,
+	/// The hrmp watermark under `HRMP_WATERMARK_KEY` could not be decoded or is ahead of the
+	/// relay parent.
+	InvalidHrmpWatermark,
+	/// The storage root could not be calculated, because the trie node with the given hash is
+	/// missing or invalid in the witness data.
+	StorageRootCalculationFailed(Vec<u8>),
+	/// The root of the child trie could not be calculated, because the trie node with the given
+	/// hash is missing or invalid in the witness data.
+	ChildStorageRootCalculationFailed {
+		/// The storage key of the child trie.
+		storage_key: Vec<u8>,
+		/// The hash of the trie node.
+		node: Vec<u8>,
+	},
+}
+
+/// The result of validating a parachain block, returned by `validate_block`.
+///
+/// The `head_data` is the first field, so the encoded result can also be decoded as
+/// the relay chain's `ValidationResult`.
+#[derive(Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug, PartialEq))]
+pub struct ValidationResult<Hash> {
+	/// The new head data of the parachain, the encoded header of the validated block.
+	pub head_data: Vec<u8>,
+	/// The storage root after executing the block.
+	pub storage_root: Hash,
+	/// The number of incoming messages processed by the blocks.
+	///
+	/// `0` if the runtime does not process downward messages.
+	pub processed_messages: u32,
+	/// The new validation code, if the validated blocks changed `:code`.
+	///
+	/// The relay chain needs to use this code to validate the following blocks.
+	pub new_validation_code: Option<Vec<u8>>,
+	/// The messages that the blocks send to the relay chain, in the order they were sent.
+	pub upward_messages: Vec<Vec<u8>>,
+	/// The messages that the blocks send to other parachains, in the order they were sent.
+	pub horizontal_messages: Vec<OutboundHrmpMessage>,
+	/// The hrmp watermark of the last block, if the runtime sets one.
+	pub hrmp_watermark: Option<u32>,
 }
\ No newline at end of file
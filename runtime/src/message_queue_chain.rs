@@ -0,0 +1,123 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The message queue chain, a hash chain over the messages of a queue.
+
+use crate::DownwardMessage;
+use codec::{Encode, Decode};
+use hash_db::Hasher;
+use primitives::{Blake2Hasher, H256};
+
+/// A hash chain over the messages of a queue.
+///
+/// The head of an empty queue is the zero hash. Every message extends the chain by hashing the
+/// previous head together with the hash of the message, so two queues have the same head only
+/// if they contain the same messages in the same order.
+#[derive(Encode, Decode, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MessageQueueChain(H256);
+
+impl MessageQueueChain {
+	/// Create a chain that continues from the given `head`.
+	pub fn from_head(head: H256) -> Self {
+		MessageQueueChain(head)
+	}
+
+	/// Extend the chain with a message that is sent by the relay chain.
+	pub fn extend_downward(&mut self, data: &[u8]) -> &mut Self {
+		let hash = Blake2Hasher::hash(data);
+		self.0 = Blake2Hasher::hash(&(self.0, hash).encode());
+		self
+	}
+
+	/// Extend the chain with a message that is sent by another parachain.
+	pub fn extend_hrmp(&mut self, message: &DownwardMessage) -> &mut Self {
+		let hash = Blake2Hasher::hash(&message.data);
+		self.0 = Blake2Hasher::hash(&(self.0, message.source, hash).encode());
+		self
+	}
+
+	/// Returns the current head of the chain.
+	pub fn head(&self) -> H256 {
+		self.0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hex_literal::hex;
+
+	#[test]
+	fn empty_chain_has_zero_head() {
+		assert_eq!(MessageQueueChain::default().head(), H256::zero());
+	}
+
+	#[test]
+	fn extend_downward_works() {
+		let mut chain = MessageQueueChain::default();
+
+		chain.extend_downward(b"hello");
+		assert_eq!(
+			chain.head(),
+			H256::from(hex!("624a8ef2307aedb4fa5652ba5fff7a4ea58cad2de54ae82ced0a5346b4284ef8")),
+		);
+
+		chain.extend_downward(b"world");
+		assert_eq!(
+			chain.head(),
+			H256::from(hex!("55f842c5c09d49c051f5ca1903735292e1d1e493731cdbe705fabd6658f596c1")),
+		);
+	}
+
+	#[test]
+	fn extend_hrmp_works() {
+		let mut chain = MessageQueueChain::default();
+
+		chain.extend_hrmp(&DownwardMessage { source: 100.into(), data: b"hello".to_vec() });
+		assert_eq!(
+			chain.head(),
+			H256::from(hex!("7a2233bb00fe956fb1415b260e720c0842a1205351501daa46e4f91540e1d06e")),
+		);
+
+		chain.extend_hrmp(&DownwardMessage { source: 200.into(), data: b"world".to_vec() });
+		assert_eq!(
+			chain.head(),
+			H256::from(hex!("ba97fde6a7e16beb0fb3f339224882c0e478076f9193244180203d22973df8e7")),
+		);
+	}
+
+	#[test]
+	fn chain_continues_from_head() {
+		let mut chain = MessageQueueChain::default();
+		chain.extend_downward(b"hello");
+
+		let mut continued = MessageQueueChain::from_head(chain.head());
+		chain.extend_downward(b"world");
+		continued.extend_downward(b"world");
+		assert_eq!(chain, continued);
+	}
+
+	#[test]
+	fn source_is_part_of_the_hrmp_hash() {
+		let mut downward = MessageQueueChain::default();
+		downward.extend_downward(b"hello");
+
+		let mut hrmp = MessageQueueChain::default();
+		hrmp.extend_hrmp(&DownwardMessage { source: 100.into(), data: b"hello".to_vec() });
+		assert_ne!(downward, hrmp);
+	}
+}
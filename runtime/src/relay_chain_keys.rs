@@ -0,0 +1,105 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The keys of the relay chain state that are proven to a parachain runtime.
+//!
+//! The keys are hashed the same way as the relay chain runtime hashes the keys of its storage
+//! items: `twox_128` of the item name for values and `blake2_256` of the item name and the
+//! encoded key for maps.
+
+use rstd::vec::Vec;
+use codec::Encode;
+use crate::ParaId;
+
+/// The key of the entry of `para_id` in the relay chain storage map `map`.
+fn para_key(map: &[u8], para_id: ParaId) -> Vec<u8> {
+	para_id.using_encoded(|id| {
+		let mut key = map.to_vec();
+		key.extend(id);
+		rio::blake2_256(&key).to_vec()
+	})
+}
+
+/// The key of the head of the given parachain.
+pub fn para_head(para_id: ParaId) -> Vec<u8> {
+	para_key(b"Parachains Heads", para_id)
+}
+
+/// The key of the validation code of the given parachain.
+pub fn para_code(para_id: ParaId) -> Vec<u8> {
+	para_key(b"Parachains Code", para_id)
+}
+
+/// The key of the BABE randomness of the epoch before the current epoch.
+pub fn one_epoch_ago_randomness() -> Vec<u8> {
+	rio::twox_128(b"Babe NextRandomness").to_vec()
+}
+
+/// The key of the BABE randomness of the epoch two epochs before the current epoch.
+pub fn two_epochs_ago_randomness() -> Vec<u8> {
+	rio::twox_128(b"Babe Randomness").to_vec()
+}
+
+/// The key of the current BABE slot.
+pub fn current_slot() -> Vec<u8> {
+	rio::twox_128(b"Babe CurrentSlot").to_vec()
+}
+
+/// All keys that are proven to the runtime of the given parachain.
+pub fn all(para_id: ParaId) -> Vec<Vec<u8>> {
+	let mut keys = Vec::new();
+	keys.push(para_head(para_id));
+	keys.push(para_code(para_id));
+	keys.push(one_epoch_ago_randomness());
+	keys.push(two_epochs_ago_randomness());
+	keys.push(current_slot());
+	keys
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hex_literal::hex;
+
+	#[test]
+	fn para_keys_are_hashed_like_map_keys() {
+		assert_eq!(
+			para_head(100.into()),
+			hex!("079e49265da501dec9f67c8c0d0a4f96dd3cbb9612e5b14df740a9d191e4e420").to_vec(),
+		);
+		assert_eq!(
+			para_code(100.into()),
+			hex!("674e358618a7a282976aa8b6bc1ef2848fd30b6a875b4db1c2395ddd16b6ec04").to_vec(),
+		);
+	}
+
+	#[test]
+	fn randomness_keys_are_hashed_like_value_keys() {
+		assert_eq!(
+			one_epoch_ago_randomness(),
+			hex!("26e9d2686ce7fdbb501a200e797a42db").to_vec(),
+		);
+		assert_eq!(
+			two_epochs_ago_randomness(),
+			hex!("d5b995311b7ab9b44b649bc5ce4a7aba").to_vec(),
+		);
+	}
+
+	#[test]
+	fn current_slot_key_is_hashed_like_a_value_key() {
+		assert_eq!(current_slot(), hex!("137a2a48eeb4491643f107bcb12f1f81").to_vec());
+	}
+}
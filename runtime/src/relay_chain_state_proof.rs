@@ -0,0 +1,114 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Read the relay chain state from a storage proof.
+
+use crate::{relay_chain_keys, ParaId};
+use rstd::vec::Vec;
+use codec::Decode;
+use hash_db::{Hasher, HashDB};
+use primitives::{Blake2Hasher, H256};
+use substrate_trie::{MemoryDB, read_trie_value};
+use trie_db::DBValue;
+
+/// Errors that can occur while reading the relay chain state from a proof.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RelayChainStateProofError {
+	/// The proof does not contain the relay chain storage root.
+	RootMismatch,
+	/// The proof does not contain the trie nodes that are required to read the given key.
+	ReadEntry(Vec<u8>),
+	/// The value of the given key could not be decoded.
+	Decode(Vec<u8>),
+}
+
+/// A storage proof of the relay chain state that is checked against the relay chain storage
+/// root.
+pub struct RelayChainStateProof {
+	db: MemoryDB<Blake2Hasher>,
+	root: H256,
+	para_id: ParaId,
+}
+
+impl RelayChainStateProof {
+	/// Create a new instance for the given parachain.
+	///
+	/// Returns an error if the proof does not contain the `relay_storage_root`.
+	pub fn new(
+		para_id: ParaId,
+		relay_storage_root: H256,
+		proof: Vec<Vec<u8>>,
+	) -> Result<Self, RelayChainStateProofError> {
+		let mut db = MemoryDB::default();
+		for node in proof {
+			let hash = Blake2Hasher::hash(&node);
+			HashDB::emplace(&mut db, hash, &[], DBValue::from_slice(&node));
+		}
+
+		if !HashDB::contains(&db, &relay_storage_root, &[]) {
+			return Err(RelayChainStateProofError::RootMismatch)
+		}
+
+		Ok(Self { db, root: relay_storage_root, para_id })
+	}
+
+	/// Read the raw value of the given `key`.
+	///
+	/// Returns an error if the proof does not contain the trie nodes to read the key.
+	pub fn read_raw(&self, key: &[u8]) -> Result<Option<Vec<u8>>, RelayChainStateProofError> {
+		read_trie_value(&self.db, &self.root, key)
+			.map_err(|_| RelayChainStateProofError::ReadEntry(key.to_vec()))
+	}
+
+	/// Read and decode the value of the given `key`.
+	pub fn read_entry<T: Decode>(
+		&self,
+		key: &[u8],
+	) -> Result<Option<T>, RelayChainStateProofError> {
+		match self.read_raw(key)? {
+			Some(value) => T::decode(&mut &value[..])
+				.map(Some)
+				.ok_or_else(|| RelayChainStateProofError::Decode(key.to_vec())),
+			None => Ok(None),
+		}
+	}
+
+	/// Read the head of the parachain that is included in the relay chain.
+	pub fn para_head(&self) -> Result<Option<Vec<u8>>, RelayChainStateProofError> {
+		self.read_raw(&relay_chain_keys::para_head(self.para_id))
+	}
+
+	/// Read the validation code of the parachain that is registered on the relay chain.
+	pub fn para_code(&self) -> Result<Option<Vec<u8>>, RelayChainStateProofError> {
+		self.read_raw(&relay_chain_keys::para_code(self.para_id))
+	}
+
+	/// Read the BABE randomness of the epoch before the current epoch of the relay chain.
+	pub fn one_epoch_ago_randomness(&self) -> Result<Option<H256>, RelayChainStateProofError> {
+		self.read_entry(&relay_chain_keys::one_epoch_ago_randomness())
+	}
+
+	/// Read the current BABE slot of the relay chain.
+	pub fn current_slot(&self) -> Result<Option<u64>, RelayChainStateProofError> {
+		self.read_entry(&relay_chain_keys::current_slot())
+	}
+
+	/// Read the BABE randomness of the epoch two epochs before the current epoch of the relay
+	/// chain.
+	pub fn two_epochs_ago_randomness(&self) -> Result<Option<H256>, RelayChainStateProofError> {
+		self.read_entry(&relay_chain_keys::two_epochs_ago_randomness())
+	}
+}
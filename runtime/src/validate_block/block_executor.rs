@@ -0,0 +1,46 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A block executor that strips the seal of a block before executing it.
+
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use executive::ExecuteBlock;
+
+use rstd::marker::PhantomData;
+
+/// Executes a block with `E` after removing the seal from its header.
+///
+/// The author of a block seals it after building it, e.g. with Aura, by appending a seal digest
+/// item to the header. The runtime never produced this digest item, so executing the sealed
+/// block fails. `validate_block` checks the parent hash and returns the head data with the
+/// sealed header, only the execution sees the header without the seal.
+///
+/// Only the last digest item is removed and only if it is a seal. Blocks without a seal are
+/// passed to `E` unaltered.
+pub struct BlockExecutor<Block, E>(PhantomData<(Block, E)>);
+
+impl<Block: BlockT, E: ExecuteBlock<Block>> ExecuteBlock<Block> for BlockExecutor<Block, E> {
+	fn execute_block(block: Block) {
+		let (mut header, extrinsics) = block.deconstruct();
+
+		let sealed = header.digest().logs().last().map_or(false, |item| item.as_seal().is_some());
+		if sealed {
+			header.digest_mut().pop();
+		}
+
+		E::execute_block(Block::new(header, extrinsics))
+	}
+}
@@ -0,0 +1,153 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Externalities that use the witness storage to execute a block natively in `validate_block`.
+
+use super::implementation::{Storage, AllocationBudget};
+use runtime_primitives::traits::Block as BlockT;
+use executive::ExecuteBlock;
+use primitives::{Blake2Hasher, H256, offchain, hexdisplay::HexDisplay};
+use rio::{Externalities, ChildStorageKey};
+
+use log::trace;
+
+/// The log target used by the externalities.
+const LOG_TARGET: &str = "validate-block";
+
+/// Display the given optional storage value as hex.
+fn display_value(value: &Option<Vec<u8>>) -> String {
+	value.as_ref().map(|v| HexDisplay::from(v).to_string()).unwrap_or_else(|| "None".into())
+}
+
+/// Execute the given block natively with externalities that use the given `storage`.
+///
+/// The `budget` is not used, as values are not allocated in the runtime memory natively.
+pub fn execute_block<B: BlockT, E: ExecuteBlock<B>>(
+	storage: &mut dyn Storage,
+	_budget: &mut AllocationBudget,
+	block: B,
+) {
+	rio::with_externalities(&mut WitnessExt { storage }, || E::execute_block(block));
+}
+
+/// Externalities that forward all storage accesses to a `Storage`.
+struct WitnessExt<'a> {
+	storage: &'a mut dyn Storage,
+}
+
+impl<'a> Externalities<Blake2Hasher> for WitnessExt<'a> {
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		let value = self.storage.get(key);
+		trace!(
+			target: LOG_TARGET,
+			"Get {} = {}",
+			HexDisplay::from(&key),
+			display_value(&value),
+		);
+		value
+	}
+
+	fn original_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.storage(key)
+	}
+
+	fn child_storage(
+		&self,
+		storage_key: ChildStorageKey<Blake2Hasher>,
+		key: &[u8],
+	) -> Option<Vec<u8>> {
+		let value = self.storage.get_child(storage_key.as_ref(), key);
+		trace!(
+			target: LOG_TARGET,
+			"Get child {} {} = {}",
+			HexDisplay::from(&storage_key.as_ref()),
+			HexDisplay::from(&key),
+			display_value(&value),
+		);
+		value
+	}
+
+	fn kill_child_storage(&mut self, storage_key: ChildStorageKey<Blake2Hasher>) {
+		trace!(target: LOG_TARGET, "Kill child {}", HexDisplay::from(&storage_key.as_ref()));
+		self.storage.kill_child(storage_key.as_ref());
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		trace!(target: LOG_TARGET, "Clear prefix {}", HexDisplay::from(&prefix));
+		self.storage.clear_prefix(prefix);
+	}
+
+	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		trace!(
+			target: LOG_TARGET,
+			"Put {} = {}",
+			HexDisplay::from(&key),
+			display_value(&value),
+		);
+		match value {
+			Some(value) => self.storage.insert(&key, &value),
+			None => self.storage.remove(&key),
+		}
+	}
+
+	fn place_child_storage(
+		&mut self,
+		storage_key: ChildStorageKey<Blake2Hasher>,
+		key: Vec<u8>,
+		value: Option<Vec<u8>>,
+	) {
+		trace!(
+			target: LOG_TARGET,
+			"Put child {} {} = {}",
+			HexDisplay::from(&storage_key.as_ref()),
+			HexDisplay::from(&key),
+			display_value(&value),
+		);
+		match value {
+			Some(value) => self.storage.insert_child(storage_key.as_ref(), &key, &value),
+			None => self.storage.remove_child(storage_key.as_ref(), &key),
+		}
+	}
+
+	fn chain_id(&self) -> u64 {
+		42
+	}
+
+	fn storage_root(&mut self) -> H256 {
+		let root = H256::from_slice(&self.storage.storage_root());
+		trace!(target: LOG_TARGET, "Storage root {}", root);
+		root
+	}
+
+	fn child_storage_root(&mut self, storage_key: ChildStorageKey<Blake2Hasher>) -> Vec<u8> {
+		let root = self.storage.child_storage_root(storage_key.as_ref());
+		trace!(
+			target: LOG_TARGET,
+			"Child storage root {} = {}",
+			HexDisplay::from(&storage_key.as_ref()),
+			HexDisplay::from(&root),
+		);
+		root
+	}
+
+	fn storage_changes_root(&mut self, _: H256) -> Result<Option<H256>, ()> {
+		Ok(None)
+	}
+
+	fn offchain(&mut self) -> Option<&mut dyn offchain::Externalities> {
+		None
+	}
+}
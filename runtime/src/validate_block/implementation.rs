@@ -16,31 +16,47 @@
 
 //! The actual implementation of the validate block functionality.
 
-use crate::WitnessData;
+use crate::{
+	ValidationResult, ValidationData, ValidationError, ProvidePolkadotInherent,
+	DownwardMessage, VersionedParachainBlockData, OutboundHrmpMessage, UPWARD_MESSAGES_KEY,
+	HORIZONTAL_MESSAGES_KEY, HRMP_WATERMARK_KEY,
+};
 use runtime_primitives::traits::{
 	Block as BlockT, Header as HeaderT, Hash as HashT
 };
 use executive::ExecuteBlock;
+use primitives::storage::well_known_keys;
 
-use substrate_trie::{MemoryDB, read_trie_value, delta_trie_root};
+use substrate_trie::{
+	TrieDB, read_trie_value, delta_trie_root, read_child_trie_value,
+	child_delta_trie_root, default_child_trie_root, for_keys_in_child_trie,
+};
+use trie_db::{Trie, TrieError};
 
-use rstd::{slice, ptr, cmp, vec::Vec, boxed::Box, mem};
+use rstd::{vec::Vec, ops::Bound, cell::RefCell, collections::btree_map::BTreeMap};
+#[cfg(not(feature = "std"))]
+use rstd::{slice, mem};
 
 use hash_db::HashDB;
 
 use parachain::ValidationParams;
 
-static mut STORAGE: Option<Box<dyn Storage>> = None;
-/// The message to use as expect message while accessing the `STORAGE`.
-const STORAGE_SET_EXPECT: &str =
-	"`STORAGE` needs to be set before calling this function.";
-const STORAGE_ROOT_LEN: usize = 32;
+use codec::{Encode, Decode};
+
+use super::witness_db::{WitnessDB, OverlayDB, WitnessStats};
+
+#[cfg(not(feature = "std"))]
+use super::storage_functions::execute_block;
+#[cfg(feature = "std")]
+use super::externalities::execute_block;
 
 /// Extract the hashing algorithm type from the given block type.
 type HashingOf<B> = <<B as BlockT>::Header as HeaderT>::Hashing;
+/// Extract the hasher type used by the trie from the given block type.
+type HasherOf<B> = <HashingOf<B> as HashT>::Hasher;
 
 /// Abstract the storage into a trait without `Block` generic.
-trait Storage {
+pub(crate) trait Storage {
 	/// Retrieve the value for the given key.
 	fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
 
@@ -50,188 +66,709 @@ trait Storage {
 	/// Remove key and value.
 	fn remove(&mut self, key: &[u8]);
 
+	/// Remove all keys and values that start with the given prefix.
+	fn clear_prefix(&mut self, prefix: &[u8]);
+
 	/// Calculate the storage root.
-	fn storage_root(&mut self) -> [u8; STORAGE_ROOT_LEN];
+	///
+	/// The length of the root depends on the hashing algorithm of the block.
+	fn storage_root(&mut self) -> Vec<u8>;
+
+	/// Retrieve the value for the given key in the given child trie.
+	fn get_child(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>>;
+
+	/// Insert the given key and value into the given child trie.
+	fn insert_child(&mut self, storage_key: &[u8], key: &[u8], value: &[u8]);
+
+	/// Remove key and value from the given child trie.
+	fn remove_child(&mut self, storage_key: &[u8], key: &[u8]);
+
+	/// Remove the given child trie with all its keys and values.
+	fn kill_child(&mut self, storage_key: &[u8]);
+
+	/// Calculate the storage root of the given child trie.
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Vec<u8>;
+}
+
+/// The default number of bytes that the storage host functions may allocate while validating.
+pub(crate) const DEFAULT_ALLOCATION_BUDGET: usize = 128 * 1024 * 1024;
+
+/// The budget of bytes that the storage host functions may allocate in the runtime memory.
+///
+/// The budget is shared by all blocks that are validated together. It is cumulative: the
+/// values are freed by the runtime, which the host functions can not observe, so freed bytes
+/// are never credited. The budget thus bounds the total size of all values that the blocks
+/// read, not the memory that is in use at any time. A candidate whose blocks read more bytes
+/// in total is rejected even if it would fit into the runtime memory, so collators need to
+/// keep the storage reads of their candidates below `DEFAULT_ALLOCATION_BUDGET`.
+pub(crate) struct AllocationBudget {
+	budget: usize,
+	allocated: usize,
+}
+
+impl AllocationBudget {
+	/// Create a new budget of `budget` bytes.
+	pub fn new(budget: usize) -> Self {
+		Self { budget, allocated: 0 }
+	}
+
+	/// Account for an allocation of `size` bytes.
+	///
+	/// Aborts the validation if all allocations together exceed the budget.
+	#[cfg_attr(feature = "std", allow(dead_code))]
+	pub fn allocate(&mut self, size: usize) {
+		self.allocated = self.allocated.saturating_add(size);
+		if self.allocated > self.budget {
+			panic!(
+				"Failed to validate block: {:?}",
+				ValidationError::AllocationBudgetExceeded(self.budget),
+			)
+		}
+	}
 }
 
-/// Validate a given parachain block on a validator.
+/// Abort the validation, because the witness data misses trie nodes to access `key`.
+fn incomplete_witness(key: &[u8]) -> ! {
+	panic!("Failed to validate block: {:?}", ValidationError::IncompleteWitness(key.to_vec()))
+}
+
+/// Abort the validation, because the witness data misses trie nodes to access `key` in the
+/// child trie identified by `storage_key`.
+fn incomplete_child_witness(storage_key: &[u8], key: &[u8]) -> ! {
+	panic!(
+		"Failed to validate block: {:?}",
+		ValidationError::IncompleteChildWitness {
+			storage_key: storage_key.to_vec(),
+			key: key.to_vec(),
+		},
+	)
+}
+
+/// Returns the hash of the trie node that caused the given error.
+fn trie_error_node<T: AsRef<[u8]>, E>(error: &TrieError<T, E>) -> Vec<u8> {
+	match error {
+		TrieError::InvalidStateRoot(hash) |
+		TrieError::IncompleteDatabase(hash) |
+		TrieError::DecoderError(hash, _) => hash.as_ref().to_vec(),
+	}
+}
+
+/// Load the `ValidationParams` and the `ValidationData` that is appended to them.
+///
+/// The `ValidationData` is optional to stay compatible with validators that only pass the
+/// `ValidationParams`.
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+pub unsafe fn load_params(
+	arguments: *const u8,
+	arguments_len: usize,
+) -> Result<(ValidationParams, Option<ValidationData>), ValidationError> {
+	let mut input = slice::from_raw_parts(arguments, arguments_len);
+	let params = ValidationParams::decode(&mut input)
+		.ok_or(ValidationError::InvalidValidationParams)?;
+
+	let validation_data = if input.is_empty() {
+		None
+	} else {
+		Some(ValidationData::decode(&mut input).ok_or(ValidationError::InvalidValidationData)?)
+	};
+
+	Ok((params, validation_data))
+}
+
+/// Validate the given parachain blocks on a validator.
+///
+/// The blocks are executed in sequence, the result contains the head of the last block.
+/// Validation is aborted before decoding if the block data or the decoded witness data is
+/// bigger than `max_pov_size` bytes.
+#[doc(hidden)]
+pub fn validate_block<B: BlockT, E: ExecuteBlock<B>, I: ProvidePolkadotInherent<B>>(
+	params: ValidationParams,
+	validation_data: Option<ValidationData>,
+	max_pov_size: usize,
+) -> Result<ValidationResult<B::Hash>, ValidationError> {
+	validate_block_with_stats::<B, E, I>(params, validation_data, max_pov_size)
+		.map(|(res, _)| res)
+}
+
+/// Validate the given parachain blocks like `validate_block`.
+///
+/// Additionally returns the statistics about the usage of the witness data, which shows how
+/// much of the witness data was required to execute the blocks.
 #[doc(hidden)]
-pub fn validate_block<B: BlockT, E: ExecuteBlock<B>>(
+pub fn validate_block_with_stats<B: BlockT, E: ExecuteBlock<B>, I: ProvidePolkadotInherent<B>>(
 	params: ValidationParams,
-) {
-	use codec::Decode;
-
-	let block_data = crate::ParachainBlockData::<B>::decode(&mut &params.block_data[..])
-		.expect("Invalid parachain block data");
-
-	let parent_head = B::Header::decode(&mut &params.parent_head[..]).expect("Invalid parent head");
-
-	// TODO: Add `PolkadotInherent`.
-	let block = B::new(block_data.header, block_data.extrinsics);
-	assert!(parent_head.hash() == *block.header().parent_hash(), "Invalid parent hash");
-
-	let storage = WitnessStorage::<B>::new(
-		block_data.witness_data,
-		block_data.witness_data_storage_root,
-	).expect("Witness data and storage root always match; qed");
-
-	let _guard = unsafe {
-		STORAGE = Some(Box::new(storage));
-		(
-			// Replace storage calls with our own implementations
-			rio::ext_get_allocated_storage.replace_implementation(ext_get_allocated_storage),
-			rio::ext_get_storage_into.replace_implementation(ext_get_storage_into),
-			rio::ext_set_storage.replace_implementation(ext_set_storage),
-			rio::ext_exists_storage.replace_implementation(ext_exists_storage),
-			rio::ext_clear_storage.replace_implementation(ext_clear_storage),
-			rio::ext_storage_root.replace_implementation(ext_storage_root),
-		)
+	validation_data: Option<ValidationData>,
+	max_pov_size: usize,
+) -> Result<(ValidationResult<B::Hash>, WitnessStats), ValidationError> {
+	let preflight = preflight::<B>(&params, max_pov_size)?;
+
+	let downward_messages = params.ingress.into_iter()
+		.map(|m| DownwardMessage { source: m.source.into(), data: m.data })
+		.collect();
+
+	execute::<B, E, I>(preflight, validation_data, downward_messages)
+}
+
+/// The parachain blocks and the witness data, decoded and checked by `preflight`.
+///
+/// Executing the blocks does not alter it, so it can be cloned to execute the blocks again.
+#[doc(hidden)]
+pub struct Preflight<B: BlockT> {
+	blocks: Vec<(B::Header, Vec<B::Extrinsic>)>,
+	parent_head: B::Header,
+	witness_data: WitnessDB<HasherOf<B>>,
+	witness_data_storage_root: B::Hash,
+}
+
+impl<B: BlockT> Clone for Preflight<B> {
+	fn clone(&self) -> Self {
+		Self {
+			blocks: self.blocks.clone(),
+			parent_head: self.parent_head.clone(),
+			witness_data: self.witness_data.clone(),
+			witness_data_storage_root: self.witness_data_storage_root,
+		}
+	}
+}
+
+/// The first phase of `validate_block`, which does not execute the blocks.
+///
+/// Decodes the block data and the parent head of `params` and checks the witness data. Fails
+/// if the block data or the decoded witness data is bigger than `max_pov_size` bytes.
+#[doc(hidden)]
+pub fn preflight<B: BlockT>(
+	params: &ValidationParams,
+	max_pov_size: usize,
+) -> Result<Preflight<B>, ValidationError> {
+	if params.block_data.len() > max_pov_size {
+		return Err(ValidationError::PoVTooLarge {
+			max: max_pov_size,
+			size: params.block_data.len(),
+		})
+	}
+
+	let block_data = VersionedParachainBlockData::<B>::decode(&mut &params.block_data[..])
+		.ok_or(ValidationError::InvalidBlockData)?
+		.into_latest();
+
+	let parent_head = B::Header::decode(&mut &params.parent_head[..])
+		.ok_or(ValidationError::InvalidParentHead)?;
+
+	if block_data.blocks.is_empty() {
+		return Err(ValidationError::NoBlocks)
+	}
+
+	let nodes = block_data.witness_data.into_nodes::<HasherOf<B>>()
+		.map_err(|_| ValidationError::InvalidWitnessData)?;
+
+	let size = nodes.iter().map(|n| n.len()).sum();
+	if size > max_pov_size {
+		return Err(ValidationError::PoVTooLarge { max: max_pov_size, size })
+	}
+
+	let witness_data = WitnessDB::new(nodes)?;
+
+	let storage_root = block_data.witness_data_storage_root;
+	if !witness_data.contains(&storage_root, &[]) {
+		return Err(ValidationError::StorageRootNotInWitness(storage_root.as_ref().to_vec()))
+	}
+
+	Ok(Preflight {
+		blocks: block_data.blocks,
+		parent_head,
+		witness_data,
+		witness_data_storage_root: storage_root,
+	})
+}
+
+/// The second phase of `validate_block`, which executes the blocks of the `preflight`.
+///
+/// The `downward_messages` are passed to the first block.
+#[doc(hidden)]
+pub fn execute<B: BlockT, E: ExecuteBlock<B>, I: ProvidePolkadotInherent<B>>(
+	preflight: Preflight<B>,
+	validation_data: Option<ValidationData>,
+	downward_messages: Vec<DownwardMessage>,
+) -> Result<(ValidationResult<B::Hash>, WitnessStats), ValidationError> {
+	// The collator strips the `PolkadotInherent` from the blocks and the validator injects it
+	// from its own `ValidationData`, so the collator can not lie about the relay chain data.
+	if I::REQUIRED && validation_data.is_none() {
+		return Err(ValidationError::MissingValidationData)
+	}
+
+	let mut storage = WitnessStorage::<B>::new(
+		preflight.witness_data,
+		preflight.witness_data_storage_root,
+	);
+
+	let mut budget = AllocationBudget::new(DEFAULT_ALLOCATION_BUDGET);
+	let mut parent_hash = preflight.parent_head.hash();
+	let mut storage_root = B::Hash::default();
+	let mut head_data = Vec::new();
+
+	let mut downward_messages_inherent = I::create_downward_messages_inherent(&downward_messages);
+	let processed_messages = if downward_messages_inherent.is_some() {
+		downward_messages.len() as u32
+	} else {
+		0
 	};
 
-	E::execute_block(block);
+	let mut upward_messages = Vec::new();
+	let mut horizontal_messages = Vec::new();
+	let mut hrmp_watermark = None;
+
+	for (header, mut extrinsics) in preflight.blocks {
+		if let Some(inherent) = downward_messages_inherent.take() {
+			extrinsics.insert(0, inherent);
+		}
+
+		if let Some(ref validation_data) = validation_data {
+			extrinsics.insert(0, I::create_inherent(validation_data));
+		}
+
+		let extrinsics_root = <HashingOf<B> as HashT>::ordered_trie_root(
+			extrinsics.iter().map(Encode::encode),
+		);
+		if extrinsics_root != *header.extrinsics_root() {
+			return Err(ValidationError::InvalidExtrinsicsRoot)
+		}
+
+		if parent_hash != *header.parent_hash() {
+			return Err(ValidationError::InvalidParentHash)
+		}
+
+		parent_hash = header.hash();
+		head_data = header.encode();
+		let state_root = *header.state_root();
+
+		execute_block::<B, E>(&mut storage, &mut budget, B::new(header, extrinsics));
+
+		storage_root = storage.calculate_storage_root();
+		if storage_root != state_root {
+			return Err(ValidationError::InvalidStorageRoot {
+				expected: state_root.as_ref().to_vec(),
+				got: storage_root.as_ref().to_vec(),
+			})
+		}
+
+		// The runtime writes or clears the keys of the messages in every block, so they are only
+		// read from the overlay and are not required to be part of the witness data.
+		if let Some(Some(messages)) = storage.overlay.get(UPWARD_MESSAGES_KEY) {
+			let messages = Vec::<Vec<u8>>::decode(&mut &messages[..])
+				.ok_or(ValidationError::InvalidUpwardMessages)?;
+			upward_messages.extend(messages);
+		}
+		if let Some(Some(messages)) = storage.overlay.get(HORIZONTAL_MESSAGES_KEY) {
+			let messages = Vec::<OutboundHrmpMessage>::decode(&mut &messages[..])
+				.ok_or(ValidationError::InvalidHorizontalMessages)?;
+			horizontal_messages.extend(messages);
+		}
+		if let Some(Some(watermark)) = storage.overlay.get(HRMP_WATERMARK_KEY) {
+			let watermark = u32::decode(&mut &watermark[..])
+				.ok_or(ValidationError::InvalidHrmpWatermark)?;
+			if validation_data.as_ref().map_or(false, |d| watermark > d.relay_parent_number) {
+				return Err(ValidationError::InvalidHrmpWatermark)
+			}
+			hrmp_watermark = Some(watermark);
+		}
+	}
+
+	// A code upgrade is only detected through the overlay, as the old code is not required to
+	// be part of the witness data.
+	let new_validation_code = match storage.overlay.get(well_known_keys::CODE) {
+		Some(Some(code)) => Some(code.clone()),
+		Some(None) => return Err(ValidationError::ValidationCodeRemoved),
+		None => None,
+	};
+
+	let stats = storage.witness_data.stats();
+	#[cfg(feature = "std")]
+	log::debug!(
+		target: "validate-block",
+		"Read {} of {} unique witness nodes ({} duplicates), {} of {} bytes were not used",
+		stats.read_nodes,
+		stats.nodes,
+		stats.duplicate_nodes,
+		stats.unused_size(),
+		stats.size,
+	);
+
+	let res = ValidationResult {
+		head_data,
+		storage_root,
+		processed_messages,
+		new_validation_code,
+		upward_messages,
+		horizontal_messages,
+		hrmp_watermark,
+	};
+	Ok((res, stats))
+}
+
+/// Write the given `result` into the memory and return a pointer to it.
+///
+/// As required by the parachain wasm ABI, the returned pointer points to the length of the
+/// encoded `result` that is appended after the encoded data.
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+pub fn write_result<R: Encode>(result: R) -> usize {
+	let mut encoded = result.encode();
+	let len = encoded.len();
+	assert!(len <= u32::max_value() as usize, "Len too large for parachain-WASM abi");
+	(len as u32).using_encoded(|s| encoded.extend(s));
+
+	// Do not alter `encoded` beyond this point, it may reallocate.
+	let end_ptr = &encoded[len] as *const u8 as usize;
+	// Leak the vector, so that the data stays alive for the caller.
+	mem::forget(encoded);
+	end_ptr
 }
 
 /// The storage implementation used when validating a block that is using the
 /// witness data as source.
+///
+/// Child tries are stored in the same witness data as the main trie, their roots are
+/// stored in the main trie under their storage key.
+///
+/// The witness data is never altered, all changes are kept in the overlays until the end of
+/// the validation. Storage roots are calculated in a temporary `OverlayDB`, so they can be
+/// calculated any number of times.
 struct WitnessStorage<B: BlockT> {
-	witness_data: MemoryDB<<HashingOf<B> as HashT>::Hasher>,
-	overlay: hashbrown::HashMap<Vec<u8>, Option<Vec<u8>>>,
+	witness_data: WitnessDB<HasherOf<B>>,
+	overlay: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+	/// The values that were read from the trie at `storage_root`, indexed by their key.
+	cache: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+	/// The overlays of the child tries, indexed by their storage key.
+	child_overlays: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+	/// The storage root of the witness data.
 	storage_root: B::Hash,
+	/// The storage root that was calculated last, reset by every change of the overlays.
+	calculated_root: Option<B::Hash>,
 }
 
 impl<B: BlockT> WitnessStorage<B> {
-	/// Initialize from the given witness data and storage root.
+	/// Initialize from the given witness data and storage root, as checked by `preflight`.
+	fn new(witness_data: WitnessDB<HasherOf<B>>, storage_root: B::Hash) -> Self {
+		Self {
+			witness_data,
+			overlay: Default::default(),
+			cache: Default::default(),
+			child_overlays: Default::default(),
+			storage_root,
+			calculated_root: None,
+		}
+	}
+
+	/// Calculate the storage root with the hashing algorithm of the block.
 	///
-	/// Returns an error if given storage root was not found in the witness data.
-	fn new(
-		data: WitnessData,
-		storage_root: B::Hash,
-	) -> Result<Self, &'static str> {
-		let mut db = MemoryDB::default();
-		data.into_iter().for_each(|i| { db.insert(&[], &i); });
+	/// The overlay is a `BTreeMap`, so the delta is passed sorted and without duplicates to
+	/// `delta_trie_root`. The root is only recalculated if the overlays changed since the last
+	/// call.
+	fn calculate_storage_root(&mut self) -> B::Hash {
+		if let Some(root) = self.calculated_root {
+			return root
+		}
+
+		// The child trie roots are part of the main trie.
+		let child_storage_keys = self.child_overlays.keys().cloned().collect::<Vec<_>>();
+		child_storage_keys.iter().for_each(|k| { self.child_storage_root(k); });
+
+		let root = delta_trie_root(
+			&mut OverlayDB::new(&self.witness_data),
+			self.storage_root.clone(),
+			self.overlay.iter().map(|(k, v)| (k, v.as_ref())),
+		).unwrap_or_else(|e| panic!(
+			"Failed to validate block: {:?}",
+			ValidationError::StorageRootCalculationFailed(trie_error_node(&*e)),
+		));
+
+		self.calculated_root = Some(root);
+		root
+	}
 
-		if !db.contains(&storage_root, &[]) {
-			return Err("Witness data does not contain given storage root.")
+	/// Set the given value in the overlay.
+	///
+	/// The calculated storage root is only reset if the value changed.
+	fn set(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+		if self.overlay.get(key) != Some(&value) {
+			self.overlay.insert(key.to_vec(), value);
+			self.calculated_root = None;
 		}
+	}
 
-		Ok(Self {
-			witness_data: db,
-			overlay: Default::default(),
-			storage_root,
-		})
+	/// Returns the value of the given key in the witness data, ignoring the overlay.
+	fn trie_value(&self, key: &[u8]) -> Option<Vec<u8>> {
+		if let Some(value) = self.cache.borrow().get(key) {
+			return value.clone()
+		}
+
+		let value = read_trie_value(
+			&self.witness_data,
+			&self.storage_root,
+			key,
+		).unwrap_or_else(|_| incomplete_witness(key));
+		self.cache.borrow_mut().insert(key.to_vec(), value.clone());
+		value
+	}
+
+	/// Returns the root of the given child trie in the witness data.
+	///
+	/// The root in the overlay is ignored, as the nodes of a calculated child trie root are not
+	/// part of the witness data.
+	fn child_root(&self, storage_key: &[u8]) -> Vec<u8> {
+		self.trie_value(storage_key)
+			.unwrap_or_else(|| default_child_trie_root::<HasherOf<B>>(storage_key))
 	}
 }
 
 impl<B: BlockT> Storage for WitnessStorage<B> {
 	fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-		self.overlay.get(key).cloned().or_else(|| {
-			read_trie_value(
-				&self.witness_data,
-				&self.storage_root,
-				key,
-			).ok()
-		}).unwrap_or(None)
+		match self.overlay.get(key) {
+			Some(value) => value.clone(),
+			None => self.trie_value(key),
+		}
 	}
 
 	fn insert(&mut self, key: &[u8], value: &[u8]) {
-		self.overlay.insert(key.to_vec(), Some(value.to_vec()));
+		self.set(key, Some(value.to_vec()));
 	}
 
 	fn remove(&mut self, key: &[u8]) {
-		self.overlay.insert(key.to_vec(), None);
+		self.set(key, None);
 	}
 
-	fn storage_root(&mut self) -> [u8; STORAGE_ROOT_LEN] {
-		let root = match delta_trie_root(
-			&mut self.witness_data,
-			self.storage_root.clone(),
-			self.overlay.drain()
-		) {
-			Ok(root) => root,
-			Err(_) => return [0; STORAGE_ROOT_LEN],
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		self.calculated_root = None;
+		self.overlay.range_mut::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+			.take_while(|(k, _)| k.starts_with(prefix))
+			.for_each(|(_, v)| *v = None);
+
+		let keys = {
+			let trie = TrieDB::<HasherOf<B>>::new(
+				&self.witness_data,
+				&self.storage_root,
+			).unwrap_or_else(|_| incomplete_witness(prefix));
+			let mut iter = trie.iter().unwrap_or_else(|_| incomplete_witness(prefix));
+			iter.seek(prefix).unwrap_or_else(|_| incomplete_witness(prefix));
+
+			iter.map(|x| x.unwrap_or_else(|_| incomplete_witness(prefix)).0)
+				.take_while(|k| k.starts_with(prefix))
+				.collect::<Vec<_>>()
 		};
 
-		assert!(root.as_ref().len() <= STORAGE_ROOT_LEN);
-		let mut res = [0; STORAGE_ROOT_LEN];
-		res.copy_from_slice(root.as_ref());
-		res
-	}
-}
-
-unsafe fn ext_get_allocated_storage(
-	key_data: *const u8,
-	key_len: u32,
-	written_out: *mut u32,
-) -> *mut u8 {
-	let key = slice::from_raw_parts(key_data, key_len as usize);
-	match STORAGE.as_mut().expect(STORAGE_SET_EXPECT).get(key) {
-		Some(value) => {
-			let mut out_value: Vec<_> = value.clone();
-			*written_out = out_value.len() as u32;
-			let ptr = out_value.as_mut_ptr();
-			mem::forget(out_value);
-			ptr
-		},
-		None => {
-			*written_out = u32::max_value();
-			ptr::null_mut()
+		keys.into_iter().for_each(|k| { self.overlay.insert(k, None); });
+	}
+
+	fn storage_root(&mut self) -> Vec<u8> {
+		self.calculate_storage_root().as_ref().to_vec()
+	}
+
+	fn get_child(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		match self.child_overlays.get(storage_key).and_then(|o| o.get(key)) {
+			Some(value) => value.clone(),
+			None => read_child_trie_value::<HasherOf<B>, _>(
+				storage_key,
+				&self.witness_data,
+				&self.child_root(storage_key),
+				key,
+			).unwrap_or_else(|_| incomplete_child_witness(storage_key, key)),
 		}
 	}
-}
 
-unsafe fn ext_set_storage(
-	key_data: *const u8,
-	key_len: u32,
-	value_data: *const u8,
-	value_len: u32,
-) {
-	let key = slice::from_raw_parts(key_data, key_len as usize);
-	let value = slice::from_raw_parts(value_data, value_len as usize);
+	fn insert_child(&mut self, storage_key: &[u8], key: &[u8], value: &[u8]) {
+		self.calculated_root = None;
+		self.child_overlays.entry(storage_key.to_vec())
+			.or_default()
+			.insert(key.to_vec(), Some(value.to_vec()));
+	}
 
-	STORAGE.as_mut().expect(STORAGE_SET_EXPECT).insert(key, value);
-}
+	fn remove_child(&mut self, storage_key: &[u8], key: &[u8]) {
+		self.calculated_root = None;
+		self.child_overlays.entry(storage_key.to_vec())
+			.or_default()
+			.insert(key.to_vec(), None);
+	}
 
-unsafe fn ext_get_storage_into(
-	key_data: *const u8,
-	key_len: u32,
-	value_data: *mut u8,
-	value_len: u32,
-	value_offset: u32,
-) -> u32 {
-	let key = slice::from_raw_parts(key_data, key_len as usize);
-	let out_value = slice::from_raw_parts_mut(value_data, value_len as usize);
+	fn kill_child(&mut self, storage_key: &[u8]) {
+		self.calculated_root = None;
+		let mut keys = Vec::new();
+		for_keys_in_child_trie::<HasherOf<B>, _, _>(
+			storage_key,
+			&self.witness_data,
+			&self.child_root(storage_key),
+			|k| keys.push(k.to_vec()),
+		).unwrap_or_else(|_| incomplete_child_witness(storage_key, &[]));
+
+		let overlay = self.child_overlays.entry(storage_key.to_vec()).or_default();
+		overlay.values_mut().for_each(|v| *v = None);
+		keys.into_iter().for_each(|k| { overlay.insert(k, None); });
+	}
 
-	match STORAGE.as_mut().expect(STORAGE_SET_EXPECT).get(key) {
-		Some(value) => {
-			let value = &value[value_offset as usize..];
-			let len = cmp::min(value_len as usize, value.len());
-			out_value[..len].copy_from_slice(&value[..len]);
-			len as u32
-		},
-		None => {
-			u32::max_value()
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Vec<u8> {
+		let root = self.child_root(storage_key);
+		let overlay = match self.child_overlays.get(storage_key) {
+			Some(overlay) => overlay,
+			None => return root,
+		};
+
+		let root = child_delta_trie_root::<HasherOf<B>, _, _, _, _>(
+			storage_key,
+			&mut OverlayDB::new(&self.witness_data),
+			root,
+			overlay.iter().map(|(k, v)| (k, v.as_ref())),
+		).unwrap_or_else(|e| panic!(
+			"Failed to validate block: {:?}",
+			ValidationError::ChildStorageRootCalculationFailed {
+				storage_key: storage_key.to_vec(),
+				node: trie_error_node(&*e),
+			},
+		));
+
+		// An empty child trie is removed from the main trie.
+		if root == default_child_trie_root::<HasherOf<B>>(storage_key) {
+			self.remove(storage_key);
+		} else {
+			self.insert(storage_key, &root);
 		}
+
+		root
 	}
 }
 
-unsafe fn ext_exists_storage(key_data: *const u8, key_len: u32) -> u32 {
-	let key = slice::from_raw_parts(key_data, key_len as usize);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rstd::mem;
+	use primitives::{Blake2Hasher, H256};
+	use runtime_primitives::testing::{Block as TestBlock, ExtrinsicWrapper};
+	use substrate_trie::{MemoryDB, TrieDBMut};
+	use trie_db::TrieMut;
+
+	type Block = TestBlock<ExtrinsicWrapper<u64>>;
+
+	/// Returns a `WitnessStorage` with a witness data that contains all nodes of a trie with the
+	/// given `items`.
+	fn witness_storage(items: &[(&[u8], &[u8])]) -> WitnessStorage<Block> {
+		let mut db = MemoryDB::<Blake2Hasher>::default();
+		let mut root = H256::default();
+		{
+			let mut trie = TrieDBMut::<Blake2Hasher>::new(&mut db, &mut root);
+			for (key, value) in items {
+				trie.insert(key, value).expect("Inserts into a new trie");
+			}
+		}
 
-	if STORAGE.as_mut().expect(STORAGE_SET_EXPECT).get(key).is_some() {
-		1
-	} else {
-		0
+		let nodes = db.drain()
+			.into_iter()
+			.filter(|(_, (_, rc))| *rc > 0)
+			.map(|(_, (node, _))| node.to_vec())
+			.collect();
+		WitnessStorage::new(WitnessDB::new(nodes).expect("Nodes of a valid trie"), root)
 	}
-}
 
-unsafe fn ext_clear_storage(key_data: *const u8, key_len: u32) {
-	let key = slice::from_raw_parts(key_data, key_len as usize);
+	#[test]
+	fn allocation_budget_may_be_used_up() {
+		let mut budget = AllocationBudget::new(10);
+		budget.allocate(4);
+		budget.allocate(6);
+		budget.allocate(0);
+	}
 
-	STORAGE.as_mut().expect(STORAGE_SET_EXPECT).remove(key);
-}
+	#[test]
+	#[should_panic(expected = "AllocationBudgetExceeded(10)")]
+	fn allocation_budget_is_cumulative() {
+		let mut budget = AllocationBudget::new(10);
+		budget.allocate(6);
+		budget.allocate(5);
+	}
+
+	#[test]
+	#[should_panic(expected = "AllocationBudgetExceeded(10)")]
+	fn allocation_budget_does_not_overflow() {
+		let mut budget = AllocationBudget::new(10);
+		budget.allocate(1);
+		budget.allocate(usize::max_value());
+	}
 
-unsafe fn ext_storage_root(result: *mut u8) {
-	let res = STORAGE.as_mut().expect(STORAGE_SET_EXPECT).storage_root();
-	let result = slice::from_raw_parts_mut(result, STORAGE_ROOT_LEN);
-	result.copy_from_slice(&res);
-}
\ No newline at end of file
+	#[test]
+	fn witness_values_are_read_once() {
+		let mut storage = witness_storage(&[(b"key", b"value"), (b"other", b"other value")]);
+
+		assert_eq!(storage.get(b"key"), Some(b"value".to_vec()));
+		assert_eq!(storage.get(b"missing"), None);
+		assert_eq!(
+			storage.cache.borrow().clone().into_iter().collect::<Vec<_>>(),
+			vec![(b"key".to_vec(), Some(b"value".to_vec())), (b"missing".to_vec(), None)],
+		);
+
+		// Without any trie nodes, the values can only be returned from the cache.
+		storage.witness_data = WitnessDB::new(Vec::new()).expect("No nodes are valid");
+		assert_eq!(storage.get(b"key"), Some(b"value".to_vec()));
+		assert_eq!(storage.get(b"missing"), None);
+	}
+
+	#[test]
+	fn overlay_values_are_not_cached() {
+		let mut storage = witness_storage(&[(b"key", b"value")]);
+
+		storage.insert(b"key", b"new value");
+		storage.insert(b"new key", b"value");
+		assert_eq!(storage.get(b"key"), Some(b"new value".to_vec()));
+		assert_eq!(storage.get(b"new key"), Some(b"value".to_vec()));
+		assert!(storage.cache.borrow().is_empty());
+
+		// The cache is independent of the overlay, it keeps the values of the witness data.
+		storage.remove(b"key");
+		assert_eq!(storage.get(b"key"), None);
+		assert_eq!(storage.trie_value(b"key"), Some(b"value".to_vec()));
+		assert_eq!(storage.cache.borrow().get(&b"key"[..]), Some(&Some(b"value".to_vec())));
+	}
+
+	#[test]
+	fn storage_root_is_reused_until_storage_changes() {
+		let mut storage = witness_storage(&[(b"key", b"value")]);
+		let witness_root = storage.storage_root;
+
+		storage.insert(b"new key", b"value");
+		let root = storage.calculate_storage_root();
+		assert_ne!(root, witness_root);
+		assert_eq!(storage.calculated_root, Some(root));
+
+		// Without any trie nodes, the root can only be the cached one.
+		let witness_data = mem::replace(
+			&mut storage.witness_data,
+			WitnessDB::new(Vec::new()).expect("No nodes are valid"),
+		);
+		assert_eq!(storage.storage_root(), root.as_ref().to_vec());
+		storage.witness_data = witness_data;
+
+		// Writing the same value again keeps the root.
+		storage.insert(b"new key", b"value");
+		assert_eq!(storage.calculated_root, Some(root));
+
+		storage.remove(b"new key");
+		assert_eq!(storage.calculated_root, None);
+		assert_eq!(storage.calculate_storage_root(), witness_root);
+	}
+
+	#[test]
+	fn storage_root_is_reset_by_all_writes() {
+		let mut storage = witness_storage(&[(b"key", b"value")]);
+		let writes: Vec<fn(&mut WitnessStorage<Block>)> = vec![
+			|s| s.insert(b"key", b"new value"),
+			|s| s.remove(b"key"),
+			|s| s.clear_prefix(b"k"),
+			|s| s.insert_child(b":child_storage:default:child", b"key", b"value"),
+			|s| s.remove_child(b":child_storage:default:child", b"key"),
+			|s| s.kill_child(b":child_storage:default:child"),
+		];
+
+		for write in writes {
+			storage.calculate_storage_root();
+			assert!(storage.calculated_root.is_some());
+			write(&mut storage);
+			assert_eq!(storage.calculated_root, None);
+		}
+	}
+}
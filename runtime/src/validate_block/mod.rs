@@ -16,36 +16,225 @@
 
 //! A module that enables a runtime to work as parachain.
 
-#[cfg(test)]
-mod tests;
-#[cfg(not(feature = "std"))]
 #[doc(hidden)]
 pub mod implementation;
+#[cfg(not(feature = "std"))]
+mod storage_functions;
+#[cfg(feature = "std")]
+mod externalities;
+mod witness_db;
+mod block_executor;
+mod signature_verification;
+
+pub use witness_db::WitnessStats;
+pub use block_executor::BlockExecutor;
+pub use signature_verification::{
+	SignatureVerification, skip_signature_verification, with_signature_verification,
+};
+
+/// Validate a parachain block natively.
+///
+/// Takes the same parameters as the `validate_block` function that is registered by
+/// `register_validate_block!`, but executes the block natively against the witness data.
+/// This is meant for debugging blocks that fail validation, as it supports logging of all
+/// storage accesses (`validate-block=trace`) and backtraces.
+#[cfg(feature = "std")]
+pub use implementation::validate_block;
+
+/// Validate a parachain block natively like `validate_block`.
+///
+/// Additionally returns the `WitnessStats` that show which part of the witness data was
+/// required to execute the block, e.g. for collators that want to inspect their PoV size.
+#[cfg(feature = "std")]
+pub use implementation::validate_block_with_stats;
+
+/// Validate a parachain block natively in two phases.
+///
+/// `preflight` decodes the block data and checks the witness data, `execute` executes the
+/// blocks. The `Preflight` can be cloned to execute the blocks again, e.g. when retrying a
+/// validation, without decoding and checking the witness data again.
+#[cfg(feature = "std")]
+pub use implementation::{preflight, execute, Preflight};
 
 #[cfg(not(feature = "std"))]
 #[doc(hidden)]
 pub use parachain;
 
+#[doc(hidden)]
+pub use executive::ExecuteBlock;
+
+/// The default maximum size in bytes of the block data and of the decoded witness data.
+///
+/// Used by `register_validate_block!` when no maximum size is given.
+pub const DEFAULT_MAX_POV_SIZE: usize = 5 * 1024 * 1024;
+
 /// Register the `validate_block` function that is used by parachains to validate blocks on a validator.
 ///
-/// Does *nothing* when `std` feature is enabled.
+/// The registered function returns the encoded `ValidationResult` of the block.
+///
+/// The function and the host function replacements it relies on are only compiled into the
+/// wasm runtime. With the `std` feature, a stub is registered instead that checks the
+/// parameters of the macro and panics when it is called. So the macro can be used without any
+/// `cfg` in a runtime that is compiled natively and to wasm.
+///
+/// Expects as parameters the block, the block executor and the type that implements
+/// `ProvidePolkadotInherent` for the block. Optionally, the maximum size in bytes of the block
+/// data and of the decoded witness data can be given, it defaults to `DEFAULT_MAX_POV_SIZE`.
+/// Validation is aborted as soon as one of them exceeds the maximum size.
+///
+/// Alternatively, the runtime and the `ProvidePolkadotInherent` type can be given by name. The
+/// block and the block executor are then taken from the `Block` and `Executive` type aliases
+/// that every runtime declares next to `construct_runtime!`. The block executor can be
+/// overridden with `BlockExecutor`. The macro fails to compile if the aliases are not in scope
+/// or if the block executor can not execute the block.
+///
+/// Runtimes whose blocks are sealed by their author, e.g. with Aura, need to wrap their block
+/// executor in `validate_block::BlockExecutor`, which removes the seal before the execution.
 ///
-/// Expects as parameters the block and the block executor.
+/// With the runtime given by name, `SignatureVerification = Skip` allows the runtime to skip
+/// the verification of signatures while the block is validated, see `SignatureVerification`
+/// for the trust implications. It defaults to `Full`.
 ///
 /// # Example
 ///
 /// ```
 ///     struct Block;
 ///     struct BlockExecutor;
+///     struct PolkadotInherent;
 ///
-///     cumulus_runtime::register_validate_block!(Block, BlockExecutor);
+///     cumulus_runtime::register_validate_block!(Block, BlockExecutor, PolkadotInherent);
 ///
 /// # fn main() {}
 /// ```
+///
+/// With a custom maximum size:
+///
+/// ```
+///     struct Block;
+///     struct BlockExecutor;
+///     struct PolkadotInherent;
+///
+///     cumulus_runtime::register_validate_block!(
+///         Block,
+///         BlockExecutor,
+///         PolkadotInherent,
+///         2 * 1024 * 1024
+///     );
+///
+/// # fn main() {}
+/// ```
+/// Taking the block and the block executor from the runtime:
+///
+/// ```ignore
+///     cumulus_runtime::register_validate_block!(
+///         Runtime = Runtime,
+///         PolkadotInherent = PolkadotInherent,
+///     );
+///
+///     cumulus_runtime::register_validate_block!(
+///         Runtime = Runtime,
+///         PolkadotInherent = PolkadotInherent,
+///         BlockExecutor = CustomExecutive,
+///     );
+///
+///     cumulus_runtime::register_validate_block!(
+///         Runtime = Runtime,
+///         PolkadotInherent = PolkadotInherent,
+///         BlockExecutor = cumulus_runtime::validate_block::BlockExecutor<Block, Executive>,
+///     );
+///
+///     cumulus_runtime::register_validate_block!(
+///         Runtime = Runtime,
+///         PolkadotInherent = PolkadotInherent,
+///         SignatureVerification = Skip,
+///     );
+/// ```
 #[macro_export]
 macro_rules! register_validate_block {
-	($block:ty, $block_executor:ty) => {
-		$crate::register_validate_block_impl!($block, $block_executor);
+	(Runtime = $runtime:ty, PolkadotInherent = $polkadot_inherent:ty $(,)?) => {
+		$crate::register_validate_block!(
+			Runtime = $runtime,
+			PolkadotInherent = $polkadot_inherent,
+			BlockExecutor = Executive,
+		);
+	};
+	(
+		Runtime = $runtime:ty,
+		PolkadotInherent = $polkadot_inherent:ty,
+		SignatureVerification = $signature_verification:ident $(,)?
+	) => {
+		$crate::register_validate_block!(
+			Runtime = $runtime,
+			PolkadotInherent = $polkadot_inherent,
+			BlockExecutor = Executive,
+			SignatureVerification = $signature_verification,
+		);
+	};
+	(
+		Runtime = $runtime:ty,
+		PolkadotInherent = $polkadot_inherent:ty,
+		BlockExecutor = $block_executor:ty $(,)?
+	) => {
+		$crate::register_validate_block!(
+			Runtime = $runtime,
+			PolkadotInherent = $polkadot_inherent,
+			BlockExecutor = $block_executor,
+			SignatureVerification = Full,
+		);
+	};
+	(
+		Runtime = $runtime:ty,
+		PolkadotInherent = $polkadot_inherent:ty,
+		BlockExecutor = $block_executor:ty,
+		SignatureVerification = $signature_verification:ident $(,)?
+	) => {
+		#[doc(hidden)]
+		#[allow(dead_code)]
+		mod parachain_validate_block_requirements {
+			use super::*;
+
+			// `register_validate_block!` requires the `Runtime` and its `Block` type alias in
+			// scope, and an executor of the block, `Executive` if no `BlockExecutor` is given.
+			type RequiredRuntime = $runtime;
+			type RequiredBlock = Block;
+
+			fn block_executor_executes_block() where
+				$block_executor: $crate::validate_block::ExecuteBlock<Block> {}
+		}
+
+		$crate::register_validate_block_impl!(
+			Block,
+			$block_executor,
+			$polkadot_inherent,
+			$crate::validate_block::DEFAULT_MAX_POV_SIZE,
+			$crate::validate_block::SignatureVerification::$signature_verification
+		);
+	};
+	($block:ty, $block_executor:ty, $polkadot_inherent:ty) => {
+		$crate::register_validate_block_impl!(
+			$block,
+			$block_executor,
+			$polkadot_inherent,
+			$crate::validate_block::DEFAULT_MAX_POV_SIZE,
+			$crate::validate_block::SignatureVerification::Full
+		);
+	};
+	($block:ty, $block_executor:ty, $polkadot_inherent:ty, $max_pov_size:expr) => {
+		$crate::register_validate_block_impl!(
+			$block,
+			$block_executor,
+			$polkadot_inherent,
+			$max_pov_size,
+			$crate::validate_block::SignatureVerification::Full
+		);
+	};
+	($($invalid:tt)*) => {
+		compile_error!(
+			"Expected `register_validate_block!(Runtime = Runtime, PolkadotInherent = Type)` with \
+			an optional `BlockExecutor = Type` and `SignatureVerification = Full | Skip`, or \
+			`register_validate_block!(Block, BlockExecutor, PolkadotInherent)` with an optional \
+			maximum PoV size"
+		);
 	};
 }
 
@@ -54,7 +243,13 @@ macro_rules! register_validate_block {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! register_validate_block_impl {
-	($block:ty, $block_executor:ty) => {
+	(
+		$block:ty,
+		$block_executor:ty,
+		$polkadot_inherent:ty,
+		$max_pov_size:expr,
+		$signature_verification:expr
+	) => {
 		#[doc(hidden)]
 		mod parachain_validate_block {
 			use super::*;
@@ -63,15 +258,23 @@ macro_rules! register_validate_block_impl {
 			unsafe fn validate_block(
 				arguments: *const u8,
 				arguments_len: usize,
-			) {
-				let params = $crate::validate_block::parachain::wasm_api::load_params(
+			) -> usize {
+				let res = $crate::validate_block::implementation::load_params(
 					arguments,
 					arguments_len,
+				).and_then(|(params, validation_data)|
+					$crate::validate_block::with_signature_verification(
+						$signature_verification,
+						|| $crate::validate_block::implementation::validate_block::<
+							$block, $block_executor, $polkadot_inherent
+						>(params, validation_data, $max_pov_size),
+					)
 				);
 
-				$crate::validate_block::implementation::validate_block::<
-					$block, $block_executor
-				>(params);
+				match res {
+					Ok(res) => $crate::validate_block::implementation::write_result(res),
+					Err(e) => panic!("Failed to validate block: {:?}", e),
+				}
 			}
 		}
 	};
@@ -82,5 +285,32 @@ macro_rules! register_validate_block_impl {
 #[doc(hidden)]
 #[macro_export]
 macro_rules! register_validate_block_impl {
-	($block:ty, $block_executor:ty) => {};
+	(
+		$block:ty,
+		$block_executor:ty,
+		$polkadot_inherent:ty,
+		$max_pov_size:expr,
+		$signature_verification:expr
+	) => {
+		#[doc(hidden)]
+		mod parachain_validate_block {
+			use super::*;
+
+			/// The native stub of the `validate_block` function of the wasm runtime.
+			#[allow(dead_code)]
+			pub fn validate_block(_arguments: *const u8, _arguments_len: usize) -> usize {
+				let _max_pov_size: usize = $max_pov_size;
+				let _signature_verification: $crate::validate_block::SignatureVerification =
+					$signature_verification;
+				let _validate = $crate::validate_block::implementation::validate_block::<
+					$block, $block_executor, $polkadot_inherent
+				>;
+
+				panic!(
+					"`validate_block` is only available in the wasm runtime, use \
+					`cumulus_runtime::validate_block::validate_block` to validate a block natively"
+				)
+			}
+		}
+	};
 }
\ No newline at end of file
@@ -0,0 +1,68 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The signature verification mode of the runtime while `validate_block` executes a block.
+
+// The mode that is used while executing the blocks, set by `with_signature_verification`.
+environmental::environmental!(mode: SignatureVerification);
+
+/// Decides whether the runtime verifies signatures while `validate_block` executes a block.
+///
+/// `validate_block` does not verify any signature itself, the runtime decides which signature
+/// checks it skips by calling `skip_signature_verification`. Blocks that are built or imported
+/// by a collator or a full node are always executed with all signatures verified.
+///
+/// # Trust implications
+///
+/// The collators are not trusted, the validators are the only ones that guarantee that a block
+/// on the relay chain is valid. With `Skip`, the validators accept blocks with invalid or
+/// forged signatures, e.g. a transfer from an account that the author of the block does not
+/// own. `Skip` is only safe if the validity of these signatures is ensured by other means, e.g.
+/// a parachain whose collators are permissioned and trusted, or a test network.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum SignatureVerification {
+	/// Verify all signatures, the default.
+	Full,
+	/// Allow the runtime to skip the verification of signatures.
+	Skip,
+}
+
+impl Default for SignatureVerification {
+	fn default() -> Self {
+		SignatureVerification::Full
+	}
+}
+
+/// Returns if the runtime may skip the verification of signatures.
+///
+/// Only returns `true` while `validate_block` executes a block with
+/// `SignatureVerification::Skip`. The runtime calls it e.g. in the signature check of its
+/// extrinsics, to choose a lighter verification while the block is validated.
+pub fn skip_signature_verification() -> bool {
+	mode::with(|mode| *mode == SignatureVerification::Skip).unwrap_or(false)
+}
+
+/// Run `f` with the given signature verification `mode`.
+///
+/// `register_validate_block!` wraps the validation of the block with it. The previous mode is
+/// restored on return.
+pub fn with_signature_verification<R>(
+	mut verification: SignatureVerification,
+	f: impl FnOnce() -> R,
+) -> R {
+	mode::using(&mut verification, f)
+}
@@ -0,0 +1,312 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The storage host functions that are replaced while executing a block in `validate_block`.
+
+use super::implementation::{Storage, AllocationBudget};
+use runtime_primitives::traits::Block as BlockT;
+use executive::ExecuteBlock;
+use primitives::Blake2Hasher;
+
+use rstd::{slice, ptr, cmp, vec::Vec, mem};
+
+// The storage that is used while executing the block, set by `execute_block`.
+//
+// `storage::using` restores the previously set storage on return, which makes nested
+// calls of `validate_block` safe.
+environmental::environmental!(storage: trait Storage);
+// The budget for the values that are allocated by the host functions, set by `execute_block`.
+environmental::environmental!(budget: AllocationBudget);
+
+/// The message to use as expect message while accessing the `storage`.
+const STORAGE_SET_EXPECT: &str =
+	"`storage` needs to be set before calling this function.";
+
+/// Execute the given block with the storage host functions replaced by implementations that
+/// use the given `storage`.
+///
+/// All values that are allocated by the host functions are accounted in the given `budget`.
+pub fn execute_block<B: BlockT, E: ExecuteBlock<B>>(
+	storage: &mut dyn Storage,
+	budget: &mut AllocationBudget,
+	block: B,
+) {
+	let _guard = (
+		// Replace storage calls with our own implementations
+		rio::ext_get_allocated_storage.replace_implementation(ext_get_allocated_storage),
+		rio::ext_get_storage_into.replace_implementation(ext_get_storage_into),
+		rio::ext_set_storage.replace_implementation(ext_set_storage),
+		rio::ext_exists_storage.replace_implementation(ext_exists_storage),
+		rio::ext_clear_storage.replace_implementation(ext_clear_storage),
+		rio::ext_clear_prefix.replace_implementation(ext_clear_prefix),
+		rio::ext_storage_root.replace_implementation(ext_storage_root),
+		rio::ext_get_allocated_child_storage
+			.replace_implementation(ext_get_allocated_child_storage),
+		rio::ext_get_child_storage_into.replace_implementation(ext_get_child_storage_into),
+		rio::ext_set_child_storage.replace_implementation(ext_set_child_storage),
+		rio::ext_exists_child_storage.replace_implementation(ext_exists_child_storage),
+		rio::ext_clear_child_storage.replace_implementation(ext_clear_child_storage),
+		rio::ext_kill_child_storage.replace_implementation(ext_kill_child_storage),
+		rio::ext_child_storage_root.replace_implementation(ext_child_storage_root),
+		rio::ext_storage_changes_root.replace_implementation(ext_storage_changes_root),
+		rio::ext_blake2_256_enumerated_trie_root
+			.replace_implementation(ext_blake2_256_enumerated_trie_root),
+	);
+
+	storage::using(storage, || budget::using(budget, || E::execute_block(block)));
+}
+
+/// Account for the allocation of `size` bytes that are returned to the runtime.
+fn allocate(size: usize) {
+	budget::with(|b| b.allocate(size)).expect("`budget` is set together with `storage`.");
+}
+
+unsafe fn ext_get_allocated_storage(
+	key_data: *const u8,
+	key_len: u32,
+	written_out: *mut u32,
+) -> *mut u8 {
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	match storage::with(|s| s.get(key)).expect(STORAGE_SET_EXPECT) {
+		Some(value) => {
+			let mut out_value: Vec<_> = value.clone();
+			allocate(out_value.len());
+			*written_out = out_value.len() as u32;
+			let ptr = out_value.as_mut_ptr();
+			mem::forget(out_value);
+			ptr
+		},
+		None => {
+			*written_out = u32::max_value();
+			ptr::null_mut()
+		}
+	}
+}
+
+unsafe fn ext_set_storage(
+	key_data: *const u8,
+	key_len: u32,
+	value_data: *const u8,
+	value_len: u32,
+) {
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	let value = slice::from_raw_parts(value_data, value_len as usize);
+
+	storage::with(|s| s.insert(key, value)).expect(STORAGE_SET_EXPECT);
+}
+
+unsafe fn ext_get_storage_into(
+	key_data: *const u8,
+	key_len: u32,
+	value_data: *mut u8,
+	value_len: u32,
+	value_offset: u32,
+) -> u32 {
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	let out_value = slice::from_raw_parts_mut(value_data, value_len as usize);
+
+	match storage::with(|s| s.get(key)).expect(STORAGE_SET_EXPECT) {
+		Some(value) => {
+			let value = &value[value_offset as usize..];
+			let len = cmp::min(value_len as usize, value.len());
+			out_value[..len].copy_from_slice(&value[..len]);
+			len as u32
+		},
+		None => {
+			u32::max_value()
+		}
+	}
+}
+
+unsafe fn ext_exists_storage(key_data: *const u8, key_len: u32) -> u32 {
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+
+	if storage::with(|s| s.get(key)).expect(STORAGE_SET_EXPECT).is_some() {
+		1
+	} else {
+		0
+	}
+}
+
+unsafe fn ext_clear_storage(key_data: *const u8, key_len: u32) {
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+
+	storage::with(|s| s.remove(key)).expect(STORAGE_SET_EXPECT);
+}
+
+unsafe fn ext_clear_prefix(prefix_data: *const u8, prefix_len: u32) {
+	let prefix = slice::from_raw_parts(prefix_data, prefix_len as usize);
+
+	storage::with(|s| s.clear_prefix(prefix)).expect(STORAGE_SET_EXPECT);
+}
+
+/// The length of the storage root that the runtime expects from `ext_storage_root`.
+const HOST_STORAGE_ROOT_LEN: usize = 32;
+
+unsafe fn ext_storage_root(result: *mut u8) {
+	let res = storage::with(|s| s.storage_root()).expect(STORAGE_SET_EXPECT);
+	assert_eq!(
+		res.len(),
+		HOST_STORAGE_ROOT_LEN,
+		"`ext_storage_root` only supports storage roots of 32 bytes",
+	);
+	let result = slice::from_raw_parts_mut(result, HOST_STORAGE_ROOT_LEN);
+	result.copy_from_slice(&res);
+}
+
+/// Changes tries are not supported while validating a block, so there is never a changes root.
+unsafe fn ext_storage_changes_root(
+	_parent_hash_data: *const u8,
+	_parent_hash_len: u32,
+	_result: *mut u8,
+) -> u32 {
+	0
+}
+
+unsafe fn ext_blake2_256_enumerated_trie_root(
+	values_data: *const u8,
+	lens_data: *const u32,
+	lens_len: u32,
+	result: *mut u8,
+) {
+	let lens = slice::from_raw_parts(lens_data, lens_len as usize);
+	let mut offset = 0;
+	let values = lens.iter().map(|len| {
+		let value = slice::from_raw_parts(values_data.add(offset), *len as usize);
+		offset += *len as usize;
+		value
+	});
+
+	let root = substrate_trie::ordered_trie_root::<Blake2Hasher, _, _>(values);
+	let result = slice::from_raw_parts_mut(result, HOST_STORAGE_ROOT_LEN);
+	result.copy_from_slice(root.as_ref());
+}
+
+unsafe fn ext_get_allocated_child_storage(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	key_data: *const u8,
+	key_len: u32,
+	written_out: *mut u32,
+) -> *mut u8 {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	match storage::with(|s| s.get_child(storage_key, key)).expect(STORAGE_SET_EXPECT) {
+		Some(value) => {
+			let mut out_value: Vec<_> = value.clone();
+			allocate(out_value.len());
+			*written_out = out_value.len() as u32;
+			let ptr = out_value.as_mut_ptr();
+			mem::forget(out_value);
+			ptr
+		},
+		None => {
+			*written_out = u32::max_value();
+			ptr::null_mut()
+		}
+	}
+}
+
+unsafe fn ext_get_child_storage_into(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	key_data: *const u8,
+	key_len: u32,
+	value_data: *mut u8,
+	value_len: u32,
+	value_offset: u32,
+) -> u32 {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	let out_value = slice::from_raw_parts_mut(value_data, value_len as usize);
+
+	match storage::with(|s| s.get_child(storage_key, key)).expect(STORAGE_SET_EXPECT) {
+		Some(value) => {
+			let value = &value[value_offset as usize..];
+			let len = cmp::min(value_len as usize, value.len());
+			out_value[..len].copy_from_slice(&value[..len]);
+			len as u32
+		},
+		None => {
+			u32::max_value()
+		}
+	}
+}
+
+unsafe fn ext_set_child_storage(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	key_data: *const u8,
+	key_len: u32,
+	value_data: *const u8,
+	value_len: u32,
+) {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+	let value = slice::from_raw_parts(value_data, value_len as usize);
+
+	storage::with(|s| s.insert_child(storage_key, key, value)).expect(STORAGE_SET_EXPECT);
+}
+
+unsafe fn ext_exists_child_storage(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	key_data: *const u8,
+	key_len: u32,
+) -> u32 {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+
+	if storage::with(|s| s.get_child(storage_key, key)).expect(STORAGE_SET_EXPECT).is_some() {
+		1
+	} else {
+		0
+	}
+}
+
+unsafe fn ext_clear_child_storage(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	key_data: *const u8,
+	key_len: u32,
+) {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let key = slice::from_raw_parts(key_data, key_len as usize);
+
+	storage::with(|s| s.remove_child(storage_key, key)).expect(STORAGE_SET_EXPECT);
+}
+
+unsafe fn ext_kill_child_storage(storage_key_data: *const u8, storage_key_len: u32) {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+
+	storage::with(|s| s.kill_child(storage_key)).expect(STORAGE_SET_EXPECT);
+}
+
+unsafe fn ext_child_storage_root(
+	storage_key_data: *const u8,
+	storage_key_len: u32,
+	written_out: *mut u32,
+) -> *mut u8 {
+	let storage_key = slice::from_raw_parts(storage_key_data, storage_key_len as usize);
+	let mut root = storage::with(|s| s.child_storage_root(storage_key))
+		.expect(STORAGE_SET_EXPECT);
+
+	allocate(root.len());
+	*written_out = root.len() as u32;
+	let ptr = root.as_mut_ptr();
+	mem::forget(root);
+	ptr
+}
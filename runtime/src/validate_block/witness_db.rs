@@ -0,0 +1,236 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A database of trie nodes that records which nodes of the witness data are read.
+//!
+//! Also provides `OverlayDB` to calculate storage roots without altering the witness data.
+
+use crate::ValidationError;
+use hash_db::{Hasher, HashDB, HashDBRef, AsHashDB};
+use substrate_trie::{MemoryDB, NodeCodec};
+use trie_db::{DBValue, NodeCodec as NodeCodecT};
+
+use rstd::{vec::Vec, collections::btree_map::BTreeMap};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Statistics about the usage of the witness data.
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "std", derive(Debug, PartialEq))]
+pub struct WitnessStats {
+	/// The number of unique trie nodes in the witness data.
+	pub nodes: usize,
+	/// The number of trie nodes that were duplicates of other nodes in the witness data.
+	pub duplicate_nodes: usize,
+	/// The size in bytes of all trie nodes in the witness data.
+	pub size: usize,
+	/// The number of trie nodes of the witness data that were read.
+	pub read_nodes: usize,
+	/// The size in bytes of the trie nodes of the witness data that were read.
+	pub read_size: usize,
+}
+
+impl WitnessStats {
+	/// The size in bytes of the trie nodes of the witness data that were never read.
+	pub fn unused_size(&self) -> usize {
+		self.size - self.read_size
+	}
+}
+
+/// A node of the witness data.
+struct WitnessNode {
+	/// The size of the encoded node.
+	size: usize,
+	/// Has the node been read?
+	///
+	/// Nodes are read through `&self`, an atomic keeps `WitnessDB` `Sync` as required by `HashDB`.
+	read: AtomicBool,
+}
+
+/// A `MemoryDB` that is initialized with the witness data and records all reads of its nodes.
+///
+/// Nodes that are inserted later on, e.g. while calculating the storage root, are not recorded.
+pub(crate) struct WitnessDB<H: Hasher> {
+	db: MemoryDB<H>,
+	witness: BTreeMap<H::Out, WitnessNode>,
+	duplicate_nodes: usize,
+}
+
+impl Clone for WitnessNode {
+	fn clone(&self) -> Self {
+		Self { size: self.size, read: AtomicBool::new(self.read.load(Ordering::Relaxed)) }
+	}
+}
+
+impl<H: Hasher> Clone for WitnessDB<H> {
+	fn clone(&self) -> Self {
+		Self {
+			db: self.db.clone(),
+			witness: self.witness.clone(),
+			duplicate_nodes: self.duplicate_nodes,
+		}
+	}
+}
+
+impl<H: Hasher> WitnessDB<H> {
+	/// Create a new instance from the given trie nodes.
+	///
+	/// Duplicate nodes are only inserted once. The nodes are indexed by the hash of their
+	/// content, so a node can not be stored under a wrong hash. Returns an error with the hash
+	/// of the first node that is not a valid trie node.
+	pub fn new(nodes: Vec<Vec<u8>>) -> Result<Self, ValidationError> {
+		let mut db = MemoryDB::default();
+		let mut witness = BTreeMap::new();
+		let mut duplicate_nodes = 0;
+
+		for node in nodes {
+			let hash = H::hash(&node);
+			if witness.contains_key(&hash) {
+				duplicate_nodes += 1;
+				continue
+			}
+
+			if <NodeCodec<H> as NodeCodecT<H>>::decode(&node).is_err() {
+				return Err(ValidationError::InvalidWitnessNode(hash.as_ref().to_vec()))
+			}
+
+			HashDB::emplace(&mut db, hash, &[], DBValue::from_slice(&node));
+			witness.insert(hash, WitnessNode { size: node.len(), read: AtomicBool::new(false) });
+		}
+
+		Ok(Self { db, witness, duplicate_nodes })
+	}
+
+	/// Returns the statistics about the usage of the witness data.
+	pub fn stats(&self) -> WitnessStats {
+		let stats = WitnessStats { duplicate_nodes: self.duplicate_nodes, ..Default::default() };
+		self.witness.values().fold(stats, |mut stats, node| {
+			stats.nodes += 1;
+			stats.size += node.size;
+			if node.read.load(Ordering::Relaxed) {
+				stats.read_nodes += 1;
+				stats.read_size += node.size;
+			}
+			stats
+		})
+	}
+
+	/// Record that the node with the given hash was read.
+	fn record(&self, key: &H::Out) {
+		if let Some(node) = self.witness.get(key) {
+			node.read.store(true, Ordering::Relaxed);
+		}
+	}
+}
+
+impl<H: Hasher> HashDB<H, DBValue> for WitnessDB<H> {
+	fn get(&self, key: &H::Out, prefix: &[u8]) -> Option<DBValue> {
+		self.record(key);
+		HashDB::get(&self.db, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: &[u8]) -> bool {
+		HashDB::contains(&self.db, key, prefix)
+	}
+
+	fn insert(&mut self, prefix: &[u8], value: &[u8]) -> H::Out {
+		HashDB::insert(&mut self.db, prefix, value)
+	}
+
+	fn emplace(&mut self, key: H::Out, prefix: &[u8], value: DBValue) {
+		HashDB::emplace(&mut self.db, key, prefix, value)
+	}
+
+	fn remove(&mut self, key: &H::Out, prefix: &[u8]) {
+		HashDB::remove(&mut self.db, key, prefix)
+	}
+}
+
+impl<H: Hasher> HashDBRef<H, DBValue> for WitnessDB<H> {
+	fn get(&self, key: &H::Out, prefix: &[u8]) -> Option<DBValue> {
+		HashDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: &[u8]) -> bool {
+		HashDB::contains(self, key, prefix)
+	}
+}
+
+impl<H: Hasher> AsHashDB<H, DBValue> for WitnessDB<H> {
+	fn as_hash_db(&self) -> &dyn HashDB<H, DBValue> {
+		self
+	}
+
+	fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn HashDB<H, DBValue> + 'a) {
+		self
+	}
+}
+
+/// A database that stores all changes in memory on top of a `WitnessDB`.
+///
+/// The `WitnessDB` is never altered, so the changes are discarded when this is dropped.
+pub(crate) struct OverlayDB<'a, H: Hasher> {
+	base: &'a WitnessDB<H>,
+	changes: MemoryDB<H>,
+}
+
+impl<'a, H: Hasher> OverlayDB<'a, H> {
+	/// Create a new instance on top of the given `base`.
+	pub fn new(base: &'a WitnessDB<H>) -> Self {
+		Self { base, changes: MemoryDB::default() }
+	}
+}
+
+impl<'a, H: Hasher> HashDB<H, DBValue> for OverlayDB<'a, H> {
+	fn get(&self, key: &H::Out, prefix: &[u8]) -> Option<DBValue> {
+		HashDB::get(&self.changes, key, prefix).or_else(|| HashDB::get(self.base, key, prefix))
+	}
+
+	fn contains(&self, key: &H::Out, prefix: &[u8]) -> bool {
+		HashDB::contains(&self.changes, key, prefix) || HashDB::contains(self.base, key, prefix)
+	}
+
+	fn insert(&mut self, prefix: &[u8], value: &[u8]) -> H::Out {
+		HashDB::insert(&mut self.changes, prefix, value)
+	}
+
+	fn emplace(&mut self, key: H::Out, prefix: &[u8], value: DBValue) {
+		HashDB::emplace(&mut self.changes, key, prefix, value)
+	}
+
+	fn remove(&mut self, key: &H::Out, prefix: &[u8]) {
+		HashDB::remove(&mut self.changes, key, prefix)
+	}
+}
+
+impl<'a, H: Hasher> HashDBRef<H, DBValue> for OverlayDB<'a, H> {
+	fn get(&self, key: &H::Out, prefix: &[u8]) -> Option<DBValue> {
+		HashDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: &[u8]) -> bool {
+		HashDB::contains(self, key, prefix)
+	}
+}
+
+impl<'a, H: Hasher> AsHashDB<H, DBValue> for OverlayDB<'a, H> {
+	fn as_hash_db(&self) -> &dyn HashDB<H, DBValue> {
+		self
+	}
+
+	fn as_hash_db_mut<'b>(&'b mut self) -> &'b mut (dyn HashDB<H, DBValue> + 'b) {
+		self
+	}
+}
@@ -0,0 +1,276 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The witness data and its encodings.
+//!
+//! The compact encoding stores the trie nodes of each trie in the proof in pre-order. A child
+//! reference to a node that is part of the encoding is replaced by an empty reference, as its
+//! hash can be recalculated while decoding.
+
+use rstd::{vec::Vec, collections::btree_map::BTreeMap, collections::btree_set::BTreeSet};
+use codec::{Encode, Decode};
+use hash_db::Hasher;
+use trie_db::{NodeCodec as NodeCodecT, ChildReference, DBValue, node::Node};
+use substrate_trie::NodeCodec;
+
+/// The maximum depth of a node in the compact encoding.
+///
+/// Every branch and extension node consumes at least one nibble of the key, so this supports
+/// keys of up to 256 bytes. It bounds the recursion while decoding untrusted witness data.
+const MAX_COMPACT_DEPTH: usize = 512;
+
+/// The data that is required to emulate the storage accesses executed by all extrinsics.
+///
+/// The variant index is encoded as first byte and identifies the encoding of the trie nodes.
+#[derive(Encode, Decode, Clone)]
+#[cfg_attr(feature = "std", derive(Debug, PartialEq))]
+pub enum WitnessData {
+	/// All trie nodes of the storage proof, in any order.
+	Full(Vec<Vec<u8>>),
+	/// The trie nodes of the storage proof in the compact encoding.
+	Compact(Vec<Vec<u8>>),
+}
+
+impl From<Vec<Vec<u8>>> for WitnessData {
+	fn from(nodes: Vec<Vec<u8>>) -> Self {
+		WitnessData::Full(nodes)
+	}
+}
+
+impl WitnessData {
+	/// Create compact witness data from the given storage proof.
+	///
+	/// The trie that starts at `storage_root` is encoded first, followed by all other tries of
+	/// the proof, e.g. child tries.
+	pub fn compact<H: Hasher>(proof: Vec<Vec<u8>>, storage_root: &H::Out) -> Self {
+		let mut nodes = proof.into_iter()
+			.map(|n| (H::hash(&n).as_ref().to_vec(), n))
+			.collect::<BTreeMap<_, _>>();
+
+		let referenced = nodes.values()
+			.filter_map(|n| <NodeCodec<H> as NodeCodecT<H>>::decode(n).ok())
+			.flat_map(|n| child_hashes::<H>(&n))
+			.collect::<BTreeSet<_>>();
+		let other_roots = nodes.keys()
+			.filter(|h| !referenced.contains(*h) && h.as_slice() != storage_root.as_ref())
+			.cloned()
+			.collect::<Vec<_>>();
+
+		let mut encoded = Vec::with_capacity(nodes.len());
+		let roots = rstd::iter::once(storage_root.as_ref().to_vec()).chain(other_roots);
+		for root in roots {
+			if let Some(data) = nodes.remove(&root) {
+				encode_compact::<H>(data, &mut nodes, &mut encoded);
+			}
+		}
+
+		// Nodes that are only referenced by nodes that are not part of the proof.
+		while let Some(hash) = nodes.keys().next().cloned() {
+			if let Some(data) = nodes.remove(&hash) {
+				encode_compact::<H>(data, &mut nodes, &mut encoded);
+			}
+		}
+
+		WitnessData::Compact(encoded)
+	}
+
+	/// Returns all trie nodes of the witness data.
+	///
+	/// Returns an error if the compact encoding is invalid.
+	pub fn into_nodes<H: Hasher>(self) -> Result<Vec<Vec<u8>>, ()> {
+		match self {
+			WitnessData::Full(nodes) => Ok(nodes),
+			WitnessData::Compact(encoded) => {
+				let mut nodes = Vec::with_capacity(encoded.len());
+				let mut encoded = encoded.into_iter().peekable();
+
+				while encoded.peek().is_some() {
+					decode_compact::<H, _>(&mut encoded, &mut nodes, 0)?;
+				}
+
+				Ok(nodes)
+			}
+		}
+	}
+}
+
+/// Returns the hashes of all children of the given node that are referenced by hash.
+fn child_hashes<H: Hasher>(node: &Node) -> Vec<Vec<u8>> {
+	let children = match node {
+		Node::Extension(_, child) => rstd::iter::once(*child).collect(),
+		Node::Branch(children, _) => children.iter().filter_map(|c| *c).collect(),
+		Node::Empty | Node::Leaf(..) => Vec::new(),
+	};
+
+	children.into_iter().filter(|c| c.len() == H::LENGTH).map(|c| c.to_vec()).collect()
+}
+
+/// Encode the given trie node and all its descendants that are in `nodes`.
+///
+/// All encoded descendants are removed from `nodes`.
+fn encode_compact<H: Hasher>(
+	data: Vec<u8>,
+	nodes: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+	encoded: &mut Vec<Vec<u8>>,
+) {
+	let node = match <NodeCodec<H> as NodeCodecT<H>>::decode(&data) {
+		Ok(node) => node,
+		// Keep nodes we can not decode as they are, they can not reference other nodes.
+		Err(_) => {
+			encoded.push(data);
+			return
+		}
+	};
+
+	// Children are removed from `nodes` directly, so that they are encoded exactly once and
+	// right after their parent.
+	let mut omitted = Vec::new();
+	let mut reference = |c: &[u8]| match nodes.remove(c) {
+		Some(child) => {
+			omitted.push(child);
+			empty_reference::<H>()
+		},
+		None => child_reference::<H>(c),
+	};
+
+	let node = match node {
+		Node::Extension(partial, child) => {
+			let child = reference(child);
+			<NodeCodec<H> as NodeCodecT<H>>::ext_node(&partial.encoded(false), child)
+		},
+		Node::Branch(children, value) => {
+			let children = children.iter().map(|c| c.map(&mut reference)).collect::<Vec<_>>();
+			<NodeCodec<H> as NodeCodecT<H>>::branch_node(
+				children.into_iter(),
+				value.map(DBValue::from_slice),
+			)
+		},
+		Node::Empty | Node::Leaf(..) => data.clone(),
+	};
+
+	encoded.push(node);
+	omitted.into_iter().for_each(|c| encode_compact::<H>(c, nodes, encoded));
+}
+
+/// Decode the next trie node and all its omitted descendants from `encoded`.
+///
+/// The decoded nodes are added to `nodes`. Returns the hash of the decoded node. Fails if the
+/// node is deeper than `MAX_COMPACT_DEPTH`.
+fn decode_compact<H: Hasher, I: Iterator<Item = Vec<u8>>>(
+	encoded: &mut I,
+	nodes: &mut Vec<Vec<u8>>,
+	depth: usize,
+) -> Result<H::Out, ()> {
+	if depth > MAX_COMPACT_DEPTH {
+		return Err(())
+	}
+
+	let data = encoded.next().ok_or(())?;
+
+	let node = match <NodeCodec<H> as NodeCodecT<H>>::decode(&data).map_err(|_| ())? {
+		Node::Extension(partial, child) => {
+			let child = decode_child_reference::<H, _>(child, encoded, nodes, depth)?;
+			<NodeCodec<H> as NodeCodecT<H>>::ext_node(&partial.encoded(false), child)
+		},
+		Node::Branch(children, value) => {
+			let mut references = Vec::with_capacity(children.len());
+			for child in children.iter() {
+				references.push(match child {
+					Some(c) => Some(decode_child_reference::<H, _>(c, encoded, nodes, depth)?),
+					None => None,
+				});
+			}
+			<NodeCodec<H> as NodeCodecT<H>>::branch_node(
+				references.into_iter(),
+				value.map(DBValue::from_slice),
+			)
+		},
+		Node::Empty | Node::Leaf(..) => data.clone(),
+	};
+
+	let hash = H::hash(&node);
+	nodes.push(node);
+	Ok(hash)
+}
+
+/// Decode the given child reference, decoding the next node from `encoded` if it was omitted.
+///
+/// `depth` is the depth of the parent node.
+fn decode_child_reference<H: Hasher, I: Iterator<Item = Vec<u8>>>(
+	child: &[u8],
+	encoded: &mut I,
+	nodes: &mut Vec<Vec<u8>>,
+	depth: usize,
+) -> Result<ChildReference<H::Out>, ()> {
+	if child.is_empty() {
+		decode_compact::<H, _>(encoded, nodes, depth + 1).map(ChildReference::Hash)
+	} else {
+		Ok(child_reference::<H>(child))
+	}
+}
+
+/// The reference that replaces the reference to an omitted child.
+fn empty_reference<H: Hasher>() -> ChildReference<H::Out> {
+	ChildReference::Inline(Default::default(), 0)
+}
+
+/// Convert the encoded child reference of a decoded node into a `ChildReference`.
+fn child_reference<H: Hasher>(child: &[u8]) -> ChildReference<H::Out> {
+	let mut data = H::Out::default();
+	if child.len() == H::LENGTH {
+		data.as_mut().copy_from_slice(child);
+		ChildReference::Hash(data)
+	} else {
+		data.as_mut()[..child.len()].copy_from_slice(child);
+		ChildReference::Inline(data, child.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitives::Blake2Hasher;
+	use trie_db::NibbleSlice;
+
+	type Codec = NodeCodec<Blake2Hasher>;
+
+	/// Returns the compact encoding of a path of `depth` extension nodes that ends in a leaf.
+	fn compact_path(depth: usize) -> WitnessData {
+		let extension = <Codec as NodeCodecT<Blake2Hasher>>::ext_node(
+			&NibbleSlice::new(&[0x12]).encoded(false),
+			empty_reference::<Blake2Hasher>(),
+		);
+		let leaf = <Codec as NodeCodecT<Blake2Hasher>>::leaf_node(
+			&NibbleSlice::new(&[0x34]).encoded(true),
+			&[1, 2, 3],
+		);
+
+		let mut encoded = vec![extension; depth];
+		encoded.push(leaf);
+		WitnessData::Compact(encoded)
+	}
+
+	#[test]
+	fn decode_compact_accepts_max_depth() {
+		let nodes = compact_path(MAX_COMPACT_DEPTH).into_nodes::<Blake2Hasher>().unwrap();
+		assert_eq!(nodes.len(), MAX_COMPACT_DEPTH + 1);
+	}
+
+	#[test]
+	fn decode_compact_rejects_too_deep_nodes() {
+		assert!(compact_path(MAX_COMPACT_DEPTH + 1).into_nodes::<Blake2Hasher>().is_err());
+	}
+}
@@ -0,0 +1,101 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Benchmarks of the wasm `validate_block` of the test runtime.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use cumulus_test_client::{
+	TestClientBuilder, DefaultTestClientBuilderExt, TestClientBuilderExt, AccountKeyring, Client,
+	build_parachain_block, validation_params, call_validate_block, sign_call, transfer,
+	runtime::{Block, Call, Hash, TestModuleCall, WASM_BINARY},
+};
+use cumulus_runtime::{ValidationData, ValidationResult};
+use runtime_primitives::{generic::BlockId, traits::Block as BlockT};
+use parachain::ValidationParams;
+
+use std::sync::Arc;
+
+/// The `ValidationData` of the benchmarked blocks.
+fn validation_data() -> ValidationData {
+	ValidationData { relay_parent_number: 1, relay_storage_root: Default::default() }
+}
+
+/// Build a block with the extrinsics that are created by `extrinsics` on top of genesis and
+/// return the parameters to validate it.
+fn validation_params_for(
+	extrinsics: impl FnOnce(&Client) -> Vec<<Block as BlockT>::Extrinsic>,
+) -> ValidationParams {
+	let client = Arc::new(TestClientBuilder::new().build());
+	let parent = client.header(&BlockId::Number(0))
+		.expect("Reads the genesis header")
+		.expect("The genesis header exists");
+
+	let extrinsics = extrinsics(&client);
+	let (_, block_data) = build_parachain_block(client, &validation_data(), extrinsics);
+	validation_params(&parent, block_data)
+}
+
+/// Register a benchmark that validates the block that is built from `extrinsics`.
+fn bench_validate_block(
+	c: &mut Criterion,
+	name: &str,
+	extrinsics: impl FnOnce(&Client) -> Vec<<Block as BlockT>::Extrinsic>,
+) {
+	let params = validation_params_for(extrinsics);
+	let (block_data, parent_head) = (params.block_data, params.parent_head);
+
+	c.bench_function(name, move |b| b.iter(|| {
+		let params = ValidationParams {
+			block_data: block_data.clone(),
+			parent_head: parent_head.clone(),
+			ingress: Vec::new(),
+		};
+		call_validate_block::<ValidationResult<Hash>, _>(
+			&WASM_BINARY,
+			params,
+			Some(validation_data()),
+		).expect("Validates the block")
+	}));
+}
+
+fn empty_block(c: &mut Criterion) {
+	bench_validate_block(c, "validate_block empty", |_| Vec::new());
+}
+
+fn transfers(c: &mut Criterion) {
+	// Alice is endowed with 1000, enough for 999 transfers of 1 above the existential deposit.
+	bench_validate_block(c, "validate_block 999 transfers", |client| {
+		(0..999)
+			.map(|nonce| transfer(client, AccountKeyring::Alice, AccountKeyring::Bob, 1, nonce))
+			.collect()
+	});
+}
+
+fn big_block_data(c: &mut Criterion) {
+	// Writes 1 MiB of storage, which is part of the extrinsics and of the storage root.
+	bench_validate_block(c, "validate_block 1 MiB storage", |client| {
+		(0..16)
+			.map(|i| {
+				let items = vec![(vec![i as u8], vec![0xab; 64 * 1024])];
+				let call = Call::TestModule(TestModuleCall::set_storage(items));
+				sign_call(client, AccountKeyring::Alice, i, call)
+			})
+			.collect()
+	});
+}
+
+criterion_group!(benches, empty_block, transfers, big_block_data);
+criterion_main!(benches);
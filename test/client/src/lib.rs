@@ -18,9 +18,17 @@
 
 pub use test_client::*;
 pub use runtime;
-use runtime::{Block, genesismap::{GenesisConfig, additional_storage_with_genesis}};
-use runtime_primitives::traits::{Hash as HashT, Header as HeaderT, Block as BlockT};
-use primitives::storage::well_known_keys;
+use runtime::{
+	Block, GenesisConfig, GenesisConfigBuilder, BuildStorage, Call, BalancesCall, Balance, Index,
+	UncheckedExtrinsic,
+};
+use runtime_primitives::generic::Era;
+use primitives::{storage::well_known_keys, ChangesTrieConfiguration, blake2_256};
+use codec::{Encode, Compact};
+
+mod validate_block;
+
+pub use validate_block::{build_parachain_block, validation_params, call_validate_block};
 
 mod local_executor {
 	use test_client::executor::native_executor_instance;
@@ -58,16 +66,18 @@ pub struct GenesisParameters {
 
 impl test_client::GenesisInit for GenesisParameters {
 	fn genesis_storage(&self) -> (StorageOverlay, ChildrenStorageOverlay) {
-		let mut storage = genesis_config(self.support_changes_trie).genesis_map();
-		storage.insert(well_known_keys::CODE.to_vec(), runtime::WASM_BINARY.to_vec());
+		let (mut storage, children_storage) = genesis_config()
+			.build_storage()
+			.expect("Builds the genesis storage");
 
-		let state_root = <<<Block as BlockT>::Header as HeaderT>::Hashing as HashT>::trie_root(
-			storage.clone().into_iter()
-		);
-		let block: runtime::Block = client::genesis::construct_genesis_block(state_root);
-		storage.extend(additional_storage_with_genesis(&block));
+		// The system module is not part of the `GenesisConfig`, see `GenesisConfigBuilder`.
+		storage.insert(well_known_keys::CODE.to_vec(), runtime::WASM_BINARY.to_vec());
+		if self.support_changes_trie {
+			let config = ChangesTrieConfiguration { digest_interval: 4, digest_levels: 2 };
+			storage.insert(well_known_keys::CHANGES_TRIE_CONFIG.to_vec(), config.encode());
+		}
 
-		(storage, Default::default())
+		(storage, children_storage)
 	}
 }
 
@@ -108,16 +118,51 @@ impl DefaultTestClientBuilderExt for TestClientBuilder {
 	}
 }
 
-fn genesis_config(support_changes_trie: bool) -> GenesisConfig {
-	GenesisConfig::new(support_changes_trie, vec![
-		AuthorityKeyring::Alice.into(),
-		AuthorityKeyring::Bob.into(),
-		AuthorityKeyring::Charlie.into(),
-	], vec![
-		AccountKeyring::Alice.into(),
-		AccountKeyring::Bob.into(),
-		AccountKeyring::Charlie.into(),
-	],
-		1000
-	)
+/// The id of the parachain of the test client.
+pub const PARACHAIN_ID: u32 = 100;
+
+fn genesis_config() -> GenesisConfig {
+	GenesisConfigBuilder::default()
+		.with_endowed_accounts(
+			vec![
+				AccountKeyring::Alice.into(),
+				AccountKeyring::Bob.into(),
+				AccountKeyring::Charlie.into(),
+			],
+			1000,
+		)
+		.with_sudo_key(AccountKeyring::Alice.into())
+		.with_parachain_id(PARACHAIN_ID.into())
+		.build()
+}
+
+/// Sign the given `call` as extrinsic of `signer` with the given `nonce`.
+///
+/// The extrinsic is immortal, so it can be put into any block of the `client`.
+pub fn sign_call(
+	client: &Client,
+	signer: AccountKeyring,
+	nonce: Index,
+	call: Call,
+) -> UncheckedExtrinsic {
+	let genesis_hash = client.info().chain.genesis_hash;
+	let payload = (Compact(nonce), call.clone(), Era::immortal(), genesis_hash);
+	let signature = payload.using_encoded(|payload| if payload.len() > 256 {
+		signer.sign(&blake2_256(payload)[..])
+	} else {
+		signer.sign(payload)
+	});
+
+	UncheckedExtrinsic::new_signed(nonce, call, signer.into(), signature, Era::immortal())
+}
+
+/// Create a signed extrinsic that transfers `amount` from `from` to `to`.
+pub fn transfer(
+	client: &Client,
+	from: AccountKeyring,
+	to: AccountKeyring,
+	amount: Balance,
+	nonce: Index,
+) -> UncheckedExtrinsic {
+	sign_call(client, from, nonce, Call::Balances(BalancesCall::transfer(to.into(), amount)))
 }
\ No newline at end of file
@@ -0,0 +1,110 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers to validate blocks of the test client with the wasm `validate_block`.
+
+use crate::Client;
+use runtime::{Block, Header, PolkadotInherent};
+use runtime_primitives::{generic::BlockId, traits::Block as BlockT};
+use test_client::executor::{WasmExecutor, error::{Result, Error}, wasmi::RuntimeValue::I32};
+use cumulus_runtime::{ParachainBlockData, ValidationData, ProvidePolkadotInherent};
+use cumulus_client_proof_recorder::ProofRecorder;
+use parachain::ValidationParams;
+use rio::TestExternalities;
+
+use codec::{Encode, Decode};
+
+use std::sync::Arc;
+
+/// Build a new block with the given `extrinsics` on top of the best block of `client`.
+///
+/// The block starts with the inherents that the validator injects when it validates the block,
+/// the `PolkadotInherent` of `validation_data` and the inherent without downward messages.
+/// Returns the block and the `ParachainBlockData` that is required to validate it, which does
+/// not contain these inherents. The block is not imported.
+pub fn build_parachain_block(
+	client: Arc<Client>,
+	validation_data: &ValidationData,
+	extrinsics: Vec<<Block as BlockT>::Extrinsic>,
+) -> (Block, ParachainBlockData<Block>) {
+	let parent = BlockId::Hash(client.info().chain.best_hash);
+	let validator_inherents = std::iter::once(PolkadotInherent::create_inherent(validation_data))
+		.chain(PolkadotInherent::create_downward_messages_inherent(&[]))
+		.collect::<Vec<_>>();
+	let validator_inherents_len = validator_inherents.len();
+
+	let recorded = ProofRecorder::new(client)
+		.build(&parent, Default::default(), |builder| {
+			validator_inherents.into_iter().chain(extrinsics).try_for_each(|e| builder.push(e))
+		})
+		.expect("Builds the block");
+
+	(recorded.block.clone(), recorded.into_parachain_block_data(validator_inherents_len))
+}
+
+/// Create the `ValidationParams` to validate `block_data` on top of `parent_head`.
+pub fn validation_params(
+	parent_head: &Header,
+	block_data: ParachainBlockData<Block>,
+) -> ValidationParams {
+	ValidationParams {
+		block_data: block_data.encode_versioned(),
+		parent_head: parent_head.encode(),
+		ingress: Vec::new(),
+	}
+}
+
+/// Call the `validate_block` function of the wasm runtime `code`, as a validator does.
+///
+/// The encoded `validation_data` is appended to the `params`, if given. Returns the decoded
+/// result, usually a `ValidationResult`.
+pub fn call_validate_block<R: Decode, V: Encode>(
+	code: &[u8],
+	params: ValidationParams,
+	validation_data: Option<V>,
+) -> Result<R> {
+	let mut ext = TestExternalities::default();
+	WasmExecutor::new().call_with_custom_signature(
+		&mut ext,
+		1024,
+		code,
+		"validate_block",
+		|alloc| {
+			let mut params = params.encode();
+			if let Some(validation_data) = validation_data {
+				validation_data.encode_to(&mut params);
+			}
+			let params_offset = alloc(&params)?;
+
+			Ok(vec![I32(params_offset as i32), I32(params.len() as i32)])
+		},
+		|res, memory| {
+			match res {
+				Some(I32(ptr)) => {
+					// `ptr` points to the length that is appended to the encoded result.
+					let len_data = memory.get(ptr as u32, 4)
+						.map_err(|_| Error::InvalidMemoryReference)?;
+					let len = u32::decode(&mut &len_data[..]).ok_or(Error::InvalidMemoryReference)?;
+					let data = memory.get(ptr as u32 - len, len as usize)
+						.map_err(|_| Error::InvalidMemoryReference)?;
+
+					Ok(R::decode(&mut &data[..]))
+				},
+				_ => Ok(None),
+			}
+		}
+	)
+}
@@ -0,0 +1,753 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tests of the wasm and the native `validate_block` with blocks of the test runtime.
+
+use cumulus_runtime::{
+	ParachainBlockData, WitnessData, ValidationResult, ValidationData, ValidationError,
+	ProvidePolkadotInherent, DownwardMessage, OutboundHrmpMessage,
+	validate_block::{self, DEFAULT_MAX_POV_SIZE},
+};
+
+use keyring::AccountKeyring;
+use runtime_primitives::{
+	generic::{BlockId, DigestItem},
+	traits::{Block as BlockT, Header as HeaderT},
+};
+use executor::error::Result;
+use cumulus_test_client::{
+	TestClientBuilder, TestClientBuilderExt, DefaultTestClientBuilderExt, Client, ClientExt,
+	transfer, sign_call,
+	runtime::{
+		Block, Hash, WASM_BINARY, Header, Extrinsic, PolkadotInherent, Call, TestModuleCall,
+		SudoCall, ParachainSystemCall, XcmpQueueCall, ChannelLimits, Executive as BlockExecutor,
+	},
+};
+use consensus_common::{SelectChain, BlockOrigin};
+use parachain::{ValidationParams, IncomingMessage};
+
+use codec::{Encode, Decode};
+use primitives::{Blake2Hasher, twox_128, storage::{StorageKey, well_known_keys}};
+use hash_db::Hasher;
+
+/// Call the wasm `validate_block` of the test runtime.
+fn call_validate_block(
+	params: ValidationParams,
+	validation_data: ValidationData,
+) -> Result<ValidationResult<Hash>> {
+	cumulus_test_client::call_validate_block(&WASM_BINARY, params, Some(validation_data))
+}
+
+fn call_validate_block_natively(
+	params: ValidationParams,
+	max_pov_size: usize,
+) -> std::result::Result<ValidationResult<Hash>, ValidationError> {
+	validate_block::validate_block::<Block, BlockExecutor, PolkadotInherent>(
+		params,
+		Some(validation_data()),
+		max_pov_size,
+	)
+}
+
+/// The default `ValidationData` of the blocks that are built by the `CandidateBuilder`, which
+/// the validator injects as `PolkadotInherent`.
+fn validation_data() -> ValidationData {
+	ValidationData {
+		relay_parent_number: 1,
+		relay_storage_root: Hash::from_low_u64_be(2),
+	}
+}
+
+fn create_extrinsics(client: &Client) -> Vec<Extrinsic> {
+	use AccountKeyring::{Alice, Bob, Charlie};
+
+	vec![
+		transfer(client, Alice, Bob, 69, 0),
+		transfer(client, Alice, Charlie, 100, 1),
+		transfer(client, Bob, Charlie, 100, 0),
+		transfer(client, Charlie, Alice, 500, 0),
+	]
+}
+
+/// Builds the blocks of a candidate on top of the genesis block of a new test client.
+///
+/// Every block starts with the inherents that the validator injects again when it validates
+/// the candidate. Every block is imported, so the next block is built on top of it.
+struct CandidateBuilder {
+	client: Client,
+	parent_head: Header,
+	downward_messages: Vec<IncomingMessage>,
+	blocks: Vec<(Block, WitnessData)>,
+}
+
+impl CandidateBuilder {
+	fn new() -> Self {
+		let (client, longest_chain) = TestClientBuilder::new().build_with_longest_chain();
+		let parent_head = longest_chain.best_chain().expect("Best block exists");
+
+		Self {
+			client,
+			parent_head,
+			downward_messages: Vec::new(),
+			blocks: Vec::new(),
+		}
+	}
+
+	/// Receive the given downward messages in the first block of the candidate.
+	fn with_downward_messages(mut self, messages: Vec<IncomingMessage>) -> Self {
+		assert!(self.blocks.is_empty(), "The messages are received in the first block");
+
+		self.downward_messages = messages;
+		self
+	}
+
+	/// Build a block with the extrinsics that are created by `extrinsics`, which becomes the
+	/// parent of the candidate.
+	///
+	/// Prepares the state that the blocks of the candidate operate on.
+	fn build_parent_block(mut self, extrinsics: impl FnOnce(&Client) -> Vec<Extrinsic>) -> Self {
+		assert!(self.blocks.is_empty(), "The parent is built before the blocks of the candidate");
+
+		let (block, _) = self.build_and_import(Some(&[]), extrinsics);
+		self.parent_head = block.deconstruct().0;
+		self
+	}
+
+	/// Build the next block with the extrinsics that are created by `extrinsics`.
+	fn build_block(mut self, extrinsics: impl FnOnce(&Client) -> Vec<Extrinsic>) -> Self {
+		let downward_messages = if self.blocks.is_empty() {
+			Some(&self.downward_messages[..])
+		} else {
+			None
+		};
+		let block = self.build_and_import(downward_messages, extrinsics);
+		self.blocks.push(block);
+		self
+	}
+
+	/// Build and import a block that starts with the inherents of the validator.
+	///
+	/// The validator only injects the downward messages into the first block, which is the
+	/// block that gets `downward_messages`.
+	fn build_and_import(
+		&self,
+		downward_messages: Option<&[IncomingMessage]>,
+		extrinsics: impl FnOnce(&Client) -> Vec<Extrinsic>,
+	) -> (Block, WitnessData) {
+		let mut inherents = vec![PolkadotInherent::create_inherent(&validation_data())];
+		if let Some(messages) = downward_messages {
+			let messages = messages.iter()
+				.map(|m| DownwardMessage { source: m.source.into(), data: m.data.clone() })
+				.collect::<Vec<_>>();
+			inherents.extend(PolkadotInherent::create_downward_messages_inherent(&messages));
+		}
+		let extrinsics = extrinsics(&self.client);
+
+		let block_id = BlockId::Hash(self.client.info().chain.best_hash);
+		let mut builder = self.client.new_block_at_with_proof_recording(
+			&block_id,
+			Default::default()
+		).expect("Initializes new block");
+
+		inherents.into_iter()
+			.chain(extrinsics)
+			.for_each(|e| builder.push(e).expect("Pushes an extrinsic"));
+
+		let (block, proof) = builder
+			.bake_and_extract_proof()
+			.expect("Finalizes block");
+
+		self.client.import(BlockOrigin::Own, block.clone()).expect("Imports the block");
+		(block, proof.expect("We enabled proof recording before.").into())
+	}
+
+	/// Build the candidate of all blocks, which share one witness that covers all of them.
+	fn build(self) -> Candidate {
+		let mut nodes = Vec::new();
+		let mut blocks = Vec::new();
+		for (index, (block, witness_data)) in self.blocks.into_iter().enumerate() {
+			match witness_data {
+				WitnessData::Full(block_nodes) => nodes.extend(block_nodes),
+				WitnessData::Compact(_) => panic!("Proofs are recorded as full witness data"),
+			}
+
+			// The validator injects its inherents at the start of the block again, the
+			// `PolkadotInherent` and, in the first block, the downward messages.
+			let validator_inherents = if index == 0 { 2 } else { 1 };
+			let (header, mut extrinsics) = block.deconstruct();
+			blocks.push((header, extrinsics.split_off(validator_inherents)));
+		}
+		nodes.sort();
+		nodes.dedup();
+
+		Candidate {
+			client: self.client,
+			witness_data_storage_root: *self.parent_head.state_root(),
+			parent_head: self.parent_head,
+			downward_messages: self.downward_messages,
+			blocks,
+			witness_data: nodes.into(),
+		}
+	}
+}
+
+/// Build a candidate of one block with the extrinsics that are created by `extrinsics`.
+fn build_candidate(extrinsics: impl FnOnce(&Client) -> Vec<Extrinsic>) -> Candidate {
+	CandidateBuilder::new().build_block(extrinsics).build()
+}
+
+/// A candidate that was built by the `CandidateBuilder`.
+///
+/// The tests can alter its parts before they validate it.
+struct Candidate {
+	/// The client that imported the blocks of the candidate.
+	client: Client,
+	parent_head: Header,
+	/// The downward messages that the validator injects into the first block.
+	downward_messages: Vec<IncomingMessage>,
+	blocks: Vec<(Header, Vec<Extrinsic>)>,
+	witness_data: WitnessData,
+	witness_data_storage_root: Hash,
+}
+
+impl Candidate {
+	/// Returns the header of the last block.
+	fn header(&self) -> &Header {
+		&self.blocks.last().expect("A candidate has at least one block").0
+	}
+
+	/// Returns the header of the last block mutably.
+	fn header_mut(&mut self) -> &mut Header {
+		&mut self.blocks.last_mut().expect("A candidate has at least one block").0
+	}
+
+	fn block_data(&self) -> ParachainBlockData<Block> {
+		ParachainBlockData::with_blocks(
+			self.blocks.clone(),
+			self.witness_data.clone(),
+			self.witness_data_storage_root,
+		)
+	}
+
+	fn params(&self) -> ValidationParams {
+		ValidationParams {
+			block_data: self.block_data().encode_versioned(),
+			parent_head: self.parent_head.encode(),
+			ingress: self.downward_messages.clone(),
+		}
+	}
+
+	/// Returns the value of `key` in the state of the last block, as the client computed it.
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		let at = BlockId::Hash(self.header().hash());
+		self.client.storage(&at, &StorageKey(key.to_vec()))
+			.expect("Reads the storage of the last block")
+			.map(|data| data.0)
+	}
+
+	/// Returns the value of `key` in the child trie `storage_key` in the state of the last
+	/// block, as the client computed it.
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		let at = BlockId::Hash(self.header().hash());
+		self.client.child_storage(&at, &StorageKey(storage_key.to_vec()), &StorageKey(key.to_vec()))
+			.expect("Reads the child storage of the last block")
+			.map(|data| data.0)
+	}
+
+	/// Returns the witness nodes of the candidate.
+	fn nodes(&self) -> Vec<Vec<u8>> {
+		match self.witness_data.clone() {
+			WitnessData::Full(nodes) => nodes,
+			WitnessData::Compact(_) => panic!("Proofs are recorded as full witness data"),
+		}
+	}
+}
+
+/// Validate the candidate with the wasm and the native `validate_block`.
+///
+/// Both must succeed with the same result, which is returned.
+fn validate_candidate(candidate: &Candidate) -> ValidationResult<Hash> {
+	let res = call_validate_block(candidate.params(), validation_data())
+		.expect("Calls `validate_block`");
+	let native_res = validate_block::validate_block::<Block, BlockExecutor, PolkadotInherent>(
+		candidate.params(),
+		Some(validation_data()),
+		DEFAULT_MAX_POV_SIZE,
+	).expect("Validates block");
+	assert_eq!(res, native_res);
+	res
+}
+
+/// Sign the given call of the `TestModule` as extrinsic of Alice with the given `nonce`.
+fn test_module_call(client: &Client, nonce: u64, call: TestModuleCall) -> Extrinsic {
+	sign_call(client, AccountKeyring::Alice, nonce, Call::TestModule(call))
+}
+
+/// Returns the `ValidationResult` of a candidate whose last block has the given `header`, and
+/// whose blocks do not process or send messages and do not upgrade the code.
+fn expected_validation_result(header: &Header) -> ValidationResult<Hash> {
+	ValidationResult {
+		head_data: header.encode(),
+		storage_root: *header.state_root(),
+		processed_messages: 0,
+		new_validation_code: None,
+		upward_messages: Vec::new(),
+		horizontal_messages: Vec::new(),
+		// All inbound messages, none, are processed up to the relay parent.
+		hrmp_watermark: Some(validation_data().relay_parent_number),
+	}
+}
+
+fn check_validation_result(header: &Header, res: ValidationResult<Hash>) {
+	assert_eq!(expected_validation_result(header), res);
+}
+
+#[test]
+fn validate_block_with_no_extrinsics() {
+	let candidate = build_candidate(|_| Vec::new());
+
+	let res = call_validate_block(candidate.params(), validation_data())
+		.expect("Calls `validate_block`");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_with_extrinsics() {
+	let candidate = build_candidate(create_extrinsics);
+
+	let res = call_validate_block(candidate.params(), validation_data())
+		.expect("Calls `validate_block`");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+#[should_panic]
+fn validate_block_invalid_parent_hash() {
+	let mut candidate = build_candidate(|_| Vec::new());
+	candidate.header_mut().set_parent_hash(Hash::from_low_u64_be(1));
+
+	call_validate_block(candidate.params(), validation_data()).expect("Calls `validate_block`");
+}
+
+#[test]
+#[should_panic]
+fn validate_block_invalid_extrinsics_root() {
+	let mut candidate = build_candidate(create_extrinsics);
+	candidate.blocks[0].1.pop();
+
+	call_validate_block(candidate.params(), validation_data()).expect("Calls `validate_block`");
+}
+
+#[test]
+#[should_panic]
+fn validate_block_invalid_state_root() {
+	let mut candidate = build_candidate(create_extrinsics);
+	candidate.header_mut().set_state_root(Hash::from_low_u64_be(1));
+
+	call_validate_block(candidate.params(), validation_data()).expect("Calls `validate_block`");
+}
+
+#[test]
+#[should_panic]
+fn validate_block_with_other_polkadot_inherent() {
+	let candidate = build_candidate(create_extrinsics);
+
+	// The validator injects a `PolkadotInherent` with other data than the collator.
+	let validation_data = ValidationData {
+		relay_parent_number: 10,
+		..validation_data()
+	};
+	call_validate_block(candidate.params(), validation_data).expect("Calls `validate_block`");
+}
+
+#[test]
+fn validate_block_natively() {
+	let candidate = build_candidate(create_extrinsics);
+
+	let res = call_validate_block_natively(candidate.params(), DEFAULT_MAX_POV_SIZE)
+		.expect("Validates block");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_natively_with_sealed_block() {
+	let mut candidate = build_candidate(create_extrinsics);
+	candidate.header_mut().digest_mut().push(DigestItem::Seal(*b"test", vec![1, 2, 3]));
+
+	let res = validate_block::validate_block::<
+		Block,
+		validate_block::BlockExecutor<Block, BlockExecutor>,
+		PolkadotInherent,
+	>(candidate.params(), Some(validation_data()), DEFAULT_MAX_POV_SIZE)
+		.expect("Validates block");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_natively_reusing_preflight() {
+	let candidate = build_candidate(create_extrinsics);
+
+	let preflight = validate_block::preflight::<Block>(&candidate.params(), DEFAULT_MAX_POV_SIZE)
+		.expect("Preflight succeeds");
+	let execute = |preflight| {
+		validate_block::execute::<Block, BlockExecutor, PolkadotInherent>(
+			preflight,
+			Some(validation_data()),
+			Vec::new(),
+		).expect("Validates block")
+	};
+
+	let (first, first_stats) = execute(preflight.clone());
+	let (second, second_stats) = execute(preflight);
+	assert_eq!(first, second);
+	assert_eq!(first_stats, second_stats);
+	check_validation_result(candidate.header(), first);
+}
+
+#[test]
+fn validate_block_natively_invalid_parent_hash() {
+	let mut candidate = build_candidate(|_| Vec::new());
+	candidate.header_mut().set_parent_hash(Hash::from_low_u64_be(1));
+
+	assert_eq!(
+		ValidationError::InvalidParentHash,
+		call_validate_block_natively(candidate.params(), DEFAULT_MAX_POV_SIZE)
+			.unwrap_err(),
+	);
+}
+
+#[test]
+fn validate_block_with_compact_witness_data() {
+	let mut candidate = build_candidate(create_extrinsics);
+
+	let compact = WitnessData::compact::<Blake2Hasher>(
+		candidate.nodes(),
+		&candidate.witness_data_storage_root,
+	);
+	assert!(compact.encode().len() < candidate.witness_data.encode().len());
+	candidate.witness_data = compact;
+
+	let res = call_validate_block(candidate.params(), validation_data())
+		.expect("Calls `validate_block`");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_with_multiple_blocks() {
+	let candidate = CandidateBuilder::new()
+		.build_block(create_extrinsics)
+		.build_block(|client| {
+			vec![transfer(client, AccountKeyring::Alice, AccountKeyring::Bob, 10, 2)]
+		})
+		.build();
+	assert_eq!(2, candidate.blocks.len());
+
+	let res = call_validate_block(candidate.params(), validation_data())
+		.expect("Calls `validate_block`");
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_with_cleared_prefix() {
+	let item = |key: &[u8]| (key.to_vec(), vec![1; 64]);
+	let candidate = CandidateBuilder::new()
+		.build_parent_block(|client| vec![
+			test_module_call(client, 0, TestModuleCall::set_storage(vec![
+				item(b"prefix_a"),
+				item(b"prefix_b"),
+				item(b"other"),
+			])),
+		])
+		.build_block(|client| vec![
+			// Clears keys of the witness data and of the overlay.
+			test_module_call(client, 1, TestModuleCall::set_storage(vec![item(b"prefix_c")])),
+			test_module_call(client, 2, TestModuleCall::clear_prefix(b"prefix".to_vec())),
+		])
+		.build();
+
+	assert!(candidate.storage(b"prefix_a").is_none());
+	assert!(candidate.storage(b"prefix_b").is_none());
+	assert!(candidate.storage(b"prefix_c").is_none());
+	assert_eq!(Some(vec![1; 64]), candidate.storage(b"other"));
+
+	let res = validate_candidate(&candidate);
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_with_child_tries() {
+	let changed = b":child_storage:default:changed".to_vec();
+	let killed = b":child_storage:default:killed".to_vec();
+	let created = b":child_storage:default:created".to_vec();
+	let item = |key: &[u8]| (key.to_vec(), vec![2; 64]);
+
+	let candidate = CandidateBuilder::new()
+		.build_parent_block(|client| vec![
+			test_module_call(
+				client,
+				0,
+				TestModuleCall::set_child_storage(changed.clone(), vec![item(b"a"), item(b"b")]),
+			),
+			test_module_call(
+				client,
+				1,
+				TestModuleCall::set_child_storage(killed.clone(), vec![item(b"a")]),
+			),
+		])
+		.build_block(|client| vec![
+			test_module_call(
+				client,
+				2,
+				TestModuleCall::set_child_storage(changed.clone(), vec![(b"a".to_vec(), vec![3])]),
+			),
+			test_module_call(client, 3, TestModuleCall::kill_child_storage(killed.clone())),
+			test_module_call(
+				client,
+				4,
+				TestModuleCall::set_child_storage(created.clone(), vec![item(b"a")]),
+			),
+		])
+		.build();
+
+	assert_eq!(Some(vec![3]), candidate.child_storage(&changed, b"a"));
+	assert_eq!(Some(vec![2; 64]), candidate.child_storage(&changed, b"b"));
+	assert!(candidate.child_storage(&killed, b"a").is_none());
+	assert_eq!(Some(vec![2; 64]), candidate.child_storage(&created, b"a"));
+
+	let res = validate_candidate(&candidate);
+	check_validation_result(candidate.header(), res);
+}
+
+#[test]
+fn validate_block_with_code_upgrade() {
+	let code = b"new validation code".to_vec();
+	let authorize_upgrade = ParachainSystemCall::authorize_upgrade(Blake2Hasher::hash(&code));
+
+	// The upgrade is scheduled without delay, so the candidate block on the same relay parent
+	// applies it.
+	let candidate = CandidateBuilder::new()
+		.build_parent_block(|client| vec![
+			sign_call(
+				client,
+				AccountKeyring::Alice,
+				0,
+				Call::Sudo(SudoCall::sudo(Box::new(Call::ParachainSystem(authorize_upgrade)))),
+			),
+			sign_call(
+				client,
+				AccountKeyring::Bob,
+				0,
+				Call::ParachainSystem(ParachainSystemCall::enact_authorized_upgrade(code.clone())),
+			),
+		])
+		.build_block(|_| Vec::new())
+		.build();
+
+	assert_eq!(Some(code.clone()), candidate.storage(well_known_keys::CODE));
+
+	let res = validate_candidate(&candidate);
+	assert_eq!(
+		ValidationResult {
+			new_validation_code: Some(code),
+			..expected_validation_result(candidate.header())
+		},
+		res,
+	);
+}
+
+#[test]
+fn validate_block_with_upward_and_horizontal_messages() {
+	let recipient = 300;
+	let limits = ChannelLimits { max_capacity: 10, max_message_size: 1024 };
+	let open_channel = XcmpQueueCall::set_outbound_channel(recipient.into(), Some(limits));
+
+	let candidate = CandidateBuilder::new()
+		.build_block(|client| vec![
+			sign_call(
+				client,
+				AccountKeyring::Alice,
+				0,
+				Call::Sudo(SudoCall::sudo(Box::new(Call::XcmpQueue(open_channel)))),
+			),
+			test_module_call(
+				client,
+				1,
+				TestModuleCall::send_upward_messages(vec![b"up 1".to_vec(), b"up 2".to_vec()]),
+			),
+			test_module_call(
+				client,
+				2,
+				TestModuleCall::send_xcmp_message(recipient.into(), b"a".to_vec()),
+			),
+			test_module_call(
+				client,
+				3,
+				TestModuleCall::send_xcmp_message(recipient.into(), b"b".to_vec()),
+			),
+		])
+		.build_block(|client| vec![
+			test_module_call(
+				client,
+				4,
+				TestModuleCall::send_upward_messages(vec![b"up 3".to_vec()]),
+			),
+		])
+		.build();
+
+	// The messages of both blocks are sent, both messages to `recipient` share one page.
+	let res = validate_candidate(&candidate);
+	assert_eq!(
+		ValidationResult {
+			upward_messages: vec![b"up 1".to_vec(), b"up 2".to_vec(), b"up 3".to_vec()],
+			horizontal_messages: vec![OutboundHrmpMessage {
+				recipient: recipient.into(),
+				data: vec![b"a".to_vec(), b"b".to_vec()].encode(),
+			}],
+			..expected_validation_result(candidate.header())
+		},
+		res,
+	);
+}
+
+#[test]
+fn validate_block_with_downward_messages() {
+	let pages = vec![
+		IncomingMessage { source: 300.into(), data: vec![b"a".to_vec(), b"bc".to_vec()].encode() },
+		IncomingMessage { source: 400.into(), data: vec![b"d".to_vec()].encode() },
+	];
+
+	let candidate = CandidateBuilder::new()
+		.with_downward_messages(pages)
+		.build_block(|_| Vec::new())
+		.build();
+
+	// All messages are handled in the block that receives them.
+	let received = candidate.storage(&twox_128(b"TestModule ReceivedXcmpMessages"))
+		.and_then(|messages| Vec::<(u32, Vec<u8>)>::decode(&mut &messages[..]));
+	assert_eq!(
+		Some(vec![(300, b"a".to_vec()), (300, b"bc".to_vec()), (400, b"d".to_vec())]),
+		received,
+	);
+
+	let res = validate_candidate(&candidate);
+	assert_eq!(
+		ValidationResult {
+			processed_messages: 2,
+			..expected_validation_result(candidate.header())
+		},
+		res,
+	);
+}
+
+#[test]
+fn validate_block_natively_without_blocks() {
+	let mut candidate = build_candidate(|_| Vec::new());
+	candidate.blocks.clear();
+	candidate.witness_data = WitnessData::Full(Vec::new());
+
+	assert_eq!(
+		ValidationError::NoBlocks,
+		call_validate_block_natively(candidate.params(), DEFAULT_MAX_POV_SIZE)
+			.unwrap_err(),
+	);
+}
+
+#[test]
+fn validate_block_natively_without_required_validation_data() {
+	let candidate = build_candidate(create_extrinsics);
+
+	assert_eq!(
+		ValidationError::MissingValidationData,
+		validate_block::validate_block::<Block, BlockExecutor, PolkadotInherent>(
+			candidate.params(),
+			None,
+			DEFAULT_MAX_POV_SIZE,
+		).unwrap_err(),
+	);
+}
+
+#[test]
+fn validate_block_natively_with_too_large_pov() {
+	let candidate = build_candidate(create_extrinsics);
+	let params = candidate.params();
+	let size = params.block_data.len();
+
+	assert_eq!(
+		ValidationError::PoVTooLarge { max: size - 1, size },
+		call_validate_block_natively(params, size - 1).unwrap_err(),
+	);
+}
+
+#[test]
+fn validate_block_natively_reports_unused_witness_data() {
+	let mut candidate = build_candidate(create_extrinsics);
+
+	let validate = |candidate: &Candidate| {
+		validate_block::validate_block_with_stats::<Block, BlockExecutor, PolkadotInherent>(
+			candidate.params(),
+			Some(validation_data()),
+			DEFAULT_MAX_POV_SIZE,
+		).expect("Validates block")
+	};
+
+	let (res, stats) = validate(&candidate);
+	check_validation_result(candidate.header(), res);
+
+	// Add a leaf node that is not referenced by any trie and a duplicate node.
+	let mut nodes = candidate.nodes();
+	nodes.push(vec![1, 12, 1, 2, 3]);
+	nodes.push(nodes[0].clone());
+	candidate.witness_data = nodes.into();
+	let (_, stats_with_unused) = validate(&candidate);
+
+	assert_eq!(stats.nodes + 1, stats_with_unused.nodes);
+	assert_eq!(stats.duplicate_nodes + 1, stats_with_unused.duplicate_nodes);
+	assert_eq!(stats.read_size, stats_with_unused.read_size);
+	assert_eq!(stats.unused_size() + 5, stats_with_unused.unused_size());
+}
+
+#[test]
+fn validate_block_natively_with_invalid_witness_node() {
+	let mut candidate = build_candidate(create_extrinsics);
+
+	let invalid_node = vec![255];
+	let mut nodes = candidate.nodes();
+	nodes.push(invalid_node.clone());
+	candidate.witness_data = nodes.into();
+
+	assert_eq!(
+		ValidationError::InvalidWitnessNode(Blake2Hasher::hash(&invalid_node).as_bytes().to_vec()),
+		call_validate_block_natively(candidate.params(), DEFAULT_MAX_POV_SIZE)
+			.unwrap_err(),
+	);
+}
+
+#[test]
+fn skip_signature_verification_is_scoped() {
+	use validate_block::{
+		SignatureVerification, skip_signature_verification, with_signature_verification,
+	};
+
+	assert!(!skip_signature_verification());
+	with_signature_verification(SignatureVerification::Skip, || {
+		assert!(skip_signature_verification());
+		with_signature_verification(SignatureVerification::Full, || {
+			assert!(!skip_signature_verification());
+		});
+		assert!(skip_signature_verification());
+	});
+	assert!(!skip_signature_verification());
+}
@@ -0,0 +1,89 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Builds a relay chain state and a proof of it for testing the parachain runtime modules.
+//!
+//! The values are put under the keys of `cumulus_runtime::relay_chain_keys`, so the proof can
+//! be read with `RelayChainStateProof`.
+
+use cumulus_runtime::{relay_chain_keys, ParaId};
+use primitives::{Blake2Hasher, H256};
+use substrate_trie::{MemoryDB, TrieDBMut, TrieMut};
+use codec::Encode;
+
+/// Builds the relay chain state that is proven to the parachain with the id `para_id`.
+///
+/// Entries that are `None` are not put into the state.
+#[derive(Clone)]
+pub struct RelayStateSproofBuilder {
+	/// The id of the parachain.
+	pub para_id: ParaId,
+	/// The head of the parachain that is included in the relay chain.
+	pub included_para_head: Option<Vec<u8>>,
+	/// The BABE randomness of the epoch before the current epoch.
+	pub one_epoch_ago_randomness: Option<H256>,
+	/// The BABE randomness of the epoch two epochs before the current epoch.
+	pub two_epochs_ago_randomness: Option<H256>,
+	/// The current BABE slot.
+	pub current_slot: Option<u64>,
+	/// Additional raw key-value pairs.
+	pub additional_key_values: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Default for RelayStateSproofBuilder {
+	fn default() -> Self {
+		Self {
+			para_id: 200.into(),
+			included_para_head: None,
+			one_epoch_ago_randomness: None,
+			two_epochs_ago_randomness: None,
+			current_slot: None,
+			additional_key_values: Vec::new(),
+		}
+	}
+}
+
+impl RelayStateSproofBuilder {
+	/// Build the relay chain state and return its storage root and a proof of all entries.
+	pub fn into_state_root_and_proof(self) -> (H256, Vec<Vec<u8>>) {
+		let mut entries = self.additional_key_values;
+		if let Some(head) = self.included_para_head {
+			entries.push((relay_chain_keys::para_head(self.para_id), head));
+		}
+		if let Some(randomness) = self.one_epoch_ago_randomness {
+			entries.push((relay_chain_keys::one_epoch_ago_randomness(), randomness.encode()));
+		}
+		if let Some(randomness) = self.two_epochs_ago_randomness {
+			entries.push((relay_chain_keys::two_epochs_ago_randomness(), randomness.encode()));
+		}
+		if let Some(slot) = self.current_slot {
+			entries.push((relay_chain_keys::current_slot(), slot.encode()));
+		}
+
+		let mut db = MemoryDB::<Blake2Hasher>::default();
+		let mut root = H256::default();
+		{
+			let mut trie = TrieDBMut::<Blake2Hasher>::new(&mut db, &mut root);
+			for (key, value) in entries {
+				trie.insert(&key, &value).expect("Inserts into an in-memory trie; qed");
+			}
+		}
+
+		// The state only contains the proven entries, so all trie nodes are the proof.
+		let proof = db.drain().into_iter().map(|(_, (node, _))| node.to_vec()).collect();
+		(root, proof)
+	}
+}
@@ -15,13 +15,322 @@
 // along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
 
 //! A Cumulus test runtime.
+//!
+//! Contains the modules that a parachain runtime is usually built of, balances, sudo and the
+//! parachain system and xcmp queue modules, and the `TestModule`, which lets tests execute
+//! arbitrary storage operations and send messages.
 
 #![cfg_attr(not(feature = "std"), no_std)]
+// `construct_runtime!` does a lot of recursion and requires us to increase the limit to 256.
+#![recursion_limit="256"]
 
 // Make the WASM binary available.
 #[cfg(feature = "std")]
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
-pub use substrate_test_runtime::*;
+pub mod test_module;
 
-runtime::register_validate_block!(Block, system::BlockExecutor);
\ No newline at end of file
+use rstd::prelude::*;
+use primitives::{sr25519, OpaqueMetadata};
+use runtime_primitives::{
+	ApplyResult, generic, create_runtime_str,
+	transaction_validity::TransactionValidity,
+	traits::{BlakeTwo256, Block as BlockT, Verify, IdentityLookup},
+};
+use client::{
+	block_builder::api::{CheckInherentsResult, InherentData, self as block_builder_api},
+	runtime_api as client_api, impl_runtime_apis,
+};
+use version::RuntimeVersion;
+#[cfg(feature = "std")]
+use version::NativeVersion;
+use srml_support::{construct_runtime, parameter_types};
+use cumulus_runtime::{ValidationData, DownwardMessage, CollationInfo, ParaId};
+use cumulus_pallet_parachain_system::FixedVelocity;
+
+pub use balances::Call as BalancesCall;
+pub use sudo::Call as SudoCall;
+pub use cumulus_pallet_parachain_system::Call as ParachainSystemCall;
+pub use cumulus_pallet_xcmp_queue::{Call as XcmpQueueCall, ChannelLimits};
+pub use test_module::Call as TestModuleCall;
+#[cfg(feature = "std")]
+pub use runtime_primitives::BuildStorage;
+
+/// The type that is used for the signatures of the extrinsics.
+pub type Signature = sr25519::Signature;
+
+/// The id of an account, the public key of its signatures.
+pub type AccountId = <Signature as Verify>::Signer;
+
+/// The index of the extrinsics of an account.
+pub type Index = u64;
+
+/// The balance of an account.
+pub type Balance = u64;
+
+/// The number of a block.
+pub type BlockNumber = u64;
+
+/// The hash of a block.
+pub type Hash = primitives::H256;
+
+/// The header of a block.
+pub type Header = generic::Header<BlockNumber, BlakeTwo256>;
+
+/// The extrinsic type of the runtime.
+pub type UncheckedExtrinsic = generic::UncheckedMortalCompactExtrinsic<
+	AccountId, Index, Call, Signature
+>;
+
+/// The extrinsic type of the runtime.
+pub type Extrinsic = UncheckedExtrinsic;
+
+/// The block type of the runtime.
+pub type Block = generic::Block<Header, UncheckedExtrinsic>;
+
+/// Executes the blocks of the runtime.
+pub type Executive = executive::Executive<
+	Runtime, Block, system::ChainContext<Runtime>, Balances, Runtime, AllModules
+>;
+
+/// The version of the runtime.
+pub const VERSION: RuntimeVersion = RuntimeVersion {
+	spec_name: create_runtime_str!("cumulus-test-parachain"),
+	impl_name: create_runtime_str!("cumulus-test-parachain"),
+	authoring_version: 1,
+	spec_version: 1,
+	impl_version: 1,
+	apis: RUNTIME_API_VERSIONS,
+};
+
+/// The version information used to identify this runtime when compiled natively.
+#[cfg(feature = "std")]
+pub fn native_version() -> NativeVersion {
+	NativeVersion {
+		runtime_version: VERSION,
+		can_author_with: Default::default(),
+	}
+}
+
+parameter_types! {
+	pub const BlockHashCount: BlockNumber = 250;
+}
+
+impl system::Trait for Runtime {
+	type Origin = Origin;
+	type Index = Index;
+	type BlockNumber = BlockNumber;
+	type Hash = Hash;
+	type Hashing = BlakeTwo256;
+	type AccountId = AccountId;
+	type Lookup = IdentityLookup<AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: Balance = 1;
+	pub const TransferFee: Balance = 0;
+	pub const CreationFee: Balance = 0;
+	pub const TransactionBaseFee: Balance = 0;
+	pub const TransactionByteFee: Balance = 0;
+}
+
+impl balances::Trait for Runtime {
+	type Balance = Balance;
+	type OnFreeBalanceZero = ();
+	type OnNewAccount = ();
+	type Event = Event;
+	type TransactionPayment = ();
+	type DustRemoval = ();
+	type TransferPayment = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type TransferFee = TransferFee;
+	type CreationFee = CreationFee;
+	type TransactionBaseFee = TransactionBaseFee;
+	type TransactionByteFee = TransactionByteFee;
+}
+
+impl sudo::Trait for Runtime {
+	type Event = Event;
+	type Proposal = Call;
+}
+
+parameter_types! {
+	// The tests validate candidates of two blocks that are built on the same relay parent.
+	pub const BlocksPerRelayParent: u32 = 2;
+	pub const UnincludedSegmentCapacity: u32 = 2;
+}
+
+impl cumulus_pallet_parachain_system::Trait for Runtime {
+	type Event = Event;
+	type DownwardMessageHandler = XcmpQueue;
+	type ConsensusHook = FixedVelocity<BlocksPerRelayParent, UnincludedSegmentCapacity>;
+}
+
+impl cumulus_pallet_xcmp_queue::Trait for Runtime {
+	type XcmpMessageHandler = TestModule;
+}
+
+impl test_module::Trait for Runtime {}
+
+construct_runtime!(
+	pub enum Runtime where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic
+	{
+		System: system::{Module, Call, Storage, Config, Event},
+		Balances: balances::{Module, Call, Storage, Config<T>, Event<T>},
+		Sudo: sudo::{Module, Call, Storage, Config<T>, Event<T>},
+		ParachainSystem: cumulus_pallet_parachain_system::{
+			Module, Call, Storage, Config, Event<T>, Inherent,
+		},
+		XcmpQueue: cumulus_pallet_xcmp_queue::{Module, Call, Storage, Config},
+		TestModule: test_module::{Module, Call, Storage},
+	}
+);
+
+/// Creates the inherents that are injected by the validators as calls of the parachain system
+/// module.
+pub struct PolkadotInherent;
+
+impl cumulus_runtime::ProvidePolkadotInherent<Block> for PolkadotInherent {
+	const REQUIRED: bool = true;
+
+	fn create_inherent(data: &ValidationData) -> UncheckedExtrinsic {
+		UncheckedExtrinsic::new_unsigned(
+			Call::ParachainSystem(ParachainSystemCall::set_validation_data(data.clone())),
+		)
+	}
+
+	fn create_downward_messages_inherent(
+		messages: &[DownwardMessage],
+	) -> Option<UncheckedExtrinsic> {
+		let call = ParachainSystemCall::receive_downward_messages(messages.to_vec());
+		Some(UncheckedExtrinsic::new_unsigned(Call::ParachainSystem(call)))
+	}
+}
+
+cumulus_runtime::register_validate_block!(
+	Runtime = Runtime,
+	PolkadotInherent = PolkadotInherent,
+);
+
+impl_runtime_apis! {
+	impl client_api::Core<Block> for Runtime {
+		fn version() -> RuntimeVersion {
+			VERSION
+		}
+
+		fn execute_block(block: Block) {
+			Executive::execute_block(block)
+		}
+
+		fn initialize_block(header: &<Block as BlockT>::Header) {
+			Executive::initialize_block(header)
+		}
+	}
+
+	impl client_api::Metadata<Block> for Runtime {
+		fn metadata() -> OpaqueMetadata {
+			Runtime::metadata().into()
+		}
+	}
+
+	impl block_builder_api::BlockBuilder<Block> for Runtime {
+		fn apply_extrinsic(extrinsic: <Block as BlockT>::Extrinsic) -> ApplyResult {
+			Executive::apply_extrinsic(extrinsic)
+		}
+
+		fn finalize_block() -> <Block as BlockT>::Header {
+			Executive::finalize_block()
+		}
+
+		fn inherent_extrinsics(data: InherentData) -> Vec<<Block as BlockT>::Extrinsic> {
+			data.create_extrinsics()
+		}
+
+		fn check_inherents(block: Block, data: InherentData) -> CheckInherentsResult {
+			data.check_extrinsics(&block)
+		}
+
+		fn random_seed() -> <Block as BlockT>::Hash {
+			System::random_seed()
+		}
+	}
+
+	impl client_api::TaggedTransactionQueue<Block> for Runtime {
+		fn validate_transaction(tx: <Block as BlockT>::Extrinsic) -> TransactionValidity {
+			Executive::validate_transaction(tx)
+		}
+	}
+
+	impl cumulus_runtime::CollectCollationInfo<Block> for Runtime {
+		fn collect_collation_info(header: &<Block as BlockT>::Header) -> CollationInfo {
+			ParachainSystem::collect_collation_info(header)
+		}
+	}
+}
+
+/// Builds the `GenesisConfig` of the test runtime.
+///
+/// Every endowed account starts with the same balance, so tests can build blocks with as many
+/// transfers as they need by endowing enough accounts.
+///
+/// The system module is not configured, the test client puts the code and the changes trie
+/// configuration into the genesis storage.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct GenesisConfigBuilder {
+	endowed_accounts: Vec<AccountId>,
+	balance: Balance,
+	sudo_key: AccountId,
+	parachain_id: ParaId,
+}
+
+#[cfg(feature = "std")]
+impl GenesisConfigBuilder {
+	/// Endow each of the given accounts with `balance`.
+	pub fn with_endowed_accounts(mut self, accounts: Vec<AccountId>, balance: Balance) -> Self {
+		self.endowed_accounts = accounts;
+		self.balance = balance;
+		self
+	}
+
+	/// Use the given account as sudo key.
+	pub fn with_sudo_key(mut self, key: AccountId) -> Self {
+		self.sudo_key = key;
+		self
+	}
+
+	/// Use the given parachain id.
+	pub fn with_parachain_id(mut self, parachain_id: ParaId) -> Self {
+		self.parachain_id = parachain_id;
+		self
+	}
+
+	/// Build the `GenesisConfig`.
+	pub fn build(self) -> GenesisConfig {
+		GenesisConfig {
+			system: None,
+			balances: Some(BalancesConfig {
+				balances: self.endowed_accounts.iter().map(|a| (a.clone(), self.balance)).collect(),
+				vesting: Vec::new(),
+			}),
+			sudo: Some(SudoConfig {
+				key: self.sudo_key,
+			}),
+			cumulus_pallet_parachain_system: Some(ParachainSystemConfig {
+				parachain_id: self.parachain_id,
+				validation_upgrade_delay: 0,
+				max_upward_message_count: 10,
+				max_upward_message_size: 1024,
+			}),
+			cumulus_pallet_xcmp_queue: Some(XcmpQueueConfig {
+				max_inbound_weight: 1024,
+			}),
+		}
+	}
+}
@@ -0,0 +1,100 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A module that lets the tests of `validate_block` execute arbitrary storage operations and
+//! send messages.
+//!
+//! All calls are signed, so the tests can put them into a block like any other extrinsic. The
+//! messages that are received from other parachains are stored in `ReceivedXcmpMessages`.
+
+use srml_support::{decl_module, decl_storage, StorageValue};
+use system::ensure_signed;
+use rstd::vec::Vec;
+use cumulus_runtime::ParaId;
+use cumulus_pallet_xcmp_queue::XcmpMessageHandler;
+
+/// The module's configuration trait.
+pub trait Trait: cumulus_pallet_xcmp_queue::Trait {}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as TestModule {
+		/// The messages that were received from other parachains, with their sender.
+		ReceivedXcmpMessages get(received_xcmp_messages): Vec<(ParaId, Vec<u8>)>;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		/// Set the given raw storage items.
+		fn set_storage(origin, items: Vec<(Vec<u8>, Vec<u8>)>) {
+			ensure_signed(origin)?;
+
+			for (key, value) in items {
+				runtime_io::set_storage(&key, &value);
+			}
+		}
+
+		/// Remove all raw storage items whose key starts with `prefix`.
+		fn clear_prefix(origin, prefix: Vec<u8>) {
+			ensure_signed(origin)?;
+
+			runtime_io::clear_prefix(&prefix);
+		}
+
+		/// Set the given items in the child trie with the given `storage_key`.
+		fn set_child_storage(origin, storage_key: Vec<u8>, items: Vec<(Vec<u8>, Vec<u8>)>) {
+			ensure_signed(origin)?;
+
+			for (key, value) in items {
+				runtime_io::set_child_storage(&storage_key, &key, &value);
+			}
+		}
+
+		/// Remove the child trie with the given `storage_key`.
+		fn kill_child_storage(origin, storage_key: Vec<u8>) {
+			ensure_signed(origin)?;
+
+			runtime_io::kill_child_storage(&storage_key);
+		}
+
+		/// Send the given messages to the relay chain.
+		fn send_upward_messages(origin, messages: Vec<Vec<u8>>) {
+			ensure_signed(origin)?;
+
+			for message in messages {
+				<cumulus_pallet_parachain_system::Module<T>>::send_upward_message(message)?;
+			}
+		}
+
+		/// Send the given message to the parachain `recipient`.
+		fn send_xcmp_message(origin, recipient: ParaId, message: Vec<u8>) {
+			ensure_signed(origin)?;
+
+			<cumulus_pallet_xcmp_queue::Module<T>>::send_xcmp_message(recipient, message)?;
+		}
+	}
+}
+
+/// Stores every message, the weight of a message is its length.
+impl<T: Trait> XcmpMessageHandler for Module<T> {
+	fn weight(_: ParaId, message: &[u8]) -> u64 {
+		message.len() as u64
+	}
+
+	fn handle_xcmp_message(source: ParaId, message: Vec<u8>) {
+		ReceivedXcmpMessages::mutate(|messages| messages.push((source, message)));
+	}
+}
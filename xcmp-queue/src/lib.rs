@@ -0,0 +1,315 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Runtime module that queues the messages a parachain sends to other parachains.
+//!
+//! Messages can only be sent over open outbound channels. Every channel has `ChannelLimits`,
+//! which bound the size of a page and the number of pages that can be queued in the channel.
+//! Channels are opened and closed by root with `set_outbound_channel`.
+//!
+//! Other modules send messages with `send_xcmp_message`. The messages of a channel are packed
+//! into pages, a page is the encoded `Vec<Vec<u8>>` of its messages. At the end of every
+//! block, the first queued page of every channel is written as `OutboundHrmpMessage` to
+//! `HORIZONTAL_MESSAGES_KEY`, from where `validate_block` puts it into the validation result.
+//!
+//! The module is the `DownwardMessageHandler` of the parachain system module for the messages
+//! of other parachains. The received pages are split into their messages, which are buffered
+//! in the inbound queue. At the end of every block, the buffered messages are passed in order
+//! to the `XcmpMessageHandler` until their weight would exceed `MaxInboundWeight`. The other
+//! messages stay in the queue for the following blocks.
+//!
+//! A message whose weight alone exceeds `MaxInboundWeight` is moved to the overweight queue.
+//! It can be executed later on with `service_overweight` by anyone who provides a weight limit
+//! that covers its weight.
+//!
+//! The hrmp watermark is the number of the relay chain block up to which all inbound messages
+//! were processed. It only advances to a relay parent when all messages that were received
+//! with this relay parent left the inbound queue, which includes messages that were moved to
+//! the overweight queue. At the end of every block, it is written to `HRMP_WATERMARK_KEY`.
+
+use srml_support::{decl_module, decl_storage, ensure, StorageValue, StorageMap};
+use system::{ensure_root, ensure_signed};
+use codec::{Encode, Decode};
+use rstd::vec::Vec;
+use cumulus_runtime::{
+	OutboundHrmpMessage, DownwardMessage, ParaId, HORIZONTAL_MESSAGES_KEY, HRMP_WATERMARK_KEY,
+};
+use cumulus_pallet_parachain_system::{DownwardMessageHandler, Module as ParachainSystem};
+
+#[cfg(test)]
+mod tests;
+
+/// The limits of an outbound channel.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ChannelLimits {
+	/// The maximum number of pages that can be queued in the channel.
+	pub max_capacity: u32,
+	/// The maximum size in bytes of an encoded page.
+	pub max_message_size: u32,
+}
+
+/// Handles the messages that are received from other parachains.
+pub trait XcmpMessageHandler {
+	/// Returns the weight of handling the given message of `source`.
+	fn weight(source: ParaId, message: &[u8]) -> u64;
+
+	/// Handle the given message of `source`.
+	fn handle_xcmp_message(source: ParaId, message: Vec<u8>);
+}
+
+impl XcmpMessageHandler for () {
+	fn weight(_: ParaId, _: &[u8]) -> u64 {
+		0
+	}
+
+	fn handle_xcmp_message(_: ParaId, _: Vec<u8>) {}
+}
+
+/// The module's configuration trait.
+pub trait Trait: cumulus_pallet_parachain_system::Trait {
+	/// The handler of the messages that are received from other parachains.
+	type XcmpMessageHandler: XcmpMessageHandler;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as XcmpQueue {
+		/// The limits of the open outbound channels, indexed by the recipient.
+		OutboundChannels get(outbound_channel): map ParaId => Option<ChannelLimits>;
+		/// The index of the first queued page and the index after the last queued page of every
+		/// outbound channel, indexed by the recipient.
+		OutboundQueue get(outbound_queue): map ParaId => (u32, u32);
+		/// The queued pages, indexed by the recipient and the page index.
+		OutboundPages: map (ParaId, u32) => Vec<Vec<u8>>;
+		/// The recipients that have queued pages, in the order their queue was started.
+		ActiveRecipients get(active_recipients): Vec<ParaId>;
+
+		/// The weight that can be used per block to handle inbound messages.
+		MaxInboundWeight get(max_inbound_weight) config(): u64;
+		/// The index of the first buffered inbound message and the index after the last one.
+		InboundQueue get(inbound_queue): (u32, u32);
+		/// The buffered inbound messages with their sender, indexed by their position in the
+		/// inbound queue.
+		InboundMessages: map u32 => (ParaId, Vec<u8>);
+		/// The messages that exceeded `MaxInboundWeight` with their sender, indexed by the
+		/// index they got when they were moved to the overweight queue.
+		Overweight get(overweight): map u64 => Option<(ParaId, Vec<u8>)>;
+		/// The number of messages that were moved to the overweight queue so far, which is the
+		/// index of the next overweight message.
+		OverweightCount get(overweight_count): u64;
+
+		/// The number of the relay chain block up to which all inbound messages were processed.
+		HrmpWatermark get(hrmp_watermark): u32;
+		/// The relay parents with inbound messages in the queue, oldest first. Every relay parent
+		/// is stored with the index after the last message that was received with it.
+		InboundRelayParents: Vec<(u32, u32)>;
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		/// Open, update or close (`None`) the outbound channel to `recipient`.
+		///
+		/// Closing a channel drops all of its queued pages.
+		fn set_outbound_channel(origin, recipient: ParaId, limits: Option<ChannelLimits>) {
+			ensure_root(origin)?;
+
+			match limits {
+				Some(limits) => OutboundChannels::insert(recipient, limits),
+				None => {
+					OutboundChannels::remove(recipient);
+					Self::drop_queue(recipient);
+				},
+			}
+		}
+
+		/// Execute the overweight message at `index`, if its weight does not exceed
+		/// `weight_limit`.
+		fn service_overweight(origin, index: u64, weight_limit: u64) {
+			ensure_signed(origin)?;
+
+			let (source, message) = Self::overweight(index).ok_or("Unknown overweight message")?;
+			ensure!(
+				T::XcmpMessageHandler::weight(source, &message) <= weight_limit,
+				"The message weight exceeds the weight limit"
+			);
+
+			Overweight::remove(index);
+			T::XcmpMessageHandler::handle_xcmp_message(source, message);
+		}
+
+		fn on_finalize() {
+			Self::process_inbound_messages();
+			Self::advance_hrmp_watermark();
+
+			let mut messages = Vec::new();
+			let mut active_recipients = Vec::new();
+
+			for recipient in ActiveRecipients::take() {
+				let (first, last) = OutboundQueue::get(recipient);
+				let page = OutboundPages::take((recipient, first));
+				messages.push(OutboundHrmpMessage { recipient, data: page.encode() });
+
+				if first + 1 < last {
+					OutboundQueue::insert(recipient, (first + 1, last));
+					active_recipients.push(recipient);
+				} else {
+					OutboundQueue::remove(recipient);
+				}
+			}
+
+			ActiveRecipients::put(active_recipients);
+
+			if messages.is_empty() {
+				runtime_io::clear_storage(HORIZONTAL_MESSAGES_KEY);
+			} else {
+				runtime_io::set_storage(HORIZONTAL_MESSAGES_KEY, &messages.encode());
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Send the given message to the parachain `recipient`.
+	///
+	/// The message is appended to the last queued page of the channel if it fits, otherwise a
+	/// new page is queued. Fails if there is no open channel to `recipient`, the message does
+	/// not fit into a page or the channel has no capacity left.
+	pub fn send_xcmp_message(recipient: ParaId, message: Vec<u8>) -> Result<(), &'static str> {
+		let limits = Self::outbound_channel(recipient).ok_or("No open channel to the recipient")?;
+		let max_message_size = limits.max_message_size as usize;
+
+		let mut page = Vec::new();
+		page.push(message);
+		ensure!(page.encode().len() <= max_message_size, "The message is too large");
+
+		let (first, last) = Self::outbound_queue(recipient);
+		if first < last {
+			let mut last_page = OutboundPages::get((recipient, last - 1));
+			last_page.extend(page.drain(..));
+			if last_page.encode().len() <= max_message_size {
+				OutboundPages::insert((recipient, last - 1), last_page);
+				return Ok(())
+			}
+			page.extend(last_page.pop());
+		}
+
+		ensure!(last - first < limits.max_capacity, "The channel has no capacity left");
+
+		if first == last {
+			ActiveRecipients::mutate(|recipients| recipients.push(recipient));
+		}
+		OutboundPages::insert((recipient, last), page);
+		OutboundQueue::insert(recipient, (first, last + 1));
+		Ok(())
+	}
+
+	/// Handle the buffered inbound messages until `MaxInboundWeight` is used up.
+	///
+	/// A message whose weight alone exceeds `MaxInboundWeight` is moved to the overweight
+	/// queue.
+	fn process_inbound_messages() {
+		let max_weight = Self::max_inbound_weight();
+		let (mut first, last) = Self::inbound_queue();
+		let mut used_weight = 0u64;
+
+		while first < last {
+			let (source, message) = InboundMessages::get(first);
+			let weight = T::XcmpMessageHandler::weight(source, &message);
+
+			if weight > max_weight {
+				let index = OverweightCount::get();
+				Overweight::insert(index, (source, message));
+				OverweightCount::put(index + 1);
+			} else if used_weight.saturating_add(weight) > max_weight {
+				break
+			} else {
+				used_weight += weight;
+				T::XcmpMessageHandler::handle_xcmp_message(source, message);
+			}
+
+			InboundMessages::remove(first);
+			first += 1;
+		}
+
+		if first == last {
+			InboundQueue::kill();
+		} else {
+			InboundQueue::put((first, last));
+		}
+	}
+
+	/// Advance the hrmp watermark to the newest relay parent whose inbound messages were all
+	/// processed and write it to `HRMP_WATERMARK_KEY`.
+	fn advance_hrmp_watermark() {
+		let relay_parent_number = <ParachainSystem<T>>::relay_parent_number();
+		let mut watermark = Self::hrmp_watermark();
+
+		if InboundQueue::exists() {
+			let (first, _) = Self::inbound_queue();
+			let mut relay_parents = InboundRelayParents::get();
+			let processed = relay_parents.iter().take_while(|(_, end)| *end <= first).count();
+			if let Some((number, _)) = relay_parents.drain(..processed).last() {
+				watermark = watermark.max(number);
+			}
+			InboundRelayParents::put(relay_parents);
+		} else {
+			// All received messages were processed.
+			InboundRelayParents::kill();
+			watermark = watermark.max(relay_parent_number);
+		}
+
+		HrmpWatermark::put(watermark);
+		runtime_io::set_storage(HRMP_WATERMARK_KEY, &watermark.encode());
+	}
+
+	/// Remove all queued pages of the channel to `recipient`.
+	fn drop_queue(recipient: ParaId) {
+		let (first, last) = OutboundQueue::take(recipient);
+		for index in first..last {
+			OutboundPages::remove((recipient, index));
+		}
+		ActiveRecipients::mutate(|recipients| recipients.retain(|r| *r != recipient));
+	}
+}
+
+impl<T: Trait> DownwardMessageHandler for Module<T> {
+	/// Buffer the messages of the received page in the inbound queue.
+	///
+	/// Pages that can not be decoded are dropped.
+	fn handle_downward_message(message: DownwardMessage) {
+		let page = match Vec::<Vec<u8>>::decode(&mut &message.data[..]) {
+			Some(page) => page,
+			None => return,
+		};
+
+		let end = InboundQueue::mutate(|(_, last)| {
+			for data in page {
+				InboundMessages::insert(*last, (message.source, data));
+				*last += 1;
+			}
+			*last
+		});
+
+		let relay_parent_number = <ParachainSystem<T>>::relay_parent_number();
+		InboundRelayParents::mutate(|relay_parents| match relay_parents.last_mut() {
+			Some((number, last)) if *number == relay_parent_number => *last = end,
+			_ => relay_parents.push((relay_parent_number, end)),
+		});
+	}
+}
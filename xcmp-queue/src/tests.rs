@@ -0,0 +1,466 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Cumulus.
+
+// Cumulus is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Cumulus is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Cumulus.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use srml_support::{impl_outer_origin, parameter_types, assert_ok, assert_noop};
+use runtime_io::{with_externalities, TestExternalities};
+use primitives::{Blake2Hasher, H256};
+use runtime_primitives::{
+	traits::{BlakeTwo256, IdentityLookup, OnInitialize, OnFinalize}, testing::Header,
+};
+use cumulus_runtime::ValidationData;
+use cumulus_pallet_parachain_system::OneBlockPerRelayParent;
+
+use std::cell::RefCell;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+}
+
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+}
+
+impl cumulus_pallet_parachain_system::Trait for Test {
+	type Event = ();
+	type DownwardMessageHandler = XcmpQueue;
+	type ConsensusHook = OneBlockPerRelayParent;
+}
+
+thread_local! {
+	static HANDLED_MESSAGES: RefCell<Vec<(ParaId, Vec<u8>)>> = RefCell::new(Vec::new());
+}
+
+/// An `XcmpMessageHandler` that stores all handled messages. The weight of a message is its
+/// length.
+pub struct SaveXcmpMessages;
+
+impl SaveXcmpMessages {
+	fn take() -> Vec<(ParaId, Vec<u8>)> {
+		HANDLED_MESSAGES.with(|m| m.borrow_mut().drain(..).collect())
+	}
+}
+
+impl XcmpMessageHandler for SaveXcmpMessages {
+	fn weight(_: ParaId, message: &[u8]) -> u64 {
+		message.len() as u64
+	}
+
+	fn handle_xcmp_message(source: ParaId, message: Vec<u8>) {
+		HANDLED_MESSAGES.with(|m| m.borrow_mut().push((source, message)));
+	}
+}
+
+impl Trait for Test {
+	type XcmpMessageHandler = SaveXcmpMessages;
+}
+
+type System = system::Module<Test>;
+type ParachainSystem = cumulus_pallet_parachain_system::Module<Test>;
+type XcmpQueue = Module<Test>;
+
+fn new_test_ext() -> TestExternalities<Blake2Hasher> {
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap().0;
+	t.extend(cumulus_pallet_parachain_system::GenesisConfig {
+		parachain_id: 200.into(),
+		validation_upgrade_delay: 5,
+		max_upward_message_count: 2,
+		max_upward_message_size: 8,
+	}.build_storage::<Test>().unwrap().0);
+	t.extend(GenesisConfig {
+		max_inbound_weight: 10,
+	}.build_storage::<Test>().unwrap().0);
+	TestExternalities::new(t)
+}
+
+/// Encode the given messages as page.
+fn page(messages: &[&[u8]]) -> Vec<u8> {
+	messages.iter().map(|m| m.to_vec()).collect::<Vec<_>>().encode()
+}
+
+fn downward_message(source: u32, data: Vec<u8>) -> DownwardMessage {
+	DownwardMessage { source: source.into(), data }
+}
+
+/// Set the validation data of the current block on the given relay parent and receive the given
+/// pages.
+fn receive_pages(relay_parent_number: u32, pages: Vec<DownwardMessage>) {
+	assert_ok!(ParachainSystem::set_validation_data(
+		Origin::NONE,
+		ValidationData { relay_parent_number, relay_storage_root: H256::zero() },
+	));
+	assert_ok!(ParachainSystem::receive_downward_messages(Origin::NONE, pages));
+}
+
+/// Finalize the current block and initialize the next one.
+fn next_block() {
+	let number = System::block_number();
+	XcmpQueue::on_finalize(number);
+	ParachainSystem::on_finalize(number);
+
+	System::set_block_number(number + 1);
+	ParachainSystem::on_initialize(number + 1);
+}
+
+#[test]
+fn inbound_messages_are_handled_in_order() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![
+			downward_message(100, page(&[b"a", b"bb"])),
+			downward_message(300, page(&[b"ccc"])),
+			downward_message(100, page(&[b"d"])),
+		]);
+		assert!(SaveXcmpMessages::take().is_empty());
+		assert_eq!(XcmpQueue::inbound_queue(), (0, 4));
+
+		next_block();
+		assert_eq!(
+			SaveXcmpMessages::take(),
+			vec![
+				(100.into(), b"a".to_vec()),
+				(100.into(), b"bb".to_vec()),
+				(300.into(), b"ccc".to_vec()),
+				(100.into(), b"d".to_vec()),
+			],
+		);
+		assert_eq!(XcmpQueue::inbound_queue(), (0, 0));
+	});
+}
+
+#[test]
+fn inbound_messages_are_limited_by_max_inbound_weight() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"aaaa", b"bbbb", b"cccc"]))]);
+		next_block();
+
+		// The third message would exceed the weight of 10.
+		assert_eq!(
+			SaveXcmpMessages::take(),
+			vec![(100.into(), b"aaaa".to_vec()), (100.into(), b"bbbb".to_vec())],
+		);
+		assert_eq!(XcmpQueue::inbound_queue(), (2, 3));
+
+		receive_pages(11, Vec::new());
+		next_block();
+		assert_eq!(SaveXcmpMessages::take(), vec![(100.into(), b"cccc".to_vec())]);
+		assert_eq!(XcmpQueue::inbound_queue(), (0, 0));
+	});
+}
+
+#[test]
+fn overweight_messages_are_moved_to_the_overweight_queue() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"overweight!", b"a"]))]);
+		next_block();
+
+		assert_eq!(SaveXcmpMessages::take(), vec![(100.into(), b"a".to_vec())]);
+		assert_eq!(XcmpQueue::overweight(0), Some((100.into(), b"overweight!".to_vec())));
+		assert_eq!(XcmpQueue::overweight_count(), 1);
+	});
+}
+
+#[test]
+fn service_overweight_works() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"overweight!"]))]);
+		next_block();
+
+		assert_noop!(
+			XcmpQueue::service_overweight(Origin::signed(1), 0, 10),
+			"The message weight exceeds the weight limit"
+		);
+		assert_noop!(
+			XcmpQueue::service_overweight(Origin::signed(1), 1, 11),
+			"Unknown overweight message"
+		);
+		assert!(XcmpQueue::service_overweight(Origin::NONE, 0, 11).is_err());
+
+		assert_ok!(XcmpQueue::service_overweight(Origin::signed(1), 0, 11));
+		assert_eq!(SaveXcmpMessages::take(), vec![(100.into(), b"overweight!".to_vec())]);
+		assert_eq!(XcmpQueue::overweight(0), None);
+
+		assert_noop!(
+			XcmpQueue::service_overweight(Origin::signed(1), 0, 11),
+			"Unknown overweight message"
+		);
+	});
+}
+
+#[test]
+fn undecodable_pages_are_dropped() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![
+			downward_message(100, Vec::new()),
+			downward_message(100, page(&[b"a"])),
+		]);
+		next_block();
+
+		assert_eq!(SaveXcmpMessages::take(), vec![(100.into(), b"a".to_vec())]);
+	});
+}
+
+fn open_channel(recipient: u32, max_capacity: u32, max_message_size: u32) {
+	assert_ok!(XcmpQueue::set_outbound_channel(
+		Origin::ROOT,
+		recipient.into(),
+		Some(ChannelLimits { max_capacity, max_message_size }),
+	));
+}
+
+fn outbound_pages(recipient: u32) -> Vec<Vec<Vec<u8>>> {
+	let (first, last) = XcmpQueue::outbound_queue(ParaId::from(recipient));
+	(first..last).map(|index| OutboundPages::get((ParaId::from(recipient), index))).collect()
+}
+
+#[test]
+fn set_outbound_channel_requires_root() {
+	with_externalities(&mut new_test_ext(), || {
+		assert!(XcmpQueue::set_outbound_channel(
+			Origin::signed(1),
+			300.into(),
+			Some(ChannelLimits { max_capacity: 1, max_message_size: 10 }),
+		).is_err());
+		assert_eq!(XcmpQueue::outbound_channel(ParaId::from(300)), None);
+	});
+}
+
+#[test]
+fn send_xcmp_message_requires_an_open_channel() {
+	with_externalities(&mut new_test_ext(), || {
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), b"abc".to_vec()),
+			"No open channel to the recipient"
+		);
+	});
+}
+
+#[test]
+fn messages_are_packed_into_pages() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+
+		// A page of two messages of three bytes is encoded in nine bytes, a third one does not
+		// fit anymore.
+		for _ in 0..3 {
+			assert_ok!(XcmpQueue::send_xcmp_message(300.into(), b"abc".to_vec()));
+		}
+
+		assert_eq!(
+			outbound_pages(300),
+			vec![vec![b"abc".to_vec(), b"abc".to_vec()], vec![b"abc".to_vec()]],
+		);
+		assert_eq!(XcmpQueue::active_recipients(), vec![ParaId::from(300)]);
+	});
+}
+
+#[test]
+fn pages_are_limited_by_the_max_message_size() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+
+		// A page of one message of nine bytes is encoded in eleven bytes.
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), vec![1; 9]),
+			"The message is too large"
+		);
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![1; 8]));
+		assert_eq!(outbound_pages(300), vec![vec![vec![1; 8]]]);
+	});
+}
+
+#[test]
+fn channels_are_limited_by_the_max_capacity() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![1; 8]));
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![2; 8]));
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), vec![3; 8]),
+			"The channel has no capacity left"
+		);
+
+		// The last page has no space left for the message either.
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), b"a".to_vec()),
+			"The channel has no capacity left"
+		);
+		assert_eq!(outbound_pages(300), vec![vec![vec![1; 8]], vec![vec![2; 8]]]);
+	});
+}
+
+#[test]
+fn channels_are_packed_independently() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+		open_channel(400, 2, 10);
+
+		assert_ok!(XcmpQueue::send_xcmp_message(400.into(), b"abc".to_vec()));
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), b"def".to_vec()));
+		assert_ok!(XcmpQueue::send_xcmp_message(400.into(), b"ghi".to_vec()));
+
+		assert_eq!(outbound_pages(300), vec![vec![b"def".to_vec()]]);
+		assert_eq!(outbound_pages(400), vec![vec![b"abc".to_vec(), b"ghi".to_vec()]]);
+		assert_eq!(
+			XcmpQueue::active_recipients(),
+			vec![ParaId::from(400), ParaId::from(300)],
+		);
+	});
+}
+
+#[test]
+fn closing_a_channel_drops_its_pages() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![1; 8]));
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![2; 8]));
+
+		assert_ok!(XcmpQueue::set_outbound_channel(Origin::ROOT, 300.into(), None));
+		assert!(outbound_pages(300).is_empty());
+		assert!(!OutboundPages::exists((ParaId::from(300), 0)));
+		assert!(XcmpQueue::active_recipients().is_empty());
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), b"abc".to_vec()),
+			"No open channel to the recipient"
+		);
+	});
+}
+
+fn horizontal_messages() -> Option<Vec<OutboundHrmpMessage>> {
+	runtime_io::storage(HORIZONTAL_MESSAGES_KEY)
+		.map(|messages| Decode::decode(&mut &messages[..]).expect("Messages are encoded"))
+}
+
+fn hrmp_watermark_in_storage() -> Option<u32> {
+	runtime_io::storage(HRMP_WATERMARK_KEY)
+		.map(|watermark| Decode::decode(&mut &watermark[..]).expect("Watermark is encoded"))
+}
+
+fn hrmp_message(recipient: u32, messages: &[&[u8]]) -> OutboundHrmpMessage {
+	OutboundHrmpMessage { recipient: recipient.into(), data: page(messages) }
+}
+
+#[test]
+fn one_page_per_recipient_is_sent_per_block() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 2, 10);
+		open_channel(400, 2, 10);
+		for _ in 0..3 {
+			assert_ok!(XcmpQueue::send_xcmp_message(300.into(), b"abc".to_vec()));
+		}
+		assert_ok!(XcmpQueue::send_xcmp_message(400.into(), b"def".to_vec()));
+
+		receive_pages(10, Vec::new());
+		next_block();
+		assert_eq!(
+			horizontal_messages(),
+			Some(vec![hrmp_message(300, &[b"abc", b"abc"]), hrmp_message(400, &[b"def"])]),
+		);
+		assert_eq!(XcmpQueue::active_recipients(), vec![ParaId::from(300)]);
+
+		receive_pages(11, Vec::new());
+		next_block();
+		assert_eq!(horizontal_messages(), Some(vec![hrmp_message(300, &[b"abc"])]));
+		assert!(XcmpQueue::active_recipients().is_empty());
+		assert_eq!(XcmpQueue::outbound_queue(ParaId::from(300)), (0, 0));
+
+		receive_pages(12, Vec::new());
+		next_block();
+		assert_eq!(horizontal_messages(), None);
+	});
+}
+
+#[test]
+fn sent_pages_free_the_channel_capacity() {
+	with_externalities(&mut new_test_ext(), || {
+		open_channel(300, 1, 10);
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![1; 8]));
+		assert_noop!(
+			XcmpQueue::send_xcmp_message(300.into(), vec![2; 8]),
+			"The channel has no capacity left"
+		);
+
+		receive_pages(10, Vec::new());
+		next_block();
+		assert_ok!(XcmpQueue::send_xcmp_message(300.into(), vec![2; 8]));
+		assert_eq!(outbound_pages(300), vec![vec![vec![2; 8]]]);
+	});
+}
+
+#[test]
+fn hrmp_watermark_advances_to_the_relay_parent() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"a"]))]);
+		next_block();
+		assert_eq!(XcmpQueue::hrmp_watermark(), 10);
+		assert_eq!(hrmp_watermark_in_storage(), Some(10));
+
+		// Without messages, the watermark follows the relay parent.
+		receive_pages(12, Vec::new());
+		next_block();
+		assert_eq!(hrmp_watermark_in_storage(), Some(12));
+	});
+}
+
+#[test]
+fn hrmp_watermark_does_not_advance_over_unprocessed_messages() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"aaaa", b"bbbb", b"cccc"]))]);
+		next_block();
+		assert_eq!(XcmpQueue::inbound_queue(), (2, 3));
+		assert_eq!(hrmp_watermark_in_storage(), Some(0));
+
+		// The remaining message of relay parent 10 is processed, the one of 11 is not.
+		receive_pages(11, vec![downward_message(300, page(&[b"dddddddd"]))]);
+		next_block();
+		assert_eq!(XcmpQueue::inbound_queue(), (3, 4));
+		assert_eq!(hrmp_watermark_in_storage(), Some(10));
+
+		receive_pages(12, Vec::new());
+		next_block();
+		assert_eq!(XcmpQueue::inbound_queue(), (0, 0));
+		assert_eq!(hrmp_watermark_in_storage(), Some(12));
+		assert_eq!(SaveXcmpMessages::take().len(), 4);
+	});
+}
+
+#[test]
+fn overweight_messages_do_not_hold_back_the_hrmp_watermark() {
+	with_externalities(&mut new_test_ext(), || {
+		receive_pages(10, vec![downward_message(100, page(&[b"overweight!"]))]);
+		next_block();
+
+		assert_eq!(XcmpQueue::overweight_count(), 1);
+		assert_eq!(hrmp_watermark_in_storage(), Some(10));
+	});
+}